@@ -0,0 +1,201 @@
+//! An async driver for tokio applications, enabled with the
+//! `tokio` feature.
+//!
+//! `AsyncTS480` runs the commands of `TS480` over any
+//! `AsyncRead + AsyncWrite` stream, such as a `TcpStream`, an
+//! async serial port or one half of `tokio::io::duplex`:
+//!
+//! ```no_run
+//! use ts480::TS480;
+//! use ts480::asynchronous::AsyncTS480;
+//! use tokio::io::{AsyncRead, AsyncWrite};
+//!
+//! async fn tune<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> ts480::RadioResult<()> {
+//!     let mut radio = AsyncTS480::new(stream);
+//!     let frequency = radio.command(TS480::read_frequency_a).await?;
+//!     radio.command(move |r| r.set_frequency_b(frequency)).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::transport::Transport;
+use crate::{RadioError, RadioEvent, RadioResult, TS480};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc as channel;
+use std::thread;
+use std::time::Duration;
+
+/// Longest a single read by the driver blocks. The driver
+/// enforces the answer timeout itself.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type Job = Box<dyn FnOnce(&mut TS480<Pipe>) + Send>;
+
+/// Async connection to the radio.
+///
+/// The commands run one at a time on a worker thread, on a `TS480`
+/// that lives as long as the `AsyncTS480`, so band privileges, the
+/// transmit state and the watchdog apply as they do to a `TS480`.
+/// Awaiting a command forwards what it sends and receives between
+/// the worker and the stream.
+///
+/// Dropping a command future does not cancel the command, which
+/// still runs before the next one. Dropping the `AsyncTS480` cannot
+/// send anything to the radio, so switch it to receive first.
+pub struct AsyncTS480<S> {
+    stream: S,
+    jobs: channel::Sender<Job>,
+    /// Bytes written by the driver, to be sent to the radio
+    output: mpsc::UnboundedReceiver<Vec<u8>>,
+    /// Bytes received from the radio, for the driver to read;
+    /// empty at the end of the stream
+    input: channel::Sender<Vec<u8>>,
+    closed: bool,
+}
+
+/// The worker's connection to the radio, through the `AsyncTS480`.
+pub struct Pipe {
+    output: mpsc::UnboundedSender<Vec<u8>>,
+    input: channel::Receiver<Vec<u8>>,
+    /// Bytes received and not read yet
+    pending: Vec<u8>,
+    closed: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncTS480<S> {
+    /// Uses an already-open connection to the radio, waiting up
+    /// to one second for each answer.
+    pub fn new(stream: S) -> Self {
+        let (jobs, queue) = channel::channel::<Job>();
+        let (output_sender, output) = mpsc::unbounded_channel();
+        let (input, input_receiver) = channel::channel();
+
+        let pipe = Pipe { output: output_sender, input: input_receiver, pending: Vec::new(), closed: false };
+        thread::spawn(move || {
+            let mut radio = TS480::with_transport(pipe);
+            for job in queue {
+                job(&mut radio);
+            }
+        });
+
+        AsyncTS480 { stream, jobs, output, input, closed: false }
+    }
+
+    /// Sets how long to wait for each answer before failing
+    /// with a `TimedOut` error.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        let _ = self.jobs.send(Box::new(move |radio| radio.set_timeout(timeout)));
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns a mutable reference to the underlying stream.
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Runs one of the `TS480` commands, e.g.
+    /// `radio.command(TS480::read_mode)` or
+    /// `radio.command(|r| r.set_power(50))`, after any commands
+    /// whose futures were dropped.
+    pub async fn command<F, R>(&mut self, command: F) -> RadioResult<R>
+        where F: FnOnce(&mut TS480<Pipe>) -> RadioResult<R> + Send + 'static, R: Send + 'static
+    {
+        let (sender, mut result) = oneshot::channel();
+        let job: Job = Box::new(move |radio| {
+            let _ = sender.send(command(radio));
+        });
+        self.jobs.send(job).map_err(|_| stopped())?;
+
+        let mut chunk = [0; 64];
+        loop {
+            tokio::select! {
+                result = &mut result => {
+                    // A command that is not answered may finish
+                    // before what it wrote has been sent
+                    while let Ok(data) = self.output.try_recv() {
+                        self.stream.write_all(&data).await?;
+                    }
+                    self.stream.flush().await?;
+                    return result.map_err(|_| stopped())?;
+                },
+                Some(data) = self.output.recv() => {
+                    self.stream.write_all(&data).await?;
+                    self.stream.flush().await?;
+                },
+                n = self.stream.read(&mut chunk), if !self.closed => {
+                    let n = n?;
+                    self.closed = n == 0;
+                    let _ = self.input.send(chunk[..n].to_vec());
+                },
+            }
+        }
+    }
+
+    /// Waits for the next unsolicited report from the radio.
+    ///
+    /// Auto Information mode must have been turned on with
+    /// `set_auto_information`.
+    pub async fn next_event(&mut self) -> RadioResult<RadioEvent> {
+        loop {
+            match self.command(TS480::next_event).await {
+                Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut => {},
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() && !self.closed {
+            match self.input.recv_timeout(POLL_INTERVAL) {
+                Ok(data) => {
+                    self.closed = data.is_empty();
+                    self.pending = data;
+                },
+                Err(channel::RecvTimeoutError::Timeout) => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "no data received"));
+                },
+                Err(channel::RecvTimeoutError::Disconnected) => self.closed = true,
+            }
+        }
+
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.send(buf.to_vec()).map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Pipe {
+    fn discard_input(&mut self) -> io::Result<()> {
+        self.pending.clear();
+        while let Ok(data) = self.input.try_recv() {
+            self.closed = data.is_empty();
+        }
+        Ok(())
+    }
+}
+
+fn stopped() -> RadioError {
+    io::Error::new(io::ErrorKind::BrokenPipe, "radio worker thread stopped").into()
+}
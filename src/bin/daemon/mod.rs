@@ -0,0 +1,99 @@
+//! What `ts480-rigctld` and `ts480-flrig` share: opening the radio
+//! from their command line and serving each client from a thread.
+
+use ts480::{BaudRate, TS480, TS480Options, Transport};
+
+use std::env;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+/// A serial port or TCP connection to the radio
+pub type Port = Box<dyn Transport + Send>;
+
+/// The radio shared by all clients
+pub type Radio = TS480<Port>;
+
+/// Answers the requests of one client until it disconnects
+pub type Serve = fn(Arc<Mutex<Radio>>, TcpStream) -> io::Result<()>;
+
+/// Opens the radio as the command line says and serves clients
+/// connecting to `--listen`, or `listen` if it is not given.
+pub fn main(name: &str, listen: &str, serve: Serve) {
+    let usage = || -> ! {
+        eprintln!(
+            "usage: {} [--port <device>] [--baud <rate>] [--tcp <address>] [--listen <address>] [--max-tx <seconds>]",
+            name,
+        );
+        process::exit(2);
+    };
+
+    let mut port = String::from("/dev/ttyUSB0");
+    let mut options = TS480Options::new();
+    let mut tcp = None;
+    let mut listen = listen.to_owned();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--port" => port = value,
+            "--baud" => {
+                let baud = value.parse::<usize>().unwrap_or_else(|_| usage());
+                options = options.baud_rate(BaudRate::from_speed(baud));
+            },
+            "--tcp" => tcp = Some(value),
+            "--listen" => listen = value,
+            "--max-tx" => {
+                let seconds = value.parse().unwrap_or_else(|_| usage());
+                options = options.max_transmit_time(Duration::from_secs(seconds));
+            },
+            _ => usage(),
+        }
+    }
+
+    let radio = match tcp {
+        Some(address) => options.open_tcp(&address)
+            .map(|stream| Box::new(stream) as Port)
+            .and_then(|stream| options.connect(stream))
+            .unwrap_or_else(|e| fail(name, &format!("{}: {}", address, e))),
+        None => options.open_port(&port)
+            .map(|port| Box::new(port) as Port)
+            .and_then(|port| options.connect(port))
+            .unwrap_or_else(|e| fail(name, &format!("{}: {}", port, e))),
+    };
+    let radio = Arc::new(Mutex::new(radio));
+
+    let listener = TcpListener::bind(&listen).unwrap_or_else(|e| fail(name, &format!("{}: {}", listen, e)));
+    eprintln!("listening on {}", listen);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("accept failed: {}", e);
+                continue;
+            },
+        };
+        let radio = radio.clone();
+        thread::spawn(move || {
+            if let Err(e) = serve(radio, stream) {
+                eprintln!("connection closed: {}", e);
+            }
+        });
+    }
+}
+
+/// Locks the radio, which stays usable after a client's
+/// thread panics while holding it.
+pub fn lock<T: Transport>(radio: &Mutex<TS480<T>>) -> MutexGuard<'_, TS480<T>> {
+    radio.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn fail(name: &str, message: &str) -> ! {
+    eprintln!("{}: {}", name, message);
+    process::exit(1);
+}
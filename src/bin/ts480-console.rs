@@ -0,0 +1,201 @@
+//! An interactive CAT console, for debugging the radio by hand.
+//! Built with the `console` feature.
+//!
+//! Usage: `ts480-console [--port <device>] [--baud <rate>] [--tcp <address>] [--sim]
+//! [--record <file>]`
+//!
+//! Each line is sent to the radio as it is typed, e.g. `fa;md`, and
+//! every answer is printed with the meaning of its fields. Tab
+//! completes command mnemonics, and the history is kept in
+//! `~/.ts480_history`. With `--record`, the CAT traffic is also
+//! recorded to a file, see `ts480::recording`.
+//!
+//! ```text
+//! help [command]  list the commands, or describe one
+//! wait <ms>       how long to wait for answers
+//! quit            leave the console, as does Ctrl-D
+//! ```
+
+extern crate rustyline;
+extern crate ts480;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use ts480::console::{self, Console, COMMANDS};
+use ts480::recording::Recorder;
+use ts480::sim::Simulator;
+use ts480::{BaudRate, TS480, TS480Options, Transport};
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &str = "usage: ts480-console [--port <device>] [--baud <rate>] [--tcp <address>] [--sim] [--record <file>]";
+
+const HISTORY_FILE: &str = ".ts480_history";
+
+/// Completes the mnemonic of the command under the cursor.
+struct Mnemonics;
+
+impl Completer for Mnemonics {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(';').map(|i| i + 1).unwrap_or(0);
+        let start = start + (line[start..pos].len() - line[start..pos].trim_start().len());
+        let word = &line[start..pos];
+        if word.len() > 2 {
+            return Ok((pos, Vec::new()));
+        }
+        // Keep the case the user is typing in
+        let lower = word.chars().any(|c| c.is_ascii_lowercase());
+        let candidates = console::complete(word).into_iter()
+            .map(|m| if lower { m.to_ascii_lowercase() } else { m.to_owned() })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Mnemonics {
+    type Hint = String;
+}
+
+impl Highlighter for Mnemonics {}
+
+impl Validator for Mnemonics {}
+
+impl Helper for Mnemonics {}
+
+fn main() {
+    let mut port = String::from("/dev/ttyUSB0");
+    let mut baud = None;
+    let mut tcp = None;
+    let mut sim = false;
+    let mut record = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().unwrap_or_else(|| usage()),
+            "--baud" => baud = Some(args.next().and_then(|b| b.parse::<usize>().ok()).unwrap_or_else(|| usage())),
+            "--tcp" => tcp = Some(args.next().unwrap_or_else(|| usage())),
+            "--sim" => sim = true,
+            "--record" => record = Some(args.next().unwrap_or_else(|| usage())),
+            _ => usage(),
+        }
+    }
+
+    let mut options = TS480Options::new();
+    if let Some(baud) = baud {
+        options = options.baud_rate(BaudRate::from_speed(baud));
+    }
+    if sim {
+        start(&options, Simulator::new(), record.as_deref());
+    } else if let Some(address) = tcp {
+        match options.open_tcp(&address) {
+            Ok(stream) => start(&options, stream, record.as_deref()),
+            Err(e) => fail(&format!("{}: {}", address, e)),
+        }
+    } else {
+        match options.open_port(&port) {
+            Ok(serial_port) => start(&options, serial_port, record.as_deref()),
+            Err(e) => fail(&format!("{}: {}", port, e)),
+        }
+    }
+}
+
+/// Connects over `transport`, recording the traffic to
+/// `record` if given, and runs the console.
+fn start<T: Transport>(options: &TS480Options, transport: T, record: Option<&str>) {
+    let result = match record {
+        Some(path) => {
+            let recorder = Recorder::create(transport, path).unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+            options.connect(recorder).map(run)
+        },
+        None => options.connect(transport).map(run),
+    };
+    if let Err(e) = result {
+        fail(&e.to_string());
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("ts480-console: {}", message);
+    process::exit(1);
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Reads lines until the user quits, sending each to the radio.
+fn run<T: Transport>(mut radio: TS480<T>) {
+    let mut editor: Editor<Mnemonics, DefaultHistory> = Editor::new()
+        .unwrap_or_else(|e| fail(&e.to_string()));
+    editor.set_helper(Some(Mnemonics));
+    let history = history_path();
+    if let Some(ref path) = history {
+        // There is no history yet the first time
+        let _ = editor.load_history(path);
+    }
+
+    let mut console = Console::new(&mut radio);
+    loop {
+        let line = match editor.readline("ts480> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => fail(&e.to_string()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("quit"), None) | (Some("exit"), None) => break,
+            (Some("help"), None) => {
+                for info in COMMANDS {
+                    println!("{}  {}", info.mnemonic, info.description);
+                }
+            },
+            (Some("help"), Some(mnemonic)) => match console::command(mnemonic) {
+                Some(info) => print!("{}", info.help()),
+                None => println!("unknown command: {}", mnemonic),
+            },
+            (Some("wait"), Some(ms)) => match ms.parse() {
+                Ok(ms) => console.set_wait(Duration::from_millis(ms)),
+                Err(_) => println!("invalid wait: {}", ms),
+            },
+            _ => match console.execute(line) {
+                Ok(ref answers) if answers.is_empty() => println!("(no answer)"),
+                Ok(answers) => {
+                    for answer in answers {
+                        print!("{}", console::describe(&answer));
+                    }
+                },
+                Err(e) => println!("error: {}", e),
+            },
+        }
+    }
+
+    if let Some(ref path) = history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("ts480-console: {}: {}", path.display(), e);
+        }
+    }
+}
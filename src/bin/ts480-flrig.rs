@@ -0,0 +1,488 @@
+//! Serves flrig's XML-RPC API, so logging programs that
+//! control radios through flrig can use this crate instead.
+//!
+//! Usage: `ts480-flrig [--port <device>] [--baud <rate>]
+//! [--tcp <address>] [--listen <address>] [--max-tx <seconds>]`
+//!
+//! The radio is opened on `--port` (default `/dev/ttyUSB0`), or
+//! reached over TCP with `--tcp`, e.g. a `ts480-sim` instance.
+//! Clients connect to `--listen`, by default `127.0.0.1:12345`.
+//!
+//! With `--max-tx`, the radio is switched back to receive after
+//! transmitting for that many seconds, whether or not any client
+//! is still connected, and is not keyed again until a client sends
+//! `rig.set_ptt` 0.
+
+extern crate ts480;
+
+mod daemon;
+
+use ts480::{
+    Frequency, Mode, PttSource, RadioError, TS480,
+    Transport, Vfo, METER_MAX,
+};
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+/// The flrig version reported to clients
+const FLRIG_VERSION: &str = "1.4.7";
+
+/// Largest request body accepted, far more than any call needs
+const MAX_BODY: usize = 64 * 1024;
+
+/// Longest request or header line accepted
+const MAX_LINE: usize = 8 * 1024;
+
+const METHODS: &[&str] = &[
+    "main.get_version", "system.listMethods",
+    "rig.get_xcvr", "rig.get_info",
+    "rig.get_vfo", "rig.set_vfo", "rig.set_frequency",
+    "rig.get_vfoA", "rig.set_vfoA", "rig.get_vfoB", "rig.set_vfoB",
+    "rig.get_AB", "rig.set_AB",
+    "rig.get_mode", "rig.set_mode", "rig.get_modes",
+    "rig.get_bw",
+    "rig.get_ptt", "rig.set_ptt",
+    "rig.get_split", "rig.set_split",
+    "rig.get_power", "rig.set_power",
+    "rig.get_volume", "rig.set_volume",
+    "rig.get_smeter", "rig.get_pwrmeter",
+];
+
+const MODES: &[Mode] = &[
+    Mode::Lsb, Mode::Usb, Mode::Cw, Mode::Fm,
+    Mode::Am, Mode::Fsk, Mode::CwReverse, Mode::FskReverse,
+];
+
+/// An XML-RPC value
+#[derive(Debug, PartialEq)]
+enum Value {
+    Int(i64),
+    Double(f64),
+    Str(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn to_xml(&self) -> String {
+        match *self {
+            Value::Int(n) => format!("<value><i4>{}</i4></value>", n),
+            Value::Double(n) => format!("<value><double>{}</double></value>", n),
+            Value::Str(ref s) => format!("<value>{}</value>", escape(s)),
+            Value::Array(ref values) => {
+                let values: String = values.iter().map(Value::to_xml).collect();
+                format!("<value><array><data>{}</data></array></value>", values)
+            },
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Int(n) => Some(n as f64),
+            Value::Double(n) => Some(n),
+            Value::Str(ref s) => s.trim().parse().ok(),
+            Value::Array(_) => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// A failed call: fault code and message
+type Fault = (i32, String);
+
+fn main() {
+    daemon::main("ts480-flrig", "127.0.0.1:12345", serve::<daemon::Port>);
+}
+
+/// Answers the HTTP requests of one client until it disconnects.
+fn serve<T: Transport>(radio: Arc<Mutex<TS480<T>>>, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    loop {
+        let request_line = match read_line(&mut reader)? {
+            Some(line) if line.is_empty() => return Ok(()),
+            Some(line) => line,
+            None => return reject(&mut writer, "414 URI Too Long"),
+        };
+
+        let mut content_length = Some(0);
+        loop {
+            let header = match read_line(&mut reader)? {
+                Some(line) if line.is_empty() => return Ok(()),
+                Some(line) => line,
+                None => return reject(&mut writer, "431 Request Header Fields Too Large"),
+            };
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            let mut parts = header.splitn(2, ':');
+            let name = parts.next().unwrap_or("");
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = parts.next().unwrap_or("").trim().parse().ok();
+            }
+        }
+
+        // The body cannot be skipped reliably, so the connection is closed
+        let content_length = match content_length {
+            Some(length) if length > MAX_BODY => return reject(&mut writer, "413 Payload Too Large"),
+            Some(length) => length,
+            None => return reject(&mut writer, "400 Bad Request"),
+        };
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        let body = String::from_utf8_lossy(&body);
+
+        let response = if request_line.starts_with("POST") {
+            let result = match parse_call(&body) {
+                Some((method, params)) => {
+                    let mut radio = daemon::lock(&radio);
+                    call(&mut radio, &method, &params)
+                },
+                None => Err((-32700, "parse error".to_owned())),
+            };
+            method_response(result)
+        } else {
+            String::new()
+        };
+
+        let status = if request_line.starts_with("POST") { "200 OK" } else { "405 Method Not Allowed" };
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nServer: ts480-flrig\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{}",
+            status, response.len(), response,
+        )?;
+        writer.flush()?;
+    }
+}
+
+/// Reads a line of at most `MAX_LINE` bytes, which is empty at the
+/// end of the stream. Returns `None` if the line is longer.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    reader.take(MAX_LINE as u64).read_line(&mut line)?;
+    if line.len() == MAX_LINE && !line.ends_with('\n') {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+/// Answers a request that cannot be served and closes the connection.
+fn reject(writer: &mut TcpStream, status: &str) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {}\r\nServer: ts480-flrig\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)?;
+    writer.flush()
+}
+
+fn call<T: Transport>(radio: &mut TS480<T>, method: &str, params: &[Value]) -> Result<Value, Fault> {
+    let param = |n: usize| params.get(n).ok_or_else(|| (-32602, format!("{} needs {} parameters", method, n + 1)));
+    let frequency = |n: usize| -> Result<Frequency, Fault> {
+        let hz = param(n)?.as_f64().ok_or_else(|| (-32602, "frequency must be a number".to_owned()))?;
+        Frequency::from_hz(hz.round() as u64).map_err(fault)
+    };
+
+    match method {
+        "main.get_version" => Ok(Value::Str(FLRIG_VERSION.to_owned())),
+        "system.listMethods" => Ok(Value::Array(METHODS.iter().map(|m| Value::Str(m.to_string())).collect())),
+        "rig.get_xcvr" => Ok(Value::Str("TS-480".to_owned())),
+        "rig.get_info" => {
+            let status = radio.status().map_err(fault)?;
+            Ok(Value::Str(format!(
+                "R:TS-480\nT:{}\nFA:{}\nM:{}\n",
+                if status.transmitting { "X" } else { "R" },
+                status.frequency.hz(),
+                status.mode,
+            )))
+        },
+        "rig.get_vfo" => {
+            let status = radio.status().map_err(fault)?;
+            Ok(Value::Str(status.frequency.hz().to_string()))
+        },
+        "rig.set_vfo" | "rig.set_frequency" => {
+            let frequency = frequency(0)?;
+            match radio.read_rx_vfo().map_err(fault)? {
+                Vfo::B => radio.set_frequency_b(frequency),
+                _ => radio.set_frequency_a(frequency),
+            }.map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_vfoA" => Ok(Value::Str(radio.read_frequency_a().map_err(fault)?.hz().to_string())),
+        "rig.get_vfoB" => Ok(Value::Str(radio.read_frequency_b().map_err(fault)?.hz().to_string())),
+        "rig.set_vfoA" => {
+            radio.set_frequency_a(frequency(0)?).map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.set_vfoB" => {
+            radio.set_frequency_b(frequency(0)?).map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_AB" => {
+            let vfo = radio.read_rx_vfo().map_err(fault)?;
+            Ok(Value::Str(if vfo == Vfo::B { "B" } else { "A" }.to_owned()))
+        },
+        "rig.set_AB" => {
+            let vfo = param(0)?.as_str().unwrap_or("").parse::<Vfo>().map_err(fault)?;
+            radio.set_rx_vfo(vfo).map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_mode" => Ok(Value::Str(radio.read_mode().map_err(fault)?.to_string())),
+        "rig.set_mode" => {
+            let mode = param(0)?.as_str().unwrap_or("").parse::<Mode>().map_err(fault)?;
+            radio.set_mode(mode).map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_modes" => Ok(Value::Array(MODES.iter().map(|m| Value::Str(m.to_string())).collect())),
+        "rig.get_bw" => {
+            let width = radio.read_filter_width().map_err(fault)?;
+            Ok(Value::Array(vec![Value::Str(width.to_string()), Value::Str(String::new())]))
+        },
+        "rig.get_ptt" => Ok(Value::Int(radio.status().map_err(fault)?.transmitting as i64)),
+        "rig.set_ptt" => {
+            match param(0)?.as_f64() {
+                Some(ptt) if ptt != 0.0 => radio.ptt_on(PttSource::Microphone),
+                _ => radio.ptt_off(),
+            }.map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_split" => Ok(Value::Int(radio.status().map_err(fault)?.split as i64)),
+        "rig.set_split" => {
+            let rx_vfo = radio.read_rx_vfo().map_err(fault)?;
+            let tx_vfo = match param(0)?.as_f64() {
+                Some(split) if split != 0.0 => if rx_vfo == Vfo::B { Vfo::A } else { Vfo::B },
+                _ => rx_vfo,
+            };
+            radio.set_tx_vfo(tx_vfo).map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_power" => Ok(Value::Int(radio.read_power().map_err(fault)? as i64)),
+        "rig.set_power" => {
+            let watts = param(0)?.as_f64().unwrap_or(0.0).round();
+            radio.set_power(watts.clamp(0.0, 255.0) as u8).map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_volume" => {
+            let gain = radio.read_af_gain().map_err(fault)? as i64;
+            Ok(Value::Int((gain * 100 + 127) / 255))
+        },
+        "rig.set_volume" => {
+            let volume = param(0)?.as_f64().unwrap_or(0.0).clamp(0.0, 100.0);
+            radio.set_af_gain((volume * 255.0 / 100.0).round() as u8).map_err(fault)?;
+            Ok(Value::Int(0))
+        },
+        "rig.get_smeter" => {
+            let reading = i64::from(radio.read_smeter().map_err(fault)?.raw());
+            Ok(Value::Int(reading * 100 / METER_MAX as i64))
+        },
+        "rig.get_pwrmeter" => {
+            let reading = i64::from(radio.read_smeter().map_err(fault)?.raw());
+            Ok(Value::Int(reading * 100 / METER_MAX as i64))
+        },
+        _ => Err((-32601, format!("unknown method {}", method))),
+    }
+}
+
+fn fault(e: RadioError) -> Fault {
+    (1, e.to_string())
+}
+
+fn method_response(result: Result<Value, Fault>) -> String {
+    let body = match result {
+        Ok(value) => format!("<params><param>{}</param></params>", value.to_xml()),
+        Err((code, message)) => format!(
+            "<fault><value><struct>\
+             <member><name>faultCode</name><value><i4>{}</i4></value></member>\
+             <member><name>faultString</name><value>{}</value></member>\
+             </struct></value></fault>",
+            code, escape(&message),
+        ),
+    };
+    format!("<?xml version=\"1.0\"?>\n<methodResponse>{}</methodResponse>\n", body)
+}
+
+/// Extracts the method name and parameters from a `methodCall`.
+fn parse_call(xml: &str) -> Option<(String, Vec<Value>)> {
+    let method = element(xml, "methodName")?.trim().to_owned();
+
+    let mut params = Vec::new();
+    let mut rest = element(xml, "params").unwrap_or("");
+    while let Some(param) = element(rest, "param") {
+        params.push(parse_value(element(param, "value").unwrap_or("")));
+        let end = rest.find("</param>")? + "</param>".len();
+        rest = &rest[end..];
+    }
+
+    Some((method, params))
+}
+
+/// Parses the contents of a `<value>` element.
+fn parse_value(value: &str) -> Value {
+    if let Some(n) = element(value, "i4").or_else(|| element(value, "int")) {
+        return n.trim().parse().map(Value::Int).unwrap_or_else(|_| Value::Str(unescape(n)));
+    }
+    if let Some(n) = element(value, "double") {
+        return n.trim().parse().map(Value::Double).unwrap_or_else(|_| Value::Str(unescape(n)));
+    }
+    if let Some(b) = element(value, "boolean") {
+        return Value::Int((b.trim() == "1") as i64);
+    }
+    if let Some(s) = element(value, "string") {
+        return Value::Str(unescape(s));
+    }
+    Value::Str(unescape(value))
+}
+
+/// Returns the contents of the first `<tag>` element in `xml`.
+fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let empty = format!("<{}/>", tag);
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+
+    let start = xml.find(&open).map(|i| i + open.len());
+    match start {
+        Some(start) => {
+            let end = xml[start..].find(&close)? + start;
+            Some(&xml[start..end])
+        },
+        None if xml.contains(&empty) => Some(""),
+        None => None,
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"")
+        .replace("&apos;", "'").replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ts480::sim::Simulator;
+
+    fn radio() -> TS480<Simulator> {
+        TS480::with_transport(Simulator::new())
+    }
+
+    fn request(method: &str, params: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\"?>\n<methodCall><methodName>{}</methodName><params>{}</params></methodCall>",
+            method, params,
+        )
+    }
+
+    /// Parses a request and answers it the way `serve` does.
+    fn answer<T: Transport>(radio: &mut TS480<T>, xml: &str) -> Result<Value, Fault> {
+        let (method, params) = parse_call(xml).unwrap();
+        call(radio, &method, &params)
+    }
+
+    #[test]
+    fn parses_calls() {
+        let xml = request(
+            " rig.set_vfoA ",
+            "<param><value><double>7074000.0</double></value></param>\
+             <param><value><i4>-3</i4></value></param>\
+             <param><value><int>12</int></value></param>\
+             <param><value><boolean>1</boolean></value></param>\
+             <param><value><string>a &lt;b&gt; &amp; c</string></value></param>\
+             <param><value>USB</value></param>\
+             <param><value><string/></value></param>",
+        );
+        assert_eq!(parse_call(&xml), Some(("rig.set_vfoA".to_owned(), vec![
+            Value::Double(7_074_000.0),
+            Value::Int(-3),
+            Value::Int(12),
+            Value::Int(1),
+            Value::Str("a <b> & c".to_owned()),
+            Value::Str("USB".to_owned()),
+            Value::Str(String::new()),
+        ])));
+
+        assert_eq!(parse_call("<methodCall><methodName>rig.get_mode</methodName></methodCall>"),
+            Some(("rig.get_mode".to_owned(), Vec::new())));
+        assert_eq!(parse_call("<methodCall><params/></methodCall>"), None);
+        // A number that does not parse is kept as text
+        assert_eq!(parse_value("<i4>lots</i4>"), Value::Str("lots".to_owned()));
+    }
+
+    #[test]
+    fn encodes_responses() {
+        assert_eq!(
+            method_response(Ok(Value::Int(5))),
+            "<?xml version=\"1.0\"?>\n<methodResponse><params><param><value><i4>5</i4></value></param></params></methodResponse>\n",
+        );
+        assert_eq!(
+            Value::Array(vec![Value::Str("<&>".to_owned()), Value::Double(1.5)]).to_xml(),
+            "<value><array><data><value>&lt;&amp;&gt;</value><value><double>1.5</double></value></data></array></value>",
+        );
+
+        let fault = method_response(Err((-32601, "unknown method <x>".to_owned())));
+        assert!(fault.contains("<member><name>faultCode</name><value><i4>-32601</i4></value></member>"), "{}", fault);
+        assert!(fault.contains("<value>unknown method &lt;x&gt;</value>"), "{}", fault);
+    }
+
+    #[test]
+    fn sets_and_reads_frequency_and_mode() {
+        let mut radio = radio();
+        let set = request("rig.set_vfoA", "<param><value><double>7074000</double></value></param>");
+        assert_eq!(answer(&mut radio, &set), Ok(Value::Int(0)));
+        assert_eq!(answer(&mut radio, &request("rig.get_vfoA", "")), Ok(Value::Str("7074000".to_owned())));
+        assert_eq!(answer(&mut radio, &request("rig.get_vfo", "")), Ok(Value::Str("7074000".to_owned())));
+
+        let set = request("rig.set_mode", "<param><value>CW</value></param>");
+        assert_eq!(answer(&mut radio, &set), Ok(Value::Int(0)));
+        assert_eq!(answer(&mut radio, &request("rig.get_mode", "")), Ok(Value::Str("CW".to_owned())));
+        assert_eq!(
+            answer(&mut radio, &request("rig.get_info", "")),
+            Ok(Value::Str("R:TS-480\nT:R\nFA:7074000\nM:CW\n".to_owned())),
+        );
+    }
+
+    #[test]
+    fn keys_and_splits() {
+        let mut radio = radio();
+        let ptt = |on: i32| request("rig.set_ptt", &format!("<param><value><i4>{}</i4></value></param>", on));
+        assert_eq!(answer(&mut radio, &ptt(1)), Ok(Value::Int(0)));
+        assert_eq!(answer(&mut radio, &request("rig.get_ptt", "")), Ok(Value::Int(1)));
+        assert_eq!(answer(&mut radio, &ptt(0)), Ok(Value::Int(0)));
+        assert!(!radio.transport().state().transmitting);
+
+        let split = request("rig.set_split", "<param><value><i4>1</i4></value></param>");
+        assert_eq!(answer(&mut radio, &split), Ok(Value::Int(0)));
+        assert_eq!(answer(&mut radio, &request("rig.get_split", "")), Ok(Value::Int(1)));
+        assert_eq!(radio.transport().state().tx_vfo, Vfo::B);
+    }
+
+    #[test]
+    fn reports_faults() {
+        let mut radio = radio();
+        assert_eq!(answer(&mut radio, &request("rig.tune", "")), Err((-32601, "unknown method rig.tune".to_owned())));
+        assert_eq!(
+            answer(&mut radio, &request("rig.set_vfoA", "")),
+            Err((-32602, "rig.set_vfoA needs 1 parameters".to_owned())),
+        );
+        let out_of_range = request("rig.set_vfoA", "<param><value><double>70000000</double></value></param>");
+        assert_eq!(answer(&mut radio, &out_of_range).unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn limits_line_length() {
+        let mut short = io::Cursor::new("POST / HTTP/1.1\r\nHost: x\r\n");
+        assert_eq!(read_line(&mut short).unwrap(), Some("POST / HTTP/1.1\r\n".to_owned()));
+        assert_eq!(read_line(&mut short).unwrap(), Some("Host: x\r\n".to_owned()));
+        assert_eq!(read_line(&mut short).unwrap(), Some(String::new()));
+
+        let mut long = io::Cursor::new(format!("X-Padding: {}\r\n", "x".repeat(MAX_LINE)));
+        assert_eq!(read_line(&mut long).unwrap(), None);
+    }
+}
@@ -0,0 +1,528 @@
+//! A Hamlib `rigctld`-compatible network daemon, so programs that
+//! speak the rigctld protocol can share one connection to the radio.
+//!
+//! Usage: `ts480-rigctld [--port <device>] [--baud <rate>]
+//! [--tcp <address>] [--listen <address>] [--max-tx <seconds>]`
+//!
+//! The radio is opened on `--port` (default `/dev/ttyUSB0`), or
+//! reached over TCP with `--tcp`, e.g. a `ts480-sim` instance.
+//! Clients connect to `--listen`, by default `127.0.0.1:4532`.
+//!
+//! With `--max-tx`, the radio is switched back to receive after
+//! transmitting for that many seconds, whether or not any client
+//! is still connected, and is not keyed again until a client sends
+//! `T 0`. A client that disconnects while the radio is transmitting
+//! because of it switches it back to receive as well.
+
+extern crate ts480;
+
+mod daemon;
+
+use ts480::{
+    Frequency, Mode, PttSource, RadioError, TS480,
+    Transport, Vfo,
+};
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+/// Hamlib model number of the TS-480
+const RIG_MODEL: u32 = 2028;
+
+// Hamlib error codes
+const RIG_EINVAL: i32 = -1;
+const RIG_ENIMPL: i32 = -4;
+const RIG_ETIMEOUT: i32 = -5;
+const RIG_EIO: i32 = -6;
+const RIG_EPROTO: i32 = -8;
+const RIG_ERJCTED: i32 = -9;
+
+// Hamlib level bits
+const LEVEL_AF: u64 = 1 << 3;
+const LEVEL_RF: u64 = 1 << 4;
+const LEVEL_SQL: u64 = 1 << 5;
+const LEVEL_RFPOWER: u64 = 1 << 12;
+const LEVEL_MICGAIN: u64 = 1 << 13;
+const LEVEL_KEYSPD: u64 = 1 << 14;
+const LEVEL_RAWSTR: u64 = 1 << 26;
+const LEVEL_STRENGTH: u64 = 1 << 30;
+
+const SET_LEVELS: u64 = LEVEL_AF | LEVEL_RF | LEVEL_SQL | LEVEL_RFPOWER | LEVEL_MICGAIN | LEVEL_KEYSPD;
+const GET_LEVELS: u64 = SET_LEVELS | LEVEL_RAWSTR | LEVEL_STRENGTH;
+
+/// Amateur bands the TS-480 transmits on, in Hz
+const TX_RANGES: &[(u64, u64)] = &[
+    (1_800_000, 2_000_000),
+    (3_500_000, 4_000_000),
+    (5_330_500, 5_406_500),
+    (7_000_000, 7_300_000),
+    (10_100_000, 10_150_000),
+    (14_000_000, 14_350_000),
+    (18_068_000, 18_168_000),
+    (21_000_000, 21_450_000),
+    (24_890_000, 24_990_000),
+    (28_000_000, 29_700_000),
+    (50_000_000, 54_000_000),
+];
+
+/// A successful reply: labelled values, or nothing for set commands.
+type Reply = Vec<(&'static str, String)>;
+
+fn main() {
+    daemon::main("ts480-rigctld", "127.0.0.1:4532", serve::<daemon::Port>);
+}
+
+/// Answers the commands of one client until it disconnects or quits.
+///
+/// If the client keyed the radio and did not switch it back to
+/// receive, that is done when it goes, since nobody else will.
+fn serve<T: Transport>(radio: Arc<Mutex<TS480<T>>>, stream: TcpStream) -> io::Result<()> {
+    let mut keyed = false;
+    let result = answer(&radio, stream, &mut keyed);
+
+    if keyed {
+        let mut radio = daemon::lock(&radio);
+        if radio.is_transmitting() {
+            if let Err(e) = radio.ptt_off() {
+                eprintln!("cannot switch to receive: {}", e);
+            }
+        }
+    }
+    result
+}
+
+/// Answers commands, noting in `keyed` whether the
+/// client last switched the radio to transmit.
+fn answer<T: Transport>(radio: &Mutex<TS480<T>>, stream: TcpStream, keyed: &mut bool) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    for line in reader.lines() {
+        let line = line?;
+        let mut line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // A leading `+` asks for the extended response format
+        let extended = line.starts_with('+');
+        if extended {
+            line = line[1..].trim_start();
+        }
+
+        let (name, args) = split_command(line);
+        if name == "q" || name == "Q" || name == "\\quit" {
+            return Ok(());
+        }
+
+        let result = {
+            let mut radio = daemon::lock(radio);
+            execute(&mut radio, name, &args)
+        };
+        if long_name(name) == "set_ptt" && result.is_ok() {
+            *keyed = args.first() != Some(&"0");
+        }
+        let response = format_response(name, &args, result, extended);
+        writer.write_all(response.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Splits a line into the command and its arguments. Short commands
+/// may be written without a space before their first argument.
+fn split_command(line: &str) -> (&str, Vec<&str>) {
+    let mut words = line.split_whitespace();
+    let first = words.next().unwrap_or("");
+
+    if first.starts_with('\\') || first.len() == 1 {
+        (first, words.collect())
+    } else {
+        let split = first.char_indices().nth(1).map(|(i, _)| i).unwrap_or(first.len());
+        let mut args = vec![&first[split..]];
+        args.extend(words);
+        (&first[..split], args)
+    }
+}
+
+fn format_response(name: &str, args: &[&str], result: Result<Reply, i32>, extended: bool) -> String {
+    let mut response = String::new();
+
+    if extended {
+        if args.is_empty() {
+            response.push_str(&format!("{}:\n", long_name(name)));
+        } else {
+            response.push_str(&format!("{}: {}\n", long_name(name), args.join(" ")));
+        }
+    }
+
+    match result {
+        Ok(ref values) if values.is_empty() => response.push_str("RPRT 0\n"),
+        Ok(values) => {
+            for (label, value) in values {
+                if extended && !label.is_empty() {
+                    response.push_str(&format!("{}: {}\n", label, value));
+                } else {
+                    response.push_str(&format!("{}\n", value));
+                }
+            }
+            if extended {
+                response.push_str("RPRT 0\n");
+            }
+        },
+        Err(code) => response.push_str(&format!("RPRT {}\n", code)),
+    }
+
+    response
+}
+
+/// Returns the long form of a command, used in extended responses.
+fn long_name(name: &str) -> &str {
+    match name {
+        "f" => "get_freq",
+        "F" => "set_freq",
+        "m" => "get_mode",
+        "M" => "set_mode",
+        "t" => "get_ptt",
+        "T" => "set_ptt",
+        "v" => "get_vfo",
+        "V" => "set_vfo",
+        "s" => "get_split_vfo",
+        "S" => "set_split_vfo",
+        "l" => "get_level",
+        "L" => "set_level",
+        "_" => "get_info",
+        _ => name.trim_start_matches('\\'),
+    }
+}
+
+fn execute<T: Transport>(radio: &mut TS480<T>, name: &str, args: &[&str]) -> Result<Reply, i32> {
+    let arg = |n: usize| args.get(n).cloned().ok_or(RIG_EINVAL);
+
+    match long_name(name) {
+        "get_freq" => {
+            let status = radio.status().map_err(error_code)?;
+            Ok(vec![("Frequency", status.frequency.hz().to_string())])
+        },
+        "set_freq" => {
+            let hz = arg(0)?.parse::<f64>().map_err(|_| RIG_EINVAL)?;
+            let frequency = Frequency::from_hz(hz.round() as u64).map_err(error_code)?;
+            match radio.read_rx_vfo().map_err(error_code)? {
+                Vfo::A => radio.set_frequency_a(frequency),
+                Vfo::B => radio.set_frequency_b(frequency),
+                Vfo::Memory => return Err(RIG_ERJCTED),
+            }.map_err(error_code)?;
+            Ok(vec![])
+        },
+        "get_mode" => {
+            let mode = radio.read_mode().map_err(error_code)?;
+            let passband = match mode {
+                Mode::Cw | Mode::CwReverse | Mode::Fsk | Mode::FskReverse => {
+                    radio.read_filter_width().map_err(error_code)?
+                },
+                _ => 0,
+            };
+            Ok(vec![("Mode", hamlib_mode(mode).to_owned()), ("Passband", passband.to_string())])
+        },
+        "set_mode" => {
+            let mode = parse_hamlib_mode(arg(0)?)?;
+            let passband = args.get(1).map(|p| p.parse::<i64>().map_err(|_| RIG_EINVAL)).unwrap_or(Ok(-1))?;
+            radio.set_mode(mode).map_err(error_code)?;
+            match mode {
+                Mode::Cw | Mode::CwReverse | Mode::Fsk | Mode::FskReverse if passband > 0 => {
+                    radio.set_filter_width(passband.min(9999) as u16).map_err(error_code)?;
+                },
+                _ => {},
+            }
+            Ok(vec![])
+        },
+        "get_ptt" => {
+            let status = radio.status().map_err(error_code)?;
+            Ok(vec![("PTT", (status.transmitting as u8).to_string())])
+        },
+        "set_ptt" => {
+            match arg(0)? {
+                "0" => radio.ptt_off(),
+                "1" => radio.ptt_on(PttSource::Microphone),
+                "2" => radio.ptt_on(PttSource::Microphone),
+                "3" => radio.ptt_on(PttSource::Data),
+                _ => return Err(RIG_EINVAL),
+            }.map_err(error_code)?;
+            Ok(vec![])
+        },
+        "get_vfo" => {
+            let vfo = radio.read_rx_vfo().map_err(error_code)?;
+            Ok(vec![("VFO", hamlib_vfo(vfo).to_owned())])
+        },
+        "set_vfo" => {
+            let vfo = parse_hamlib_vfo(arg(0)?)?;
+            radio.set_rx_vfo(vfo).map_err(error_code)?;
+            Ok(vec![])
+        },
+        "get_split_vfo" => {
+            let status = radio.status().map_err(error_code)?;
+            let tx_vfo = radio.read_tx_vfo().map_err(error_code)?;
+            Ok(vec![("Split", (status.split as u8).to_string()), ("TX VFO", hamlib_vfo(tx_vfo).to_owned())])
+        },
+        "set_split_vfo" => {
+            let rx_vfo = radio.read_rx_vfo().map_err(error_code)?;
+            let tx_vfo = match arg(0)? {
+                "0" => rx_vfo,
+                "1" => match args.get(1) {
+                    Some(vfo) => parse_hamlib_vfo(vfo)?,
+                    None if rx_vfo == Vfo::A => Vfo::B,
+                    None => Vfo::A,
+                },
+                _ => return Err(RIG_EINVAL),
+            };
+            radio.set_tx_vfo(tx_vfo).map_err(error_code)?;
+            Ok(vec![])
+        },
+        "get_level" => {
+            let value = match arg(0)?.to_ascii_uppercase().as_str() {
+                "AF" => format!("{:.6}", radio.read_af_gain().map_err(error_code)? as f64 / 255.0),
+                "RF" => format!("{:.6}", radio.read_rf_gain().map_err(error_code)? as f64 / 255.0),
+                "SQL" => format!("{:.6}", radio.read_squelch().map_err(error_code)? as f64 / 255.0),
+                "RFPOWER" => format!("{:.6}", radio.read_power().map_err(error_code)? as f64 / 100.0),
+                "MICGAIN" => format!("{:.6}", radio.read_mic_gain().map_err(error_code)? as f64 / 100.0),
+                "KEYSPD" => radio.read_keying_speed().map_err(error_code)?.to_string(),
+                "RAWSTR" => radio.read_smeter().map_err(error_code)?.raw().to_string(),
+                "STRENGTH" => radio.read_smeter().map_err(error_code)?.db_over_s9().round().to_string(),
+                _ => return Err(RIG_EINVAL),
+            };
+            Ok(vec![("Level", value)])
+        },
+        "set_level" => {
+            let level = arg(0)?.to_ascii_uppercase();
+            let value = arg(1)?.parse::<f64>().map_err(|_| RIG_EINVAL)?;
+            let scaled = |max: f64| (value.clamp(0.0, 1.0) * max).round() as u8;
+            match level.as_str() {
+                "AF" => radio.set_af_gain(scaled(255.0)),
+                "RF" => radio.set_rf_gain(scaled(255.0)),
+                "SQL" => radio.set_squelch(scaled(255.0)),
+                "RFPOWER" => radio.set_power(scaled(100.0).max(5)),
+                "MICGAIN" => radio.set_mic_gain(scaled(100.0)),
+                "KEYSPD" => radio.set_keying_speed(value.round() as u8),
+                _ => return Err(RIG_EINVAL),
+            }.map_err(error_code)?;
+            Ok(vec![])
+        },
+        "get_powerstat" => {
+            let on = radio.read_power_status().map_err(error_code)?;
+            Ok(vec![("Power Status", (on as u8).to_string())])
+        },
+        "set_powerstat" => {
+            radio.set_power_status(arg(0)? != "0").map_err(error_code)?;
+            Ok(vec![])
+        },
+        "get_info" => Ok(vec![("Info", "Kenwood TS-480".to_owned())]),
+        "chk_vfo" => Ok(vec![("ChkVFO", "0".to_owned())]),
+        "dump_state" => Ok(vec![("", dump_state())]),
+        _ => Err(RIG_ENIMPL),
+    }
+}
+
+fn error_code(e: RadioError) -> i32 {
+    match e {
+        RadioError::InvalidParameter(_) => RIG_EINVAL,
+        RadioError::SyntaxOrStatus | RadioError::TransmitRefused(_) => RIG_ERJCTED,
+        RadioError::Io(ref e) if e.kind() == io::ErrorKind::TimedOut => RIG_ETIMEOUT,
+        RadioError::Io(_) | RadioError::Serial(_) | RadioError::NotDetected => RIG_EIO,
+        _ => RIG_EPROTO,
+    }
+}
+
+fn hamlib_mode(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Lsb => "LSB",
+        Mode::Usb => "USB",
+        Mode::Cw => "CW",
+        Mode::Fm => "FM",
+        Mode::Am => "AM",
+        Mode::Fsk => "RTTY",
+        Mode::CwReverse => "CWR",
+        Mode::FskReverse => "RTTYR",
+    }
+}
+
+fn parse_hamlib_mode(mode: &str) -> Result<Mode, i32> {
+    match mode.to_ascii_uppercase().as_str() {
+        "LSB" | "PKTLSB" => Ok(Mode::Lsb),
+        "USB" | "PKTUSB" => Ok(Mode::Usb),
+        "CW" => Ok(Mode::Cw),
+        "FM" | "PKTFM" => Ok(Mode::Fm),
+        "AM" => Ok(Mode::Am),
+        "RTTY" => Ok(Mode::Fsk),
+        "CWR" => Ok(Mode::CwReverse),
+        "RTTYR" => Ok(Mode::FskReverse),
+        _ => Err(RIG_EINVAL),
+    }
+}
+
+fn hamlib_vfo(vfo: Vfo) -> &'static str {
+    match vfo {
+        Vfo::A => "VFOA",
+        Vfo::B => "VFOB",
+        Vfo::Memory => "MEM",
+    }
+}
+
+fn parse_hamlib_vfo(vfo: &str) -> Result<Vfo, i32> {
+    match vfo.to_ascii_uppercase().as_str() {
+        "VFOA" | "MAIN" | "CURRVFO" => Ok(Vfo::A),
+        "VFOB" | "SUB" => Ok(Vfo::B),
+        "MEM" => Ok(Vfo::Memory),
+        _ => Err(RIG_EINVAL),
+    }
+}
+
+/// Describes the radio's capabilities, in version 0 of
+/// the format read by Hamlib's `NET rigctl` backend.
+fn dump_state() -> String {
+    // Hamlib mode bits
+    let am = 0x1;
+    let cw = 0x2;
+    let usb = 0x4;
+    let lsb = 0x8;
+    let rtty = 0x10;
+    let fm = 0x20;
+    let cwr = 0x80;
+    let rttyr = 0x100;
+    let all = am | cw | usb | lsb | rtty | fm | cwr | rttyr;
+    let vfos = 0x3;
+    let antennas = 0x3;
+
+    let mut state = String::new();
+    state.push_str(&format!("0\n{}\n2\n", RIG_MODEL));
+
+    state.push_str(&format!("{}.000000 {}.000000 0x{:x} -1 -1 0x{:x} 0x{:x}\n",
+        Frequency::MIN_HZ, Frequency::MAX_HZ, all, vfos, antennas));
+    state.push_str("0 0 0 0 0 0 0\n");
+
+    for &(start, end) in TX_RANGES {
+        state.push_str(&format!("{}.000000 {}.000000 0x{:x} 5000 100000 0x{:x} 0x{:x}\n",
+            start, end, all & !am, vfos, antennas));
+        state.push_str(&format!("{}.000000 {}.000000 0x{:x} 5000 25000 0x{:x} 0x{:x}\n",
+            start, end, am, vfos, antennas));
+    }
+    state.push_str("0 0 0 0 0 0 0\n");
+
+    for step in &[1, 10, 100, 1000, 5000, 10000] {
+        state.push_str(&format!("0x{:x} {}\n", all, step));
+    }
+    state.push_str("0 0\n");
+
+    state.push_str(&format!("0x{:x} 2400\n", usb | lsb));
+    state.push_str(&format!("0x{:x} 500\n", cw | cwr | rtty | rttyr));
+    state.push_str(&format!("0x{:x} 6000\n", am));
+    state.push_str(&format!("0x{:x} 12000\n", fm));
+    state.push_str("0 0\n");
+
+    // max RIT, max XIT, max IF shift, announces
+    state.push_str("9999\n9999\n0\n0\n");
+    // preamp and attenuator levels in dB
+    state.push_str("10\n12\n");
+    // functions, levels, parameters
+    state.push_str(&format!("0x0\n0x0\n0x{:x}\n0x{:x}\n0x0\n0x0", GET_LEVELS, SET_LEVELS));
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ts480::sim::Simulator;
+
+    fn radio() -> TS480<Simulator> {
+        TS480::with_transport(Simulator::new())
+    }
+
+    /// Runs a command line the way `answer` does.
+    fn run<T: Transport>(radio: &mut TS480<T>, line: &str) -> String {
+        let extended = line.starts_with('+');
+        let (name, args) = split_command(line.trim_start_matches('+'));
+        let result = execute(radio, name, &args);
+        format_response(name, &args, result, extended)
+    }
+
+    #[test]
+    fn splits_commands() {
+        assert_eq!(split_command("F 7074000"), ("F", vec!["7074000"]));
+        assert_eq!(split_command("F7074000"), ("F", vec!["7074000"]));
+        assert_eq!(split_command("M USB 2400"), ("M", vec!["USB", "2400"]));
+        assert_eq!(split_command("\\set_freq  14074000"), ("\\set_freq", vec!["14074000"]));
+        assert_eq!(split_command("f"), ("f", vec![]));
+    }
+
+    #[test]
+    fn formats_responses() {
+        assert_eq!(format_response("F", &["7074000"], Ok(vec![]), false), "RPRT 0\n");
+        assert_eq!(format_response("F", &["7074000"], Ok(vec![]), true), "set_freq: 7074000\nRPRT 0\n");
+        assert_eq!(format_response("f", &[], Err(RIG_ETIMEOUT), false), "RPRT -5\n");
+
+        let mode = vec![("Mode", "USB".to_owned()), ("Passband", "0".to_owned())];
+        assert_eq!(format_response("m", &[], Ok(mode.clone()), false), "USB\n0\n");
+        assert_eq!(format_response("m", &[], Ok(mode), true), "get_mode:\nMode: USB\nPassband: 0\nRPRT 0\n");
+    }
+
+    #[test]
+    fn sets_and_reads_frequency_and_mode() {
+        let mut radio = radio();
+        assert_eq!(run(&mut radio, "F 7074000"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "f"), "7074000\n");
+        assert_eq!(run(&mut radio, "M CW 500"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "+m"), "get_mode:\nMode: CW\nPassband: 500\nRPRT 0\n");
+        assert_eq!(radio.transport().state().mode, Mode::Cw);
+
+        assert_eq!(run(&mut radio, "V VFOB"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "F 3573000"), "RPRT 0\n");
+        assert_eq!(radio.transport().state().frequency_b, Frequency::from_hz(3_573_000).unwrap());
+        assert_eq!(run(&mut radio, "v"), "VFOB\n");
+    }
+
+    #[test]
+    fn keys_and_splits() {
+        let mut radio = radio();
+        assert_eq!(run(&mut radio, "T 1"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "t"), "1\n");
+        assert_eq!(run(&mut radio, "T 0"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "t"), "0\n");
+
+        assert_eq!(run(&mut radio, "S 1 VFOB"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "s"), "1\nVFOB\n");
+        assert_eq!(run(&mut radio, "S 0"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "s"), "0\nVFOA\n");
+    }
+
+    #[test]
+    fn sets_and_reads_levels() {
+        let mut radio = radio();
+        assert_eq!(run(&mut radio, "L RFPOWER 0.5"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "l RFPOWER"), "0.500000\n");
+        assert_eq!(run(&mut radio, "L KEYSPD 25"), "RPRT 0\n");
+        assert_eq!(run(&mut radio, "l KEYSPD"), "25\n");
+
+        radio.transport_mut().state_mut().smeter = 15;
+        assert_eq!(run(&mut radio, "l RAWSTR"), "15\n");
+        assert_eq!(run(&mut radio, "l STRENGTH"), "0\n");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut radio = radio();
+        assert_eq!(run(&mut radio, "F"), "RPRT -1\n");
+        assert_eq!(run(&mut radio, "F 70000000"), "RPRT -1\n");
+        assert_eq!(run(&mut radio, "M DSB"), "RPRT -1\n");
+        assert_eq!(run(&mut radio, "l NOTCH"), "RPRT -1\n");
+        assert_eq!(run(&mut radio, "\\set_ant 1"), "RPRT -4\n");
+        assert_eq!(run(&mut radio, "T 5"), "RPRT -1\n");
+    }
+
+    #[test]
+    fn describes_state() {
+        let state = dump_state();
+        assert!(state.starts_with("0\n2028\n2\n30000.000000 60000000.000000 "), "{}", state);
+        assert!(state.contains("\n14000000.000000 14350000.000000 "), "{}", state);
+    }
+}
@@ -0,0 +1,152 @@
+//! Serves a simulated TS-480 over TCP or a pseudo-terminal.
+//!
+//! Usage: `ts480-sim --tcp 127.0.0.1:4533` or `ts480-sim --pty`
+//!
+//! Lines typed on standard input are applied as front panel
+//! operations, e.g. `FA00007074000`, so Auto Information
+//! reports can be exercised.
+
+extern crate ts480;
+#[cfg(unix)]
+extern crate libc;
+
+use ts480::sim::Simulator;
+
+use std::env;
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpListener;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const USAGE: &str = "usage: ts480-sim --tcp <address> | --pty";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let simulator = Arc::new(Mutex::new(Simulator::new()));
+
+    spawn_front_panel(simulator.clone());
+
+    let result = match args.first().map(|a| a.as_str()) {
+        Some("--tcp") => match args.get(1) {
+            Some(address) => serve_tcp(address, simulator),
+            None => usage(),
+        },
+        Some("--pty") => serve_pty(simulator),
+        _ => usage(),
+    };
+
+    if let Err(e) = result {
+        eprintln!("ts480-sim: {}", e);
+        process::exit(1);
+    }
+}
+
+fn usage() -> io::Result<()> {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Applies lines from standard input as front panel operations.
+fn spawn_front_panel(simulator: Arc<Mutex<Simulator>>) {
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => return,
+            };
+            let command = line.trim().trim_end_matches(';');
+            if command.is_empty() {
+                continue;
+            }
+            if !simulator.lock().unwrap().front_panel(command) {
+                eprintln!("rejected: {}", command);
+            }
+        }
+    });
+}
+
+fn serve_tcp(address: &str, simulator: Arc<Mutex<Simulator>>) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    eprintln!("listening on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        eprintln!("connection from {}", stream.peer_addr()?);
+        let reader = stream.try_clone()?;
+        if let Err(e) = serve(simulator.clone(), reader, stream) {
+            eprintln!("connection closed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn serve_pty(simulator: Arc<Mutex<Simulator>>) -> io::Result<()> {
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::os::unix::io::FromRawFd;
+
+    let master = unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if fd < 0 || libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let name = libc::ptsname(fd);
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        println!("{}", CStr::from_ptr(name).to_string_lossy());
+        File::from_raw_fd(fd)
+    };
+
+    // Reading the master fails while no one has the
+    // other side open, so keep retrying until they do.
+    loop {
+        let reader = master.try_clone()?;
+        let writer = master.try_clone()?;
+        if let Err(e) = serve(simulator.clone(), reader, writer) {
+            if e.raw_os_error() != Some(libc::EIO) {
+                return Err(e);
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[cfg(not(unix))]
+fn serve_pty(_simulator: Arc<Mutex<Simulator>>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "pseudo-terminals are only supported on Unix"))
+}
+
+/// Feeds everything read from `reader` to the simulator, and writes
+/// its answers and Auto Information reports to `writer`, until
+/// `reader` is closed.
+fn serve<R, W>(simulator: Arc<Mutex<Simulator>>, mut reader: R, mut writer: W) -> io::Result<()>
+    where R: Read + Send + 'static, W: Write
+{
+    let input = simulator.clone();
+    let receiver = thread::spawn(move || -> io::Result<()> {
+        let mut buf = [0; 256];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            input.lock().unwrap().feed(&buf[..n]);
+        }
+    });
+
+    while !receiver.is_finished() {
+        let output = simulator.lock().unwrap().take_output();
+        if !output.is_empty() {
+            writer.write_all(&output)?;
+            writer.flush()?;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    receiver.join().unwrap()
+}
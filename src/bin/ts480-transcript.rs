@@ -0,0 +1,37 @@
+//! Converts a recorded CAT session into a readable transcript.
+//!
+//! Usage: `ts480-transcript <file>`
+//!
+//! Sessions are recorded with `ts480 --record` or
+//! `ts480::recording::Recorder`. Each command and answer is
+//! printed on a line of its own with the time since the start
+//! of the recording, `>` for commands sent to the radio and `<`
+//! for its answers, followed by the meaning of its fields.
+
+extern crate ts480;
+
+use ts480::recording::Session;
+
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: ts480-transcript <file>";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let path = match (args.next(), args.next()) {
+        (Some(path), None) if !path.starts_with("--") => path,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+
+    match Session::open(&path) {
+        Ok(session) => print!("{}", session.transcript()),
+        Err(e) => {
+            eprintln!("ts480-transcript: {}: {}", path, e);
+            process::exit(1);
+        },
+    }
+}
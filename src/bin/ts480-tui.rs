@@ -0,0 +1,391 @@
+//! A front panel in the terminal, for operating the radio remotely.
+//! Built with the `tui` feature.
+//!
+//! Usage: `ts480-tui [--port <device>] [--baud <rate>] [--tcp <address>] [--sim]`
+//!
+//! The radio is opened on `--port` (default `/dev/ttyUSB0`), reached
+//! over TCP with `--tcp`, e.g. a `ts480-sim` instance, or replaced by
+//! a simulator running in the same process with `--sim`.
+//!
+//! ```text
+//! Left/Right  tune down/up          [ ]  tuning step
+//! Down/Up     band down/up          m M  next/previous mode
+//! v           receive on VFO A/B    t    transmit/receive
+//! s           select SWR/COMP/ALC meter
+//! j k         select menu item      - +  change menu item
+//! q Esc       quit
+//! ```
+
+extern crate ratatui;
+extern crate ts480;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use ts480::sim::Simulator;
+use ts480::{
+    BaudRate, ExMenu, Frequency, MeterKind, Mode, PttSource, RadioResult,
+    SMeterReading, TS480, TS480Options, Transport, Vfo, METER_MAX,
+};
+
+use std::env;
+use std::io;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: ts480-tui [--port <device>] [--baud <rate>] [--tcp <address>] [--sim]";
+
+/// How often the display is refreshed from the radio
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Settings that rarely change are read every this many polls
+const SLOW_POLL: u32 = 5;
+
+/// Tuning steps selectable with `[` and `]`, in Hz
+const STEPS: &[u64] = &[10, 100, 1_000, 10_000, 100_000];
+
+/// Modes in the order `m` steps through them
+const MODES: &[Mode] = &[
+    Mode::Lsb, Mode::Usb, Mode::Cw, Mode::CwReverse,
+    Mode::Fsk, Mode::FskReverse, Mode::Fm, Mode::Am,
+];
+
+const METERS: [MeterKind; 3] = [MeterKind::Swr, MeterKind::Comp, MeterKind::Alc];
+
+/// What the panel shows, as last read from the radio.
+#[derive(Default)]
+struct Panel {
+    frequency_a: Option<Frequency>,
+    frequency_b: Option<Frequency>,
+    frequency: Option<Frequency>,
+    vfo: Option<Vfo>,
+    mode: Option<Mode>,
+    transmitting: bool,
+    split: bool,
+    filter_width: Option<u16>,
+    power: Option<u8>,
+    smeter: u16,
+    /// Meter selected with `RM`, as an index into `METERS`
+    selected_meter: usize,
+    /// SWR, COMP and ALC readings, 0 - 30
+    meters: [u16; 3],
+    menu: Vec<Option<u16>>,
+    menu_state: ListState,
+    step: usize,
+    message: String,
+}
+
+fn main() {
+    let mut port = String::from("/dev/ttyUSB0");
+    let mut baud = None;
+    let mut tcp = None;
+    let mut sim = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().unwrap_or_else(|| usage()),
+            "--baud" => baud = Some(args.next().and_then(|b| b.parse::<usize>().ok()).unwrap_or_else(|| usage())),
+            "--tcp" => tcp = Some(args.next().unwrap_or_else(|| usage())),
+            "--sim" => sim = true,
+            _ => usage(),
+        }
+    }
+
+    let result = if sim {
+        run(TS480::with_transport(Simulator::new()))
+    } else if let Some(address) = tcp {
+        match TS480Options::new().connect_tcp(&address) {
+            Ok(radio) => run(radio),
+            Err(e) => fail(&format!("{}: {}", address, e)),
+        }
+    } else {
+        let mut options = TS480Options::new();
+        if let Some(baud) = baud {
+            options = options.baud_rate(BaudRate::from_speed(baud));
+        }
+        match options.open(&port) {
+            Ok(radio) => run(radio),
+            Err(e) => fail(&format!("{}: {}", port, e)),
+        }
+    };
+
+    if let Err(e) = result {
+        fail(&e.to_string());
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("ts480-tui: {}", message);
+    process::exit(1);
+}
+
+/// Reads the menu, then runs the panel until the user quits.
+fn run<T: Transport>(mut radio: TS480<T>) -> io::Result<()> {
+    let mut panel = Panel { step: 2, ..Panel::default() };
+    panel.menu = ExMenu::ALL.iter().map(|&item| radio.read_menu(item).ok()).collect();
+    panel.menu_state.select(Some(0));
+
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, &mut radio, &mut panel);
+    ratatui::restore();
+    result
+}
+
+fn event_loop<T: Transport>(terminal: &mut DefaultTerminal, radio: &mut TS480<T>, panel: &mut Panel) -> io::Result<()> {
+    let mut polls = 0;
+    let mut next_poll = Instant::now();
+
+    loop {
+        if Instant::now() >= next_poll {
+            if let Err(e) = poll(radio, panel, polls % SLOW_POLL == 0) {
+                panel.message = e.to_string();
+            }
+            polls += 1;
+            next_poll = Instant::now() + POLL_INTERVAL;
+        }
+
+        terminal.draw(|frame| draw(frame, panel))?;
+
+        if event::poll(next_poll.saturating_duration_since(Instant::now()))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if key.code == KeyCode::Char('q') || key.code == KeyCode::Esc {
+                    return Ok(());
+                }
+                match handle_key(radio, panel, key.code) {
+                    Ok(()) => next_poll = Instant::now(),
+                    Err(e) => panel.message = e.to_string(),
+                }
+            }
+        }
+    }
+}
+
+/// Reads what the panel shows. The status and meters are read
+/// every time, the rest only if `slow` is set.
+fn poll<T: Transport>(radio: &mut TS480<T>, panel: &mut Panel, slow: bool) -> RadioResult<()> {
+    let status = radio.status()?;
+    panel.frequency = Some(status.frequency);
+    panel.vfo = Some(status.vfo);
+    panel.mode = Some(status.mode);
+    panel.transmitting = status.transmitting;
+    panel.split = status.split;
+
+    panel.smeter = radio.read_smeter()?.raw();
+    if status.transmitting || slow {
+        panel.meters[panel.selected_meter] = radio.read_meter(METERS[panel.selected_meter])?;
+    }
+
+    if slow {
+        panel.frequency_a = Some(radio.read_frequency_a()?);
+        panel.frequency_b = Some(radio.read_frequency_b()?);
+        panel.filter_width = Some(radio.read_filter_width()?);
+        panel.power = Some(radio.read_power()?);
+    }
+    Ok(())
+}
+
+fn handle_key<T: Transport>(radio: &mut TS480<T>, panel: &mut Panel, key: KeyCode) -> RadioResult<()> {
+    panel.message.clear();
+
+    match key {
+        KeyCode::Left | KeyCode::Right => {
+            let step = STEPS[panel.step] as i64;
+            let step = if key == KeyCode::Left { -step } else { step };
+            let frequency = match panel.frequency {
+                Some(frequency) => frequency,
+                None => return Ok(()),
+            };
+            let hz = (frequency.hz() as i64 + step).max(0) as u64;
+            let frequency = Frequency::from_hz(hz - hz % STEPS[panel.step])?;
+            match panel.vfo {
+                Some(Vfo::A) => radio.set_frequency_a(frequency)?,
+                Some(Vfo::B) => radio.set_frequency_b(frequency)?,
+                _ => panel.message = "cannot tune a memory channel".to_owned(),
+            }
+        },
+        KeyCode::Char('[') => panel.step = panel.step.saturating_sub(1),
+        KeyCode::Char(']') => panel.step = (panel.step + 1).min(STEPS.len() - 1),
+        KeyCode::Up => radio.frequency_up()?,
+        KeyCode::Down => radio.frequency_down()?,
+        KeyCode::Char('m') | KeyCode::Char('M') => {
+            let current = panel.mode.and_then(|mode| MODES.iter().position(|&m| m == mode)).unwrap_or(0);
+            let next = if key == KeyCode::Char('m') {
+                (current + 1) % MODES.len()
+            } else {
+                (current + MODES.len() - 1) % MODES.len()
+            };
+            radio.set_mode(MODES[next])?;
+        },
+        KeyCode::Char('v') => {
+            let vfo = if panel.vfo == Some(Vfo::A) { Vfo::B } else { Vfo::A };
+            radio.set_rx_vfo(vfo)?;
+        },
+        KeyCode::Char('t') => {
+            if panel.transmitting {
+                radio.ptt_off()?;
+            } else {
+                radio.ptt_on(PttSource::Microphone)?;
+            }
+        },
+        KeyCode::Char('s') => {
+            panel.selected_meter = (panel.selected_meter + 1) % METERS.len();
+            radio.set_meter(METERS[panel.selected_meter])?;
+        },
+        KeyCode::Char('j') | KeyCode::Char('k') => {
+            let selected = panel.menu_state.selected().unwrap_or(0);
+            let selected = if key == KeyCode::Char('j') {
+                (selected + 1).min(ExMenu::ALL.len() - 1)
+            } else {
+                selected.saturating_sub(1)
+            };
+            panel.menu_state.select(Some(selected));
+        },
+        KeyCode::Char('+') | KeyCode::Char('-') => {
+            let selected = panel.menu_state.selected().unwrap_or(0);
+            let item = ExMenu::ALL[selected];
+            let count = item.values().count();
+            let value = panel.menu[selected].unwrap_or_else(|| item.default_value());
+            let value = if key == KeyCode::Char('+') { (value + 1) % count } else { (value + count - 1) % count };
+            radio.write_menu(item, value)?;
+            panel.menu[selected] = Some(radio.read_menu(item)?);
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, panel: &mut Panel) {
+    let [top, middle, bottom] = Layout::vertical([
+        Constraint::Length(7),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+    let [meters, menu] = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(middle);
+
+    draw_display(frame, panel, top);
+    draw_meters(frame, panel, meters);
+    draw_menu(frame, panel, menu);
+
+    let help = "←/→ tune  [/] step  ↑/↓ band  m mode  v VFO  t TX  s meter  j/k/+/- menu  q quit";
+    let status = if panel.message.is_empty() { help } else { panel.message.as_str() };
+    frame.render_widget(Paragraph::new(status), bottom);
+}
+
+/// The VFO frequencies, mode, filter and TX state.
+fn draw_display(frame: &mut Frame, panel: &Panel, area: Rect) {
+    let vfo_line = |vfo: Vfo, frequency: Option<Frequency>| {
+        let receiving = panel.vfo == Some(vfo);
+        let style = if receiving {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        Line::from(vec![
+            Span::styled(format!("{} {:>14}", vfo_name(vfo), frequency.map(format_frequency).unwrap_or_default()), style),
+            Span::raw(if receiving { "  ◀" } else { "" }),
+        ])
+    };
+
+    let (tx_label, tx_style) = if panel.transmitting {
+        (" TX ", Style::default().fg(Color::White).bg(Color::Red))
+    } else {
+        (" RX ", Style::default().fg(Color::Black).bg(Color::Green))
+    };
+
+    let mut lines = vec![vfo_line(Vfo::A, panel.frequency_a), vfo_line(Vfo::B, panel.frequency_b)];
+    if panel.vfo == Some(Vfo::Memory) {
+        lines.push(Line::from(format!("MEM   {:>14}  ◀", panel.frequency.map(format_frequency).unwrap_or_default())));
+    } else {
+        lines.push(Line::from(""));
+    }
+    lines.push(Line::from(vec![
+        Span::styled(tx_label, tx_style),
+        Span::raw(format!(
+            "  {}  filter {}  {} W  step {} Hz{}",
+            panel.mode.map(|mode| mode.to_string()).unwrap_or_default(),
+            panel.filter_width.map(|width| width.to_string()).unwrap_or_default(),
+            panel.power.map(|power| power.to_string()).unwrap_or_default(),
+            STEPS[panel.step],
+            if panel.split { "  SPLIT" } else { "" },
+        )),
+    ]));
+
+    let block = Block::default().borders(Borders::ALL).title(" TS-480 ");
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Bar graphs of the S-meter and the SWR, COMP and ALC meters.
+fn draw_meters(frame: &mut Frame, panel: &Panel, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title(" Meters ");
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let rows = Layout::vertical([Constraint::Length(1); 4]).split(inner);
+    if panel.transmitting {
+        draw_meter(frame, rows[0], "PWR", panel.smeter, &panel.smeter.to_string(), true);
+    } else {
+        let label = SMeterReading::from_raw(panel.smeter).to_string();
+        draw_meter(frame, rows[0], "S", panel.smeter, &label, false);
+    }
+
+    for (index, &meter) in METERS.iter().enumerate() {
+        let selected = panel.selected_meter == index;
+        let value = panel.meters[index];
+        let label = if selected { format!("{} *", value) } else { value.to_string() };
+        draw_meter(frame, rows[index + 1], &meter.to_string(), value, &label, panel.transmitting && selected);
+    }
+}
+
+fn draw_meter(frame: &mut Frame, area: Rect, name: &str, value: u16, label: &str, active: bool) {
+    let [name_area, gauge_area] = Layout::horizontal([Constraint::Length(5), Constraint::Min(1)]).areas(area);
+    frame.render_widget(Paragraph::new(name.to_owned()), name_area);
+
+    let color = if active { Color::Red } else { Color::Cyan };
+    let gauge = Gauge::default()
+        .gauge_style(Style::default().fg(color))
+        .ratio(f64::from(value.min(METER_MAX)) / f64::from(METER_MAX))
+        .label(label.to_owned());
+    frame.render_widget(gauge, gauge_area);
+}
+
+/// The menu items and their values.
+fn draw_menu(frame: &mut Frame, panel: &mut Panel, area: Rect) {
+    let items: Vec<ListItem> = ExMenu::ALL.iter().zip(&panel.menu)
+        .map(|(item, value)| {
+            let value = value.map(|value| item.values().describe(value)).unwrap_or_else(|| "?".to_owned());
+            ListItem::new(format!("{}: {}", item, value))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(" Menu "))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut panel.menu_state);
+}
+
+fn vfo_name(vfo: Vfo) -> &'static str {
+    match vfo {
+        Vfo::A => "VFO A",
+        Vfo::B => "VFO B",
+        Vfo::Memory => "MEM",
+    }
+}
+
+/// Formats a frequency as on the radio's display, e.g. `14.074.000`
+fn format_frequency(frequency: Frequency) -> String {
+    let hz = frequency.hz();
+    format!("{}.{:03}.{:03}", hz / 1_000_000, hz / 1_000 % 1_000, hz % 1_000)
+}
@@ -0,0 +1,555 @@
+//! Command-line access to the radio, for quick checks and
+//! shell scripts.
+//!
+//! Usage: `ts480 [--port <device>] [--baud <rate>] [--tcp <address>]
+//! [--record <file>] [--json] <command> [<args>]`
+//!
+//! ```text
+//! freq [get [a|b]]           frequency in Hz of the receive VFO, or of VFO A or B
+//! freq set <freq> [a|b]      e.g. `freq set 7.074MHz`
+//! mode [get | set <mode>]    LSB, USB, CW, FM, AM, FSK, CW-R, FSK-R
+//! antenna [get | set <1|2>]
+//! power [get | set <watts>]
+//! status                     decoded `IF;` answer
+//! memory dump [--chirp]      memory channels as CSV, see `ts480::channels`
+//! memory load <file> [--chirp] [--dry-run]
+//! menu get <n>
+//! menu set <n> <value>       value numbered as in the radio's menu
+//! meter [<meter>]            s, power, swr, comp or alc; s by default
+//! meter <meter> <n> [<ms>]   n readings every ms (default 100), then statistics
+//! raw <commands>             e.g. `raw "FA;MD;"`, printing the answers
+//! monitor                    prints Auto Information reports until killed
+//! ```
+//!
+//! The radio is opened on `--port` (default `/dev/ttyUSB0`), or
+//! reached over TCP with `--tcp`, e.g. a `ts480-sim` instance.
+//! With `--json`, results and errors are printed as a JSON object.
+//! With `--record`, the CAT traffic is recorded to a file, see
+//! `ts480::recording`.
+
+extern crate ts480;
+
+use ts480::channels::{self, CsvFormat};
+use ts480::recording::Recorder;
+use ts480::{
+    Antenna, BaudRate, ExMenu, Frequency, MeterKind, MeterSampler, Mode,
+    RadioError, RadioEvent, TS480, TS480Options, Transport, Vfo,
+};
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: ts480 [--port <device>] [--baud <rate>] [--tcp <address>] [--record <file>] [--json] <command> [<args>]
+
+commands:
+    freq [get [a|b]] | freq set <freq> [a|b]
+    mode [get | set <mode>]
+    antenna [get | set <1|2>]
+    power [get | set <watts>]
+    status
+    memory dump [--chirp] | memory load <file> [--chirp] [--dry-run]
+    menu get <n> | menu set <n> <value>
+    meter [s|power|swr|comp|alc] [<readings> [<interval ms>]]
+    raw <commands>
+    monitor";
+
+/// How long `raw` waits for further answers
+const RAW_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Interval between meter readings unless given
+const METER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A result, printed as plain text or JSON.
+enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    List(Vec<Value>),
+    Object(Vec<(&'static str, Value)>),
+}
+
+/// A failed command, with the message to print
+struct Failure(String);
+
+impl From<RadioError> for Failure {
+    fn from(e: RadioError) -> Self {
+        Failure(e.to_string())
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure(e.to_string())
+    }
+}
+
+type CommandResult = Result<Value, Failure>;
+
+/// Command-line flags that take no value
+struct Flags {
+    json: bool,
+    chirp: bool,
+    dry_run: bool,
+}
+
+fn main() {
+    let mut port = String::from("/dev/ttyUSB0");
+    let mut baud = None;
+    let mut tcp = None;
+    let mut record = None;
+    let mut flags = Flags { json: false, chirp: false, dry_run: false };
+    let mut words = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => port = args.next().unwrap_or_else(|| usage()),
+            "--baud" => baud = Some(args.next().and_then(|b| b.parse::<usize>().ok()).unwrap_or_else(|| usage())),
+            "--tcp" => tcp = Some(args.next().unwrap_or_else(|| usage())),
+            "--record" => record = Some(args.next().unwrap_or_else(|| usage())),
+            "--json" => flags.json = true,
+            "--chirp" => flags.chirp = true,
+            "--dry-run" => flags.dry_run = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if arg.starts_with("--") => usage(),
+            _ => words.push(arg),
+        }
+    }
+    if words.is_empty() {
+        usage();
+    }
+
+    let mut options = TS480Options::new();
+    if let Some(baud) = baud {
+        options = options.baud_rate(BaudRate::from_speed(baud));
+    }
+    let result = match tcp {
+        Some(address) => match options.open_tcp(&address) {
+            Ok(stream) => start(&options, stream, record.as_deref(), &words, &flags),
+            Err(e) => Err(Failure(format!("{}: {}", address, e))),
+        },
+        None => match options.open_port(&port) {
+            Ok(serial_port) => start(&options, serial_port, record.as_deref(), &words, &flags),
+            Err(e) => Err(Failure(format!("{}: {}", port, e))),
+        },
+    };
+
+    match result {
+        Ok(value) => print_value(&value, flags.json),
+        Err(Failure(message)) => {
+            if flags.json {
+                println!("{}", json(&Value::Object(vec![("error", Value::Str(message))])));
+            } else {
+                eprintln!("ts480: {}", message);
+            }
+            process::exit(1);
+        },
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Connects over `transport`, recording the traffic to
+/// `record` if given, and runs the command.
+fn start<T: Transport>(options: &TS480Options, transport: T, record: Option<&str>, words: &[String], flags: &Flags) -> CommandResult {
+    match record {
+        Some(path) => {
+            let recorder = Recorder::create(transport, path).map_err(|e| Failure(format!("{}: {}", path, e)))?;
+            run(options.connect(recorder)?, words, flags)
+        },
+        None => run(options.connect(transport)?, words, flags),
+    }
+}
+
+fn run<T: Transport>(mut radio: TS480<T>, words: &[String], flags: &Flags) -> CommandResult {
+    let args: Vec<&str> = words[1..].iter().map(String::as_str).collect();
+    let radio = &mut radio;
+
+    match (words[0].as_str(), args.as_slice()) {
+        ("freq", &[]) | ("freq", &["get"]) => {
+            let frequency = match radio.read_rx_vfo()? {
+                Vfo::A => radio.read_frequency_a()?,
+                Vfo::B => radio.read_frequency_b()?,
+                Vfo::Memory => radio.status()?.frequency,
+            };
+            Ok(frequency_value(frequency))
+        },
+        ("freq", &["get", vfo]) => {
+            let frequency = match parse_vfo(vfo)? {
+                Vfo::A => radio.read_frequency_a()?,
+                _ => radio.read_frequency_b()?,
+            };
+            Ok(frequency_value(frequency))
+        },
+        ("freq", &["set", frequency]) => {
+            let frequency = frequency.parse::<Frequency>()?;
+            match radio.read_rx_vfo()? {
+                Vfo::A => radio.set_frequency_a(frequency)?,
+                Vfo::B => radio.set_frequency_b(frequency)?,
+                Vfo::Memory => return Err(Failure("the radio is in memory mode; give a VFO".to_owned())),
+            }
+            Ok(Value::Null)
+        },
+        ("freq", &["set", frequency, vfo]) => {
+            let frequency = frequency.parse::<Frequency>()?;
+            match parse_vfo(vfo)? {
+                Vfo::A => radio.set_frequency_a(frequency)?,
+                _ => radio.set_frequency_b(frequency)?,
+            }
+            Ok(Value::Null)
+        },
+        ("mode", &[]) | ("mode", &["get"]) => Ok(field("mode", Value::Str(radio.read_mode()?.to_string()))),
+        ("mode", &["set", mode]) => {
+            radio.set_mode(mode.parse::<Mode>()?)?;
+            Ok(Value::Null)
+        },
+        ("antenna", &[]) | ("antenna", &["get"]) => {
+            let antenna = match radio.read_antenna()? {
+                Antenna::Ant1 => 1,
+                Antenna::Ant2 => 2,
+            };
+            Ok(field("antenna", Value::Int(antenna)))
+        },
+        ("antenna", &["set", antenna]) => {
+            radio.set_antenna(antenna.parse::<Antenna>()?)?;
+            Ok(Value::Null)
+        },
+        ("power", &[]) | ("power", &["get"]) => Ok(field("power", Value::Int(i64::from(radio.read_power()?)))),
+        ("power", &["set", watts]) => {
+            let watts = watts.parse::<u8>().map_err(|_| Failure(format!("invalid power {:?}", watts)))?;
+            radio.set_power(watts)?;
+            Ok(Value::Null)
+        },
+        ("status", &[]) => {
+            let status = radio.status()?;
+            Ok(Value::Object(vec![
+                ("frequency", Value::Int(status.frequency.hz() as i64)),
+                ("mode", Value::Str(status.mode.to_string())),
+                ("vfo", Value::Str(vfo_name(status.vfo).to_owned())),
+                ("memory_channel", Value::Int(i64::from(status.memory_channel))),
+                ("transmitting", Value::Bool(status.transmitting)),
+                ("split", Value::Bool(status.split)),
+                ("rit", Value::Bool(status.rit)),
+                ("xit", Value::Bool(status.xit)),
+                ("rit_xit_offset", Value::Int(i64::from(status.rit_xit_offset))),
+                ("scanning", Value::Bool(status.scanning)),
+                ("tone", Value::Str(status.tone.to_string())),
+                ("tone_number", Value::Int(i64::from(status.tone_number))),
+            ]))
+        },
+        ("memory", &["dump"]) => dump_memories(radio, flags),
+        ("memory", &["load", file]) => load_memories(radio, file, flags),
+        ("menu", &["get", number]) => {
+            let item = parse_menu(number)?;
+            let value = radio.read_menu(item)?;
+            Ok(Value::Object(vec![
+                ("value", Value::Int(i64::from(value))),
+                ("meaning", Value::Str(item.values().describe(value))),
+                ("menu", Value::Int(i64::from(item.number()))),
+                ("description", Value::Str(item.description().to_owned())),
+            ]))
+        },
+        ("menu", &["set", number, value]) => {
+            let item = parse_menu(number)?;
+            let value = value.parse::<u16>().map_err(|_| Failure(format!("invalid menu value {:?}", value)))?;
+            radio.write_menu(item, value)?;
+            Ok(Value::Null)
+        },
+        ("meter", &[]) => meter(radio, "s", "1", None, flags.json),
+        ("meter", &[name]) => meter(radio, name, "1", None, flags.json),
+        ("meter", &[name, readings]) => meter(radio, name, readings, None, flags.json),
+        ("meter", &[name, readings, interval]) => meter(radio, name, readings, Some(interval), flags.json),
+        ("raw", commands) if !commands.is_empty() => raw(radio, &commands.join(" ")),
+        ("monitor", &[]) => monitor(radio, flags.json),
+        _ => usage(),
+    }
+}
+
+fn field(name: &'static str, value: Value) -> Value {
+    Value::Object(vec![(name, value)])
+}
+
+fn frequency_value(frequency: Frequency) -> Value {
+    field("frequency", Value::Int(frequency.hz() as i64))
+}
+
+fn parse_vfo(vfo: &str) -> Result<Vfo, Failure> {
+    match vfo.parse::<Vfo>()? {
+        Vfo::Memory => Err(Failure("give VFO a or b".to_owned())),
+        vfo => Ok(vfo),
+    }
+}
+
+fn vfo_name(vfo: Vfo) -> &'static str {
+    match vfo {
+        Vfo::A => "A",
+        Vfo::B => "B",
+        Vfo::Memory => "memory",
+    }
+}
+
+fn parse_menu(number: &str) -> Result<ExMenu, Failure> {
+    number.parse::<u16>().ok()
+        .and_then(ExMenu::from_number)
+        .ok_or_else(|| Failure(format!("invalid menu number {:?}", number)))
+}
+
+fn csv_format(flags: &Flags) -> CsvFormat {
+    if flags.chirp { CsvFormat::Chirp } else { CsvFormat::Native }
+}
+
+/// Prints the memory channels as CSV, or as a JSON list
+/// of the programmed channels.
+fn dump_memories<T: Transport>(radio: &mut TS480<T>, flags: &Flags) -> CommandResult {
+    let memories = radio.dump_memories(|_, _| {})?;
+
+    if !flags.json {
+        let stdout = io::stdout();
+        channels::export(csv_format(flags), &memories, stdout.lock())?;
+        return Ok(Value::Null);
+    }
+
+    let channels = memories.iter().enumerate()
+        .filter_map(|(channel, memory)| memory.as_ref().map(|memory| (channel, memory)))
+        .map(|(channel, memory)| Value::Object(vec![
+            ("channel", Value::Int(channel as i64)),
+            ("rx_frequency", Value::Int(memory.rx_frequency.hz() as i64)),
+            ("tx_frequency", memory.tx_frequency.map(|f| Value::Int(f.hz() as i64)).unwrap_or(Value::Null)),
+            ("mode", Value::Str(memory.mode.to_string())),
+            ("lockout", Value::Bool(memory.lockout)),
+            ("tone", Value::Str(memory.tone.to_string())),
+            ("tone_number", Value::Int(i64::from(memory.tone_number))),
+            ("ctcss_number", Value::Int(i64::from(memory.ctcss_number))),
+            ("fm_narrow", Value::Bool(memory.fm_narrow)),
+            ("name", Value::Str(memory.name.clone())),
+        ]))
+        .collect();
+    Ok(field("channels", Value::List(channels)))
+}
+
+/// Programs the channels listed in a CSV file, printing what changed.
+fn load_memories<T: Transport>(radio: &mut TS480<T>, file: &str, flags: &Flags) -> CommandResult {
+    let text = fs::read_to_string(file).map_err(|e| Failure(format!("{}: {}", file, e)))?;
+    let rows = channels::import(csv_format(flags), &text).map_err(|errors| {
+        let errors: Vec<String> = errors.iter().map(|e| format!("{}: {}", file, e)).collect();
+        Failure(errors.join("\n"))
+    })?;
+
+    let changes = radio.import_memories(&rows, flags.dry_run)?;
+    let changes = changes.iter().map(|change| Value::Str(change.to_string())).collect();
+    Ok(field("changes", Value::List(changes)))
+}
+
+/// Sends raw CAT commands and collects every answer that
+/// arrives before the radio goes quiet.
+fn raw<T: Transport>(radio: &mut TS480<T>, commands: &str) -> CommandResult {
+    let mut commands = commands.trim().to_owned();
+    if !commands.ends_with(';') {
+        commands.push(';');
+    }
+
+    radio.set_timeout(RAW_TIMEOUT);
+    radio.transmit(&commands)?;
+
+    let mut answers = Vec::new();
+    loop {
+        let answer = match radio.receive() {
+            Ok(answer) => answer.to_string(),
+            Err(RadioError::SyntaxOrStatus) => "?;".to_owned(),
+            Err(RadioError::CommError) => "E;".to_owned(),
+            Err(RadioError::ProcIncomplete) => "O;".to_owned(),
+            Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut => break,
+            Err(e) => return Err(e.into()),
+        };
+        answers.push(Value::Str(answer));
+    }
+    Ok(field("answers", Value::List(answers)))
+}
+
+/// Reads a meter once, or samples it and prints each reading
+/// followed by the statistics.
+fn meter<T: Transport>(radio: &mut TS480<T>, name: &str, readings: &str, interval: Option<&str>, json_output: bool) -> CommandResult {
+    let kind = match name.to_ascii_lowercase().as_str() {
+        "s" | "power" => None,
+        _ => Some(name.parse::<MeterKind>()?),
+    };
+    let readings = readings.parse::<u64>().ok().filter(|&n| n > 0)
+        .ok_or_else(|| Failure(format!("invalid number of readings {:?}", readings)))?;
+    let interval = match interval {
+        Some(ms) => Duration::from_millis(ms.parse().map_err(|_| Failure(format!("invalid interval {:?}", ms)))?),
+        None => METER_INTERVAL,
+    };
+
+    if readings == 1 {
+        if name.eq_ignore_ascii_case("s") {
+            let reading = radio.read_smeter()?;
+            return Ok(Value::Object(vec![
+                ("value", Value::Int(i64::from(reading.raw()))),
+                ("reading", Value::Str(reading.to_string())),
+                ("dbm", Value::Float(f64::from(reading.dbm()))),
+            ]));
+        }
+        let value = match kind {
+            Some(kind) => radio.read_meter(kind)?,
+            None => radio.read_smeter()?.raw(),
+        };
+        return Ok(field("value", Value::Int(i64::from(value))));
+    }
+
+    let mut sampler = MeterSampler::new(radio, interval, |radio| match kind {
+        Some(kind) => radio.read_meter(kind),
+        None => radio.read_smeter().map(|reading| reading.raw()),
+    });
+    let start = Instant::now();
+    for _ in 0..readings {
+        let sample = sampler.next().expect("the sampler never ends")?;
+        let value = Value::Object(vec![
+            ("time_ms", Value::Int((sample.time - start).as_millis() as i64)),
+            ("value", Value::Int(i64::from(sample.value))),
+        ]);
+        if json_output {
+            println!("{}", json(&value));
+        } else {
+            println!("{}", text(&value));
+        }
+        io::stdout().flush()?;
+    }
+
+    let statistics = sampler.statistics();
+    let number = |n: Option<u16>| n.map(|n| Value::Int(i64::from(n))).unwrap_or(Value::Null);
+    Ok(Value::Object(vec![
+        ("count", Value::Int(statistics.count() as i64)),
+        ("min", number(statistics.min())),
+        ("max", number(statistics.max())),
+        ("average", statistics.average().map(Value::Float).unwrap_or(Value::Null)),
+    ]))
+}
+
+/// Turns on Auto Information mode and prints each report.
+fn monitor<T: Transport>(radio: &mut TS480<T>, json_output: bool) -> CommandResult {
+    radio.set_auto_information(2)?;
+
+    for event in radio.events() {
+        let value = match event? {
+            RadioEvent::FrequencyChanged { vfo, frequency } => Value::Object(vec![
+                ("event", Value::Str("frequency".to_owned())),
+                ("vfo", Value::Str(vfo_name(vfo).to_owned())),
+                ("frequency", Value::Int(frequency.hz() as i64)),
+            ]),
+            RadioEvent::ModeChanged(mode) => Value::Object(vec![
+                ("event", Value::Str("mode".to_owned())),
+                ("mode", Value::Str(mode.to_string())),
+            ]),
+            RadioEvent::TransmitChanged(transmitting) => Value::Object(vec![
+                ("event", Value::Str("transmit".to_owned())),
+                ("transmitting", Value::Bool(transmitting)),
+            ]),
+            RadioEvent::Other(answer) => Value::Object(vec![
+                ("event", Value::Str("other".to_owned())),
+                ("answer", Value::Str(answer)),
+            ]),
+        };
+
+        if json_output {
+            println!("{}", json(&value));
+        } else {
+            println!("{}", text(&value));
+        }
+        io::stdout().flush()?;
+    }
+
+    Ok(Value::Null)
+}
+
+/// Prints a result: JSON on one line, or in text one field per
+/// line, with just the value if there is only one field.
+fn print_value(value: &Value, json_output: bool) {
+    if json_output {
+        match *value {
+            Value::Null => println!("{{}}"),
+            ref value => println!("{}", json(value)),
+        }
+        return;
+    }
+
+    match *value {
+        Value::Null => {},
+        Value::Object(ref fields) if fields.len() == 1 => print_lines(&fields[0].1),
+        Value::Object(ref fields) => {
+            for &(name, ref value) in fields {
+                println!("{}: {}", name, text(value));
+            }
+        },
+        ref value => print_lines(value),
+    }
+}
+
+/// Prints a list one element per line, or any other value on its own.
+fn print_lines(value: &Value) {
+    match *value {
+        Value::List(ref values) => {
+            for value in values {
+                println!("{}", text(value));
+            }
+        },
+        ref value => println!("{}", text(value)),
+    }
+}
+
+fn text(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(n) => n.to_string(),
+        Value::Float(x) => format!("{:.1}", x),
+        Value::Str(ref s) => s.clone(),
+        Value::List(ref values) => values.iter().map(text).collect::<Vec<_>>().join(", "),
+        Value::Object(ref fields) => {
+            fields.iter().map(|&(name, ref value)| format!("{}={}", name, text(value))).collect::<Vec<_>>().join(" ")
+        },
+    }
+}
+
+fn json(value: &Value) -> String {
+    match *value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(n) => n.to_string(),
+        Value::Float(x) if x.is_finite() => format!("{:.1}", x),
+        Value::Float(_) => "null".to_owned(),
+        Value::Str(ref s) => json_string(s),
+        Value::List(ref values) => format!("[{}]", values.iter().map(json).collect::<Vec<_>>().join(",")),
+        Value::Object(ref fields) => {
+            let fields: Vec<String> = fields.iter()
+                .map(|&(name, ref value)| format!("{}:{}", json_string(name), json(value)))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        },
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
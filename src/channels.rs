@@ -0,0 +1,657 @@
+//! Memory channel lists as CSV, for sharing channels between radios.
+//!
+//! Two layouts are supported. `CsvFormat::Native` has one row per
+//! programmed channel with these columns, in any order:
+//!
+//! | Column          | Contents                                  |
+//! |-----------------|-------------------------------------------|
+//! | Channel         | 0 - 109; P0 - P9 are 100 - 109            |
+//! | RX Frequency    | Hz, or with a unit, e.g. `29.62 MHz`      |
+//! | TX Frequency    | As above; empty for simplex               |
+//! | Mode            | LSB, USB, CW, FM, AM, FSK, CW-R or FSK-R  |
+//! | Lockout         | 0 or 1                                    |
+//! | Tone            | Off, Tone or CTCSS                        |
+//! | Tone Number     | Tone frequency number, 0 - 41             |
+//! | CTCSS Number    | CTCSS frequency number, 0 - 41            |
+//! | FM Narrow       | 0 or 1                                    |
+//! | Name            | Up to 8 characters                        |
+//!
+//! `CsvFormat::Chirp` is CHIRP's generic CSV format. Its `Location`
+//! column is used as the channel number. DTCS, cross tone modes and
+//! modes the TS-480 lacks are rejected.
+//!
+//! ```no_run
+//! use ts480::TS480;
+//! use ts480::channels::{self, CsvFormat};
+//! use std::fs;
+//!
+//! let mut radio = TS480::new("/dev/ttyUSB0").unwrap();
+//! let text = fs::read_to_string("club.csv").unwrap();
+//!
+//! match channels::import(CsvFormat::Chirp, &text) {
+//!     Ok(rows) => {
+//!         for change in radio.import_memories(&rows, true).unwrap() {
+//!             println!("{}", change);
+//!         }
+//!     },
+//!     Err(errors) => for error in errors {
+//!         eprintln!("{}", error);
+//!     },
+//! }
+//! ```
+
+use crate::memory::{MemoryChannel, MEMORY_CHANNELS};
+use crate::transport::Transport;
+use crate::types::{Frequency, Mode, ToneMode, TONE_FREQUENCIES};
+use crate::{RadioResult, TS480};
+
+use std::fmt;
+use std::io::{self, Write};
+
+/// CSV layout of a channel list
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsvFormat {
+    /// This crate's layout, described in the module documentation
+    Native,
+
+    /// CHIRP's generic CSV format
+    Chirp,
+}
+
+const NATIVE_COLUMNS: &[&str] = &[
+    "Channel", "RX Frequency", "TX Frequency", "Mode", "Lockout",
+    "Tone", "Tone Number", "CTCSS Number", "FM Narrow", "Name",
+];
+
+const CHIRP_COLUMNS: &[&str] = &[
+    "Location", "Name", "Frequency", "Duplex", "Offset", "Tone",
+    "rToneFreq", "cToneFreq", "DtcsCode", "DtcsPolarity", "Mode",
+    "TStep", "Skip", "Comment", "URCALL", "RPT1CALL", "RPT2CALL", "DVCODE",
+];
+
+/// A row of an imported file that cannot be stored in the radio.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RowError {
+    /// Line number in the file, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// How importing a channel list changes a memory channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChannelChange {
+    /// An unprogrammed channel is programmed.
+    Added { channel: u8, new: MemoryChannel },
+
+    /// A programmed channel is overwritten.
+    Changed { channel: u8, old: MemoryChannel, new: MemoryChannel },
+}
+
+impl fmt::Display for ChannelChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChannelChange::Added { channel, ref new } => {
+                write!(f, "+ {:03}: {}", channel, new)
+            },
+            ChannelChange::Changed { channel, ref old, ref new } => {
+                write!(f, "- {:03}: {}\n+ {:03}: {}", channel, old, channel, new)
+            },
+        }
+    }
+}
+
+/// Writes the programmed channels of `memories`, in which
+/// element `n` is channel `n` as returned by `TS480::dump_memories`.
+pub fn export<W: Write>(format: CsvFormat, memories: &[Option<MemoryChannel>], mut writer: W) -> io::Result<()> {
+    let columns = match format {
+        CsvFormat::Native => NATIVE_COLUMNS,
+        CsvFormat::Chirp => CHIRP_COLUMNS,
+    };
+    writeln!(writer, "{}", columns.join(","))?;
+
+    for (channel, memory) in memories.iter().enumerate() {
+        let memory = match *memory {
+            Some(ref memory) => memory,
+            None => continue,
+        };
+        let fields = match format {
+            CsvFormat::Native => native_row(channel, memory),
+            CsvFormat::Chirp => chirp_row(channel, memory),
+        };
+        let fields: Vec<String> = fields.iter().map(|field| quote(field)).collect();
+        writeln!(writer, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Parses a channel list, checking every row against the limits
+/// of the radio. Returns the channels with their numbers, or an
+/// error for each row that is invalid.
+pub fn import(format: CsvFormat, text: &str) -> Result<Vec<(u8, MemoryChannel)>, Vec<RowError>> {
+    let mut lines = text.lines().enumerate().filter(|&(_, line)| !line.trim().is_empty());
+
+    let (header_line, header) = match lines.next() {
+        Some((index, header)) => (index + 1, split(header)),
+        None => return Ok(Vec::new()),
+    };
+    let (required, parse_row): (&[&str], ParseRow) = match format {
+        CsvFormat::Native => (&NATIVE_COLUMNS[..2], native_channel),
+        CsvFormat::Chirp => (&CHIRP_COLUMNS[..3], chirp_channel),
+    };
+    let missing: Vec<RowError> = required.iter()
+        .filter(|column| !header.iter().any(|name| name.eq_ignore_ascii_case(column)))
+        .map(|column| RowError { line: header_line, message: format!("missing column {:?}", column) })
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+
+    let mut channels: Vec<(u8, MemoryChannel)> = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in lines {
+        let row = Row { header: &header, fields: split(line) };
+        let result = parse_row(&row).and_then(|(channel, memory)| {
+            memory.validate().map_err(|e| e.to_string())?;
+            if channels.iter().any(|&(other, _)| other == channel) {
+                return Err(format!("channel {} appears more than once", channel));
+            }
+            Ok((channel, memory))
+        });
+        match result {
+            Ok(channel) => channels.push(channel),
+            Err(message) => errors.push(RowError { line: index + 1, message }),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(channels)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Compares imported channels against the contents of the radio,
+/// in which element `n` is channel `n`. Channels that would not
+/// change are left out.
+pub fn diff(current: &[Option<MemoryChannel>], imported: &[(u8, MemoryChannel)]) -> Vec<ChannelChange> {
+    imported.iter()
+        .filter_map(|&(channel, ref new)| {
+            match current.get(channel as usize).and_then(|memory| memory.as_ref()) {
+                None => Some(ChannelChange::Added { channel, new: new.clone() }),
+                Some(old) if old != new => Some(ChannelChange::Changed { channel, old: old.clone(), new: new.clone() }),
+                Some(_) => None,
+            }
+        })
+        .collect()
+}
+
+impl<T: Transport> TS480<T> {
+    /// Programs imported channels, writing only those that
+    /// differ from what the radio holds. With `dry_run`, nothing
+    /// is written. Returns the changes made, or that would be made.
+    pub fn import_memories(&mut self, channels: &[(u8, MemoryChannel)], dry_run: bool) -> RadioResult<Vec<ChannelChange>> {
+        let mut current = vec![None; MEMORY_CHANNELS as usize];
+        for &(channel, _) in channels {
+            if let Some(memory) = current.get_mut(channel as usize) {
+                *memory = self.read_memory(channel)?;
+            }
+        }
+
+        let changes = diff(&current, channels);
+        if !dry_run {
+            for change in &changes {
+                match *change {
+                    ChannelChange::Added { channel, ref new } | ChannelChange::Changed { channel, ref new, .. } => {
+                        self.write_memory(channel, new)?;
+                    },
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Turns a data row into a channel number and its contents
+type ParseRow = fn(&Row) -> Result<(u8, MemoryChannel), String>;
+
+/// The fields of a data row, looked up by column name
+struct Row<'a> {
+    header: &'a [String],
+    fields: Vec<String>,
+}
+
+impl<'a> Row<'a> {
+    /// Returns the trimmed field in `column`, or an empty string
+    /// if the file has no such column.
+    fn get(&self, column: &str) -> &str {
+        self.header.iter()
+            .position(|name| name.eq_ignore_ascii_case(column))
+            .and_then(|index| self.fields.get(index))
+            .map(|field| field.trim())
+            .unwrap_or("")
+    }
+}
+
+fn native_row(channel: usize, memory: &MemoryChannel) -> Vec<String> {
+    vec![
+        channel.to_string(),
+        memory.rx_frequency.hz().to_string(),
+        memory.tx_frequency.map(|f| f.hz().to_string()).unwrap_or_default(),
+        memory.mode.to_string(),
+        (memory.lockout as u8).to_string(),
+        memory.tone.to_string(),
+        memory.tone_number.to_string(),
+        memory.ctcss_number.to_string(),
+        (memory.fm_narrow as u8).to_string(),
+        memory.name.clone(),
+    ]
+}
+
+fn native_channel(row: &Row) -> Result<(u8, MemoryChannel), String> {
+    let channel = parse_channel(row.get("Channel"))?;
+    let rx_frequency = row.get("RX Frequency").parse::<Frequency>().map_err(|e| e.to_string())?;
+    let tx_frequency = match row.get("TX Frequency") {
+        "" => None,
+        tx => Some(tx.parse::<Frequency>().map_err(|e| e.to_string())?),
+    };
+
+    let memory = MemoryChannel {
+        rx_frequency,
+        tx_frequency: tx_frequency.filter(|&tx| tx != rx_frequency),
+        mode: row.get("Mode").parse::<Mode>().map_err(|e| e.to_string())?,
+        lockout: parse_bool("Lockout", row.get("Lockout"))?,
+        tone: row.get("Tone").parse::<ToneMode>().map_err(|e| e.to_string())?,
+        tone_number: parse_number("Tone Number", row.get("Tone Number"))?,
+        ctcss_number: parse_number("CTCSS Number", row.get("CTCSS Number"))?,
+        fm_narrow: parse_bool("FM Narrow", row.get("FM Narrow"))?,
+        name: row.get("Name").to_owned(),
+    };
+    Ok((channel, memory))
+}
+
+fn chirp_row(channel: usize, memory: &MemoryChannel) -> Vec<String> {
+    let rx = memory.rx_frequency.hz();
+    let (duplex, offset) = match memory.tx_frequency.map(|f| f.hz()) {
+        None => ("", 0),
+        Some(tx) if tx > rx => ("+", tx - rx),
+        Some(tx) => ("-", rx - tx),
+    };
+    let tone = match memory.tone {
+        ToneMode::Off => "",
+        ToneMode::Tone => "Tone",
+        ToneMode::Ctcss => "TSQL",
+    };
+    let mode = match (memory.mode, memory.fm_narrow) {
+        (Mode::Fm, true) => "NFM",
+        (Mode::Fm, false) => "FM",
+        (Mode::Am, _) => "AM",
+        (Mode::Usb, _) => "USB",
+        (Mode::Lsb, _) => "LSB",
+        (Mode::Cw, _) => "CW",
+        (Mode::CwReverse, _) => "CWR",
+        (Mode::Fsk, _) => "RTTY",
+        (Mode::FskReverse, _) => "RTTYR",
+    };
+
+    vec![
+        channel.to_string(),
+        memory.name.clone(),
+        format_mhz(rx),
+        duplex.to_owned(),
+        format_mhz(offset),
+        tone.to_owned(),
+        format_tone(memory.tone_number),
+        format_tone(memory.ctcss_number),
+        "023".to_owned(),
+        "NN".to_owned(),
+        mode.to_owned(),
+        "5.00".to_owned(),
+        if memory.lockout { "S" } else { "" }.to_owned(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+    ]
+}
+
+fn chirp_channel(row: &Row) -> Result<(u8, MemoryChannel), String> {
+    let channel = parse_channel(row.get("Location"))?;
+    let rx = parse_mhz("Frequency", row.get("Frequency"))?;
+    let rx_frequency = Frequency::from_hz(rx).map_err(|e| e.to_string())?;
+
+    let tx = match row.get("Duplex") {
+        "" => None,
+        "+" => Some(rx.checked_add(parse_mhz("Offset", row.get("Offset"))?).ok_or_else(|| format!("invalid Offset {:?}", row.get("Offset")))?),
+        "-" => Some(rx.checked_sub(parse_mhz("Offset", row.get("Offset"))?).ok_or("offset is larger than the frequency")?),
+        "split" => Some(parse_mhz("Offset", row.get("Offset"))?),
+        "off" => return Err("transmit inhibit is not supported".to_owned()),
+        duplex => return Err(format!("invalid Duplex {:?}", duplex)),
+    };
+    let tx_frequency = match tx {
+        Some(tx) if tx != rx => Some(Frequency::from_hz(tx).map_err(|e| format!("TX {}", e))?),
+        _ => None,
+    };
+
+    let tone = match row.get("Tone") {
+        "" => ToneMode::Off,
+        "Tone" => ToneMode::Tone,
+        "TSQL" => ToneMode::Ctcss,
+        tone => return Err(format!("tone mode {:?} is not supported", tone)),
+    };
+    let tone_number = match parse_tone("rToneFreq", row.get("rToneFreq")) {
+        Err(e) if tone == ToneMode::Tone => return Err(e),
+        result => result.unwrap_or(0),
+    };
+    let ctcss_number = match parse_tone("cToneFreq", row.get("cToneFreq")) {
+        Err(e) if tone == ToneMode::Ctcss => return Err(e),
+        result => result.unwrap_or(0),
+    };
+
+    let (mode, fm_narrow) = match row.get("Mode") {
+        "FM" => (Mode::Fm, false),
+        "NFM" => (Mode::Fm, true),
+        "AM" => (Mode::Am, false),
+        "USB" => (Mode::Usb, false),
+        "LSB" => (Mode::Lsb, false),
+        "CW" => (Mode::Cw, false),
+        "CWR" => (Mode::CwReverse, false),
+        "RTTY" => (Mode::Fsk, false),
+        "RTTYR" => (Mode::FskReverse, false),
+        mode => return Err(format!("mode {:?} is not supported", mode)),
+    };
+
+    let memory = MemoryChannel {
+        rx_frequency,
+        tx_frequency,
+        mode,
+        lockout: !row.get("Skip").is_empty(),
+        tone,
+        tone_number,
+        ctcss_number,
+        fm_narrow,
+        name: row.get("Name").to_owned(),
+    };
+    Ok((channel, memory))
+}
+
+/// Parses a channel number, 0 - 109 or P0 - P9.
+fn parse_channel(field: &str) -> Result<u8, String> {
+    let channel = match field.strip_prefix('P').or_else(|| field.strip_prefix('p')) {
+        Some(program) => program.parse::<u8>().ok().filter(|&n| n < 10).map(|n| 100 + n),
+        None => field.parse::<u8>().ok(),
+    };
+    channel
+        .filter(|&channel| channel < MEMORY_CHANNELS)
+        .ok_or_else(|| format!("channel must be 0 - {}, got {:?}", MEMORY_CHANNELS - 1, field))
+}
+
+fn parse_bool(column: &str, field: &str) -> Result<bool, String> {
+    match field {
+        "" | "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(format!("{} must be 0 or 1, got {:?}", column, field)),
+    }
+}
+
+fn parse_number(column: &str, field: &str) -> Result<u8, String> {
+    if field.is_empty() {
+        return Ok(0);
+    }
+    field.parse().map_err(|_| format!("invalid {} {:?}", column, field))
+}
+
+/// Parses a value in MHz, as used by CHIRP, into Hz.
+fn parse_mhz(column: &str, field: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid {} {:?}", column, field);
+
+    let (whole, fraction) = match field.find('.') {
+        Some(dot) => (&field[..dot], &field[dot + 1..]),
+        None => (field, ""),
+    };
+    if whole.is_empty() || fraction.len() > 6 || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let whole: u64 = whole.parse().map_err(|_| invalid())?;
+    let fraction: u64 = format!("{:0<6}", fraction).parse().map_err(|_| invalid())?;
+    whole.checked_mul(1_000_000).and_then(|hz| hz.checked_add(fraction)).ok_or_else(invalid)
+}
+
+fn format_mhz(hz: u64) -> String {
+    format!("{}.{:06}", hz / 1_000_000, hz % 1_000_000)
+}
+
+/// Looks up the frequency number of a tone given in Hz, e.g. `88.5`.
+fn parse_tone(column: &str, field: &str) -> Result<u8, String> {
+    let tenths = field.parse::<f64>().ok().map(|hz| (hz * 10.0).round() as u16);
+    tenths
+        .and_then(|tenths| TONE_FREQUENCIES.iter().position(|&f| f == tenths))
+        .map(|number| number as u8)
+        .ok_or_else(|| format!("{} {:?} is not a TS-480 tone frequency", column, field))
+}
+
+fn format_tone(number: u8) -> String {
+    let tenths = TONE_FREQUENCIES.get(number as usize).cloned().unwrap_or(TONE_FREQUENCIES[0]);
+    format!("{}.{}", tenths / 10, tenths % 10)
+}
+
+/// Splits a CSV line into fields, removing quotes.
+fn split(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(field.split_off(0)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Quotes a CSV field if it contains a separator or quote.
+fn quote(field: &str) -> String {
+    if field.contains(',') || field.contains('"') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Simulator;
+
+    fn mhz(mhz: f64) -> Frequency {
+        Frequency::from_hz((mhz * 1_000_000.0).round() as u64).unwrap()
+    }
+
+    /// A repeater channel, a simplex channel and a program channel.
+    fn memories() -> Vec<Option<MemoryChannel>> {
+        let mut repeater = MemoryChannel::new(mhz(29.62), Mode::Fm);
+        repeater.tx_frequency = Some(mhz(29.52));
+        repeater.tone = ToneMode::Tone;
+        repeater.tone_number = 8;
+        repeater.name = "RPT, \"A\"".to_owned();
+
+        let mut simplex = MemoryChannel::new(mhz(7.074), Mode::Usb);
+        simplex.lockout = true;
+        simplex.name = "FT8".to_owned();
+
+        let mut program = MemoryChannel::new(mhz(51.0), Mode::Fm);
+        program.tone = ToneMode::Ctcss;
+        program.ctcss_number = 12;
+        program.fm_narrow = true;
+
+        let mut memories = vec![None; MEMORY_CHANNELS as usize];
+        memories[3] = Some(repeater);
+        memories[40] = Some(simplex);
+        memories[105] = Some(program);
+        memories
+    }
+
+    fn exported(format: CsvFormat, memories: &[Option<MemoryChannel>]) -> String {
+        let mut text = Vec::new();
+        export(format, memories, &mut text).unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    fn programmed(memories: &[Option<MemoryChannel>]) -> Vec<(u8, MemoryChannel)> {
+        memories.iter().enumerate()
+            .filter_map(|(channel, memory)| memory.clone().map(|memory| (channel as u8, memory)))
+            .collect()
+    }
+
+    fn errors(format: CsvFormat, text: &str) -> Vec<RowError> {
+        import(format, text).unwrap_err()
+    }
+
+    #[test]
+    fn native_round_trip() {
+        let text = exported(CsvFormat::Native, &memories());
+        assert!(text.starts_with("Channel,RX Frequency,TX Frequency,Mode,"), "{}", text);
+        assert!(text.contains(",\"RPT, \"\"A\"\"\"\n"), "{}", text);
+        assert_eq!(import(CsvFormat::Native, &text).unwrap(), programmed(&memories()));
+    }
+
+    #[test]
+    fn chirp_round_trip() {
+        let text = exported(CsvFormat::Chirp, &memories());
+        assert!(text.contains("3,\"RPT, \"\"A\"\"\",29.620000,-,0.100000,Tone,88.5,"), "{}", text);
+        assert!(text.contains("105,,51.000000,,0.000000,TSQL,67.0,100.0,023,NN,NFM,"), "{}", text);
+        assert_eq!(import(CsvFormat::Chirp, &text).unwrap(), programmed(&memories()));
+    }
+
+    #[test]
+    fn splits_quoted_fields() {
+        assert_eq!(split("1,\"a, b\",\"say \"\"hi\"\"\",,x"), ["1", "a, b", "say \"hi\"", "", "x"]);
+        assert_eq!(quote("a, b"), "\"a, b\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote("plain"), "plain");
+    }
+
+    #[test]
+    fn native_columns_in_any_order() {
+        let text = "Name,Mode,RX Frequency,Channel\n\"A,B\",cw,7.01 MHz,P3\n";
+        let mut memory = MemoryChannel::new(mhz(7.01), Mode::Cw);
+        memory.name = "A,B".to_owned();
+        assert_eq!(import(CsvFormat::Native, text).unwrap(), vec![(103, memory)]);
+    }
+
+    #[test]
+    fn reports_every_bad_row_with_its_line() {
+        let text = "Channel,RX Frequency,Mode,Tone,Tone Number,Name\n\
+                    1,7.074 MHz,USB,,,\n\
+                    \n\
+                    2,61 MHz,USB,,,\n\
+                    3,14.074 MHz,USB,Tone,42,\n\
+                    4,14.074 MHz,USB,,,NINECHARS\n\
+                    5,14.074 MHz,USB,,,EIGHTCHR\n\
+                    1,3.5 MHz,CW,,,\n\
+                    110,3.5 MHz,CW,,,\n";
+        let errors = errors(CsvFormat::Native, text);
+        let lines: Vec<usize> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [4, 5, 6, 8, 9], "{:?}", errors);
+
+        assert!(errors[0].message.contains("60000000 Hz"), "{}", errors[0]);
+        assert!(errors[1].message.contains("tone frequency number"), "{}", errors[1]);
+        assert!(errors[2].message.contains("at most 8 characters"), "{}", errors[2]);
+        assert_eq!(errors[3].message, "channel 1 appears more than once");
+        assert_eq!(errors[4].to_string(), "line 9: channel must be 0 - 109, got \"110\"");
+    }
+
+    #[test]
+    fn reports_missing_columns_on_header_line() {
+        assert_eq!(errors(CsvFormat::Chirp, "\n\nLocation,Name,Duplex\n1,A,\n"), vec![RowError { line: 3, message: "missing column \"Frequency\"".to_owned() }]);
+
+        assert_eq!(errors(CsvFormat::Native, "Channel,Name\n")[0].to_string(), "line 1: missing column \"RX Frequency\"");
+        assert_eq!(import(CsvFormat::Native, "\n  \n"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn chirp_offsets() {
+        let tx_frequency = |duplex: &str, offset: &str| {
+            let text = format!("Location,Name,Frequency,Duplex,Offset,Mode\n1,,29.620000,{},{},FM\n", duplex, offset);
+            import(CsvFormat::Chirp, &text)
+                .map(|rows| rows[0].1.tx_frequency)
+                .map_err(|errors| errors[0].message.clone())
+        };
+
+        assert_eq!(tx_frequency("", "0.100000"), Ok(None));
+        assert_eq!(tx_frequency("+", "0.1"), Ok(Some(mhz(29.72))));
+        assert_eq!(tx_frequency("-", "0.100000"), Ok(Some(mhz(29.52))));
+        assert_eq!(tx_frequency("split", "28.500000"), Ok(Some(mhz(28.5))));
+        assert_eq!(tx_frequency("split", "29.620000"), Ok(None));
+
+        assert_eq!(tx_frequency("-", "30.000000"), Err("offset is larger than the frequency".to_owned()));
+        assert!(tx_frequency("+", "31.000000").unwrap_err().starts_with("TX "));
+        assert_eq!(tx_frequency("+", "0.1.2"), Err("invalid Offset \"0.1.2\"".to_owned()));
+        assert_eq!(tx_frequency("+", "99999999999999.0"), Err("invalid Offset \"99999999999999.0\"".to_owned()));
+        assert_eq!(tx_frequency("off", ""), Err("transmit inhibit is not supported".to_owned()));
+        assert_eq!(tx_frequency("DV", ""), Err("invalid Duplex \"DV\"".to_owned()));
+    }
+
+    #[test]
+    fn chirp_tones_and_modes() {
+        let row = |tone: &str, mode: &str| {
+            let text = format!(
+                "Location,Name,Frequency,Tone,rToneFreq,cToneFreq,Mode\n1,,29.620000,{},88.5,110.0,{}\n", tone, mode,
+            );
+            import(CsvFormat::Chirp, &text).map(|rows| rows[0].1.clone()).map_err(|errors| errors[0].message.clone())
+        };
+
+        assert_eq!(row("", "FM").unwrap().tone, ToneMode::Off);
+        // A bad tone frequency only matters when the tone is used
+        let memory = row("Tone", "FM").unwrap();
+        assert_eq!((memory.tone, memory.tone_number, memory.ctcss_number), (ToneMode::Tone, 8, 0));
+        assert_eq!(row("TSQL", "FM"), Err("cToneFreq \"110.0\" is not a TS-480 tone frequency".to_owned()));
+        assert_eq!(row("DTCS", "FM"), Err("tone mode \"DTCS\" is not supported".to_owned()));
+        assert_eq!(row("", "DV"), Err("mode \"DV\" is not supported".to_owned()));
+        assert!(row("", "NFM").unwrap().fm_narrow);
+    }
+
+    #[test]
+    fn dry_run_reports_changes_without_writing() {
+        let mut radio = TS480::with_transport(Simulator::new());
+        let old = MemoryChannel::new(mhz(7.074), Mode::Usb);
+        radio.write_memory(40, &old).unwrap();
+        let unchanged = MemoryChannel::new(mhz(3.5), Mode::Cw);
+        radio.write_memory(41, &unchanged).unwrap();
+
+        let imported = programmed(&memories());
+        let mut rows = imported.clone();
+        rows.push((41, unchanged.clone()));
+        let new = imported[1].1.clone();
+
+        let changes = radio.import_memories(&rows, true).unwrap();
+        assert_eq!(changes, vec![
+            ChannelChange::Added { channel: 3, new: imported[0].1.clone() },
+            ChannelChange::Changed { channel: 40, old: old.clone(), new: new.clone() },
+            ChannelChange::Added { channel: 105, new: imported[2].1.clone() },
+        ]);
+        assert_eq!(radio.read_memory(3).unwrap(), None);
+        assert_eq!(radio.read_memory(40).unwrap(), Some(old));
+
+        assert_eq!(radio.import_memories(&rows, false).unwrap(), changes);
+        assert_eq!(radio.read_memory(3).unwrap(), Some(imported[0].1.clone()));
+        assert_eq!(radio.read_memory(40).unwrap(), Some(new));
+        assert_eq!(radio.read_memory(41).unwrap(), Some(unchanged));
+        assert_eq!(radio.import_memories(&rows, true).unwrap(), Vec::new());
+    }
+}
@@ -0,0 +1,810 @@
+//! The TS-480 PC control commands, in the order they appear
+//! in the command reference.
+
+use crate::{RadioError, RadioResult, Transport, TS480};
+use crate::menu::LAST_MENU;
+use crate::types::{Antenna, Frequency, Mode, Vfo};
+
+use std::fmt::Display;
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::Instant;
+
+impl<T: Transport> TS480<T> {
+    /// Sets the internal antenna tuner status.
+    ///
+    /// p1: 0 = RX-AT THRU; 1 = RX-AT IN
+    ///
+    /// p2: 0 = TX-AT THRU; 1 = TX-AT IN
+    ///
+    /// p3: 0 = Stop tuning; 1 = Start tuning
+    pub fn set_tuner_status(&mut self, p1: u8, p2: u8, p3: u8) -> RadioResult<()> {
+        check_range("RX-AT status", p1, 0, 1)?;
+        check_range("TX-AT status", p2, 0, 1)?;
+        check_range("tuning status", p3, 0, 1)?;
+        self.transmit(&format!("AC{}{}{};", p1, p2, p3))
+    }
+
+    /// Reads the internal antenna tuner status.
+    ///
+    /// Returns (p1, p2, p3) where
+    ///
+    /// p1: 0 = RX-AT THRU; 1 = RX-AT IN
+    ///
+    /// p2: 0 = TX-AT THRU; 1 = TX-AT IN
+    ///
+    /// p3: 0 = Not tuning; 1 = Tuning
+    pub fn read_tuner_status(&mut self) -> RadioResult<(u8, u8, u8)> {
+        let params = self.read_parameters("AC")?;
+        Ok((
+            parse_field(&params, 0..1)?,
+            parse_field(&params, 1..2)?,
+            parse_field(&params, 2..3)?,
+        ))
+    }
+
+    /// Sets the AF gain, 0 - 255
+    pub fn set_af_gain(&mut self, gain: u8) -> RadioResult<()> {
+        self.transmit(&format!("AG0{:03};", gain))
+    }
+
+    /// Reads the AF gain, 0 - 255
+    pub fn read_af_gain(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("AG0")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Sets the Auto Information function status.
+    ///
+    /// p1: 0 = OFF; 2 = ON; 4 = ON (with backup)
+    pub fn set_auto_information(&mut self, p1: u8) -> RadioResult<()> {
+        if p1 != 0 && p1 != 2 && p1 != 4 {
+            return Err(RadioError::InvalidParameter(
+                format!("auto information must be 0, 2 or 4, got {}", p1)
+            ));
+        }
+        self.transmit(&format!("AI{};", p1))?;
+        self.auto_information = p1 != 0;
+        Ok(())
+    }
+
+    /// Reads the Auto Information function status.
+    ///
+    /// 0 = OFF; 2 = ON; 4 = ON (with backup)
+    pub fn read_auto_information(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("AI")?;
+        let p1 = parse_field(&params, 0..1)?;
+        self.auto_information = p1 != 0;
+        Ok(p1)
+    }
+
+    /// Selects the antenna connector ANT1/ANT2
+    pub fn set_antenna(&mut self, antenna: Antenna) -> RadioResult<()> {
+        self.transmit(&format!("AN{};", antenna.to_cat()))
+    }
+
+    /// Reads the selected antenna connector ANT1/ANT2
+    pub fn read_antenna(&mut self) -> RadioResult<Antenna> {
+        let params = self.read_parameters("AN")?;
+        Antenna::from_cat(&params)
+    }
+
+    /// Sets the Beat Cancel function status.
+    ///
+    /// p1: 0 = OFF; 1 = BC1; 2 = BC2
+    pub fn set_beat_cancel(&mut self, p1: u8) -> RadioResult<()> {
+        check_range("beat cancel", p1, 0, 2)?;
+        self.transmit(&format!("BC{};", p1))
+    }
+
+    /// Reads the Beat Cancel function status.
+    ///
+    /// 0 = OFF; 1 = BC1; 2 = BC2
+    pub fn read_beat_cancel(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("BC")?;
+        parse_field(&params, 0..1)
+    }
+
+    /// Moves down the frequency band
+    pub fn frequency_down(&mut self) -> RadioResult<()> {
+        self.transmit("BD;")
+    }
+
+    /// Moves up the frequency band
+    pub fn frequency_up(&mut self) -> RadioResult<()> {
+        self.transmit("BU;")
+    }
+
+    /// Reads whether the squelch is open (busy).
+    pub fn read_busy(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("BY")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Starts or stops the CW Auto Zero-beat function.
+    pub fn set_cw_auto_zero_beat(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("CA{};", on as u8))
+    }
+
+    /// Reads whether the CW Auto Zero-beat function is running.
+    pub fn read_cw_auto_zero_beat(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("CA")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Turns the MULTI/CH encoder one step.
+    ///
+    /// p1: 0 = Up; 1 = Down
+    pub fn multi_channel_step(&mut self, p1: u8) -> RadioResult<()> {
+        check_range("MULTI/CH direction", p1, 0, 1)?;
+        self.transmit(&format!("CH{};", p1))
+    }
+
+    /// Sets the CTCSS frequency number, 0 - 41
+    pub fn set_ctcss_frequency(&mut self, number: u8) -> RadioResult<()> {
+        check_range("CTCSS frequency number", number, 0, 41)?;
+        self.transmit(&format!("CN{:02};", number))
+    }
+
+    /// Reads the CTCSS frequency number, 0 - 41
+    pub fn read_ctcss_frequency(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("CN")?;
+        parse_field(&params, 0..2)
+    }
+
+    /// Turns the CTCSS function on or off.
+    pub fn set_ctcss(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("CT{};", on as u8))
+    }
+
+    /// Reads whether the CTCSS function is on.
+    pub fn read_ctcss(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("CT")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Emulates the microphone DWN key
+    pub fn microphone_down(&mut self) -> RadioResult<()> {
+        self.transmit("DN;")
+    }
+
+    /// Emulates the microphone UP key
+    pub fn microphone_up(&mut self) -> RadioResult<()> {
+        self.transmit("UP;")
+    }
+
+    /// Sets menu item `number`, 0 - 62, to the raw `value`
+    /// as it appears in the `EX` command. See `write_menu`
+    /// for typed access.
+    pub fn set_extended_menu(&mut self, number: u16, value: &str) -> RadioResult<()> {
+        check_range("menu number", number, 0, LAST_MENU)?;
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_graphic() && b != b';') {
+            return Err(RadioError::InvalidParameter(format!("invalid menu value {:?}", value)));
+        }
+        self.transmit(&format!("EX{:03}0000{};", number, value))
+    }
+
+    /// Reads the raw value of menu item `number`, 0 - 62.
+    /// See `read_menu` for typed access.
+    pub fn read_extended_menu(&mut self, number: u16) -> RadioResult<String> {
+        check_range("menu number", number, 0, LAST_MENU)?;
+        self.read_parameters(&format!("EX{:03}0000", number))
+    }
+
+    /// Sets the VFO A frequency
+    pub fn set_frequency_a(&mut self, frequency: Frequency) -> RadioResult<()> {
+        self.transmit(&format!("FA{};", frequency.to_cat()))
+    }
+
+    /// Reads the VFO A frequency
+    pub fn read_frequency_a(&mut self) -> RadioResult<Frequency> {
+        let params = self.read_parameters("FA")?;
+        Frequency::from_cat(&params)
+    }
+
+    /// Sets the VFO B frequency
+    pub fn set_frequency_b(&mut self, frequency: Frequency) -> RadioResult<()> {
+        self.transmit(&format!("FB{};", frequency.to_cat()))
+    }
+
+    /// Reads the VFO B frequency
+    pub fn read_frequency_b(&mut self) -> RadioResult<Frequency> {
+        let params = self.read_parameters("FB")?;
+        Frequency::from_cat(&params)
+    }
+
+    /// Selects the VFO used for receiving. Also selects
+    /// the transmit VFO, so set the transmit VFO afterwards
+    /// to operate split.
+    pub fn set_rx_vfo(&mut self, vfo: Vfo) -> RadioResult<()> {
+        self.transmit(&format!("FR{};", vfo.to_cat()))
+    }
+
+    /// Reads the VFO used for receiving.
+    pub fn read_rx_vfo(&mut self) -> RadioResult<Vfo> {
+        let params = self.read_parameters("FR")?;
+        Vfo::from_cat(&params)
+    }
+
+    /// Turns the Fine Tuning function on or off.
+    pub fn set_fine_step(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("FS{};", on as u8))
+    }
+
+    /// Reads whether the Fine Tuning function is on.
+    pub fn read_fine_step(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("FS")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Selects the VFO used for transmitting.
+    pub fn set_tx_vfo(&mut self, vfo: Vfo) -> RadioResult<()> {
+        self.transmit(&format!("FT{};", vfo.to_cat()))
+    }
+
+    /// Reads the VFO used for transmitting.
+    pub fn read_tx_vfo(&mut self) -> RadioResult<Vfo> {
+        let params = self.read_parameters("FT")?;
+        Vfo::from_cat(&params)
+    }
+
+    /// Reads the firmware version, e.g. `"1.00"`
+    pub fn read_firmware_version(&mut self) -> RadioResult<String> {
+        self.read_parameters("FV")
+    }
+
+    /// Sets the DSP filter bandwidth in Hz, 0 - 9999.
+    ///
+    /// In FM mode, 0 = Narrow; 1 = Wide
+    pub fn set_filter_width(&mut self, width: u16) -> RadioResult<()> {
+        check_range("filter width", width, 0, 9999)?;
+        self.transmit(&format!("FW{:04};", width))
+    }
+
+    /// Reads the DSP filter bandwidth in Hz.
+    ///
+    /// In FM mode, 0 = Narrow; 1 = Wide
+    pub fn read_filter_width(&mut self) -> RadioResult<u16> {
+        let params = self.read_parameters("FW")?;
+        parse_field(&params, 0..4)
+    }
+
+    /// Sets the AGC time constant, 0 - 20.
+    ///
+    /// 0 = AGC OFF
+    pub fn set_agc(&mut self, time_constant: u8) -> RadioResult<()> {
+        check_range("AGC time constant", time_constant, 0, 20)?;
+        self.transmit(&format!("GT{:03};", time_constant))
+    }
+
+    /// Reads the AGC time constant, 0 - 20.
+    ///
+    /// 0 = AGC OFF
+    pub fn read_agc(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("GT")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Reads the transceiver ID number. The TS-480 answers 20.
+    pub fn read_id(&mut self) -> RadioResult<u16> {
+        let params = self.read_parameters("ID")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Reads the transceiver status. Returns the 35 parameter
+    /// characters of the `IF` answer without any decoding;
+    /// see `status` for the decoded form.
+    pub fn read_information(&mut self) -> RadioResult<String> {
+        let params = self.read_parameters("IF")?;
+        if params.len() != 35 {
+            return Err(RadioError::UnexpectedAnswer(format!("IF{};", params)));
+        }
+        Ok(params)
+    }
+
+    /// Sets the IF shift frequency in Hz, 0 - 9999
+    pub fn set_if_shift(&mut self, hz: u16) -> RadioResult<()> {
+        check_range("IF shift", hz, 0, 9999)?;
+        self.transmit(&format!("IS {:04};", hz))
+    }
+
+    /// Reads the IF shift frequency in Hz
+    pub fn read_if_shift(&mut self) -> RadioResult<u16> {
+        let params = self.read_parameters("IS")?;
+        parse_field(&params, 1..5)
+    }
+
+    /// Sets the electronic keyer speed in WPM, 10 - 60
+    pub fn set_keying_speed(&mut self, wpm: u8) -> RadioResult<()> {
+        check_range("keying speed", wpm, 10, 60)?;
+        self.transmit(&format!("KS{:03};", wpm))
+    }
+
+    /// Reads the electronic keyer speed in WPM
+    pub fn read_keying_speed(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("KS")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Sends a CW message of up to 24 characters
+    /// using the electronic keyer.
+    pub fn send_cw(&mut self, message: &str) -> RadioResult<()> {
+        if message.len() > 24 || !message.is_ascii() || message.contains(';') {
+            return Err(RadioError::InvalidParameter(
+                format!("CW message must be at most 24 ASCII characters without ';', got {:?}", message)
+            ));
+        }
+        self.transmit(&format!("KY {:<24};", message))
+    }
+
+    /// Reads whether the keyer's character buffer is full.
+    pub fn read_cw_buffer_full(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("KY")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Locks or unlocks the front panel.
+    pub fn set_lock(&mut self, locked: bool) -> RadioResult<()> {
+        self.transmit(&format!("LK{}0;", locked as u8))
+    }
+
+    /// Reads whether the front panel is locked.
+    pub fn read_lock(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("LK")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Selects a memory channel.
+    ///
+    /// 0 - 99 = Channels 00 - 99; 100 - 109 = Program scan channels P0 - P9
+    pub fn set_memory_channel(&mut self, channel: u8) -> RadioResult<()> {
+        check_range("memory channel", channel, 0, 109)?;
+        self.transmit(&format!("MC{:03};", channel))
+    }
+
+    /// Reads the selected memory channel.
+    ///
+    /// 0 - 99 = Channels 00 - 99; 100 - 109 = Program scan channels P0 - P9
+    pub fn read_memory_channel(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("MC")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Sets the operating mode.
+    pub fn set_mode(&mut self, mode: Mode) -> RadioResult<()> {
+        self.transmit(&format!("MD{};", mode.to_cat()))
+    }
+
+    /// Reads the operating mode.
+    pub fn read_mode(&mut self) -> RadioResult<Mode> {
+        let params = self.read_parameters("MD")?;
+        Mode::from_cat(&params)
+    }
+
+    /// Selects menu A or B.
+    ///
+    /// p1: 0 = Menu A; 1 = Menu B
+    pub fn set_menu_bank(&mut self, p1: u8) -> RadioResult<()> {
+        check_range("menu bank", p1, 0, 1)?;
+        self.transmit(&format!("MF{};", p1))
+    }
+
+    /// Reads the selected menu.
+    ///
+    /// 0 = Menu A; 1 = Menu B
+    pub fn read_menu_bank(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("MF")?;
+        parse_field(&params, 0..1)
+    }
+
+    /// Sets the microphone gain, 0 - 100
+    pub fn set_mic_gain(&mut self, gain: u8) -> RadioResult<()> {
+        check_range("microphone gain", gain, 0, 100)?;
+        self.transmit(&format!("MG{:03};", gain))
+    }
+
+    /// Reads the microphone gain, 0 - 100
+    pub fn read_mic_gain(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("MG")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Sets the TX monitor output level, 0 - 9.
+    ///
+    /// 0 = Monitor OFF
+    pub fn set_monitor_level(&mut self, level: u8) -> RadioResult<()> {
+        check_range("monitor level", level, 0, 9)?;
+        self.transmit(&format!("ML{:03};", level))
+    }
+
+    /// Reads the TX monitor output level, 0 - 9
+    pub fn read_monitor_level(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("ML")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Turns the Noise Blanker on or off.
+    pub fn set_noise_blanker(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("NB{};", on as u8))
+    }
+
+    /// Reads whether the Noise Blanker is on.
+    pub fn read_noise_blanker(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("NB")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Sets the Noise Blanker level, 1 - 10
+    pub fn set_noise_blanker_level(&mut self, level: u8) -> RadioResult<()> {
+        check_range("noise blanker level", level, 1, 10)?;
+        self.transmit(&format!("NL{:03};", level))
+    }
+
+    /// Reads the Noise Blanker level, 1 - 10
+    pub fn read_noise_blanker_level(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("NL")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Sets the Noise Reduction function.
+    ///
+    /// p1: 0 = OFF; 1 = NR1; 2 = NR2
+    pub fn set_noise_reduction(&mut self, p1: u8) -> RadioResult<()> {
+        check_range("noise reduction", p1, 0, 2)?;
+        self.transmit(&format!("NR{};", p1))
+    }
+
+    /// Reads the Noise Reduction function.
+    ///
+    /// 0 = OFF; 1 = NR1; 2 = NR2
+    pub fn read_noise_reduction(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("NR")?;
+        parse_field(&params, 0..1)
+    }
+
+    /// Turns the Auto Notch function on or off.
+    pub fn set_auto_notch(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("NT{};", on as u8))
+    }
+
+    /// Reads whether the Auto Notch function is on.
+    pub fn read_auto_notch(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("NT")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Turns the pre-amplifier on or off.
+    pub fn set_preamp(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("PA{};", on as u8))
+    }
+
+    /// Reads whether the pre-amplifier is on.
+    pub fn read_preamp(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("PA")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Sets the output power in watts, 5 - 100.
+    ///
+    /// In AM mode the maximum is 25 watts.
+    pub fn set_power(&mut self, watts: u8) -> RadioResult<()> {
+        check_range("output power", watts, 5, 100)?;
+        self.transmit(&format!("PC{:03};", watts))
+    }
+
+    /// Reads the output power in watts, 5 - 100
+    pub fn read_power(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("PC")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Turns the Speech Processor on or off.
+    pub fn set_speech_processor(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("PR{};", on as u8))
+    }
+
+    /// Reads whether the Speech Processor is on.
+    pub fn read_speech_processor(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("PR")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Turns the transceiver on or off.
+    pub fn set_power_status(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("PS{};", on as u8))
+    }
+
+    /// Reads the power on/off status.
+    pub fn read_power_status(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("PS")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Stores the current settings in the Quick Memory.
+    pub fn quick_memory_store(&mut self) -> RadioResult<()> {
+        self.transmit("QI;")
+    }
+
+    /// Turns the RF attenuator on or off.
+    pub fn set_attenuator(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("RA{:02};", on as u8))
+    }
+
+    /// Reads whether the RF attenuator is on.
+    pub fn read_attenuator(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("RA")?;
+        parse_flag(&params, 1)
+    }
+
+    /// Clears the RIT/XIT offset.
+    pub fn rit_clear(&mut self) -> RadioResult<()> {
+        self.transmit("RC;")
+    }
+
+    /// Moves the RIT/XIT offset down by `hz`, 0 - 9999
+    pub fn rit_down(&mut self, hz: u16) -> RadioResult<()> {
+        check_range("RIT/XIT step", hz, 0, 9999)?;
+        self.transmit(&format!("RD{:05};", hz))
+    }
+
+    /// Moves the RIT/XIT offset up by `hz`, 0 - 9999
+    pub fn rit_up(&mut self, hz: u16) -> RadioResult<()> {
+        check_range("RIT/XIT step", hz, 0, 9999)?;
+        self.transmit(&format!("RU{:05};", hz))
+    }
+
+    /// Sets the RF gain, 0 - 255
+    pub fn set_rf_gain(&mut self, gain: u8) -> RadioResult<()> {
+        self.transmit(&format!("RG{:03};", gain))
+    }
+
+    /// Reads the RF gain, 0 - 255
+    pub fn read_rf_gain(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("RG")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Sets the Noise Reduction level, 1 - 10
+    pub fn set_noise_reduction_level(&mut self, level: u8) -> RadioResult<()> {
+        check_range("noise reduction level", level, 1, 10)?;
+        self.transmit(&format!("RL{:02};", level))
+    }
+
+    /// Reads the Noise Reduction level, 1 - 10
+    pub fn read_noise_reduction_level(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("RL")?;
+        parse_field(&params, 0..2)
+    }
+
+    /// Turns the RIT function on or off.
+    pub fn set_rit(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("RT{};", on as u8))
+    }
+
+    /// Reads whether the RIT function is on.
+    pub fn read_rit(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("RT")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Starts or stops scanning.
+    pub fn set_scan(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("SC{};", on as u8))
+    }
+
+    /// Reads whether the transceiver is scanning.
+    pub fn read_scan(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("SC")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Sets the CW break-in delay in milliseconds, 0 - 1000
+    pub fn set_break_in_delay(&mut self, ms: u16) -> RadioResult<()> {
+        check_range("break-in delay", ms, 0, 1000)?;
+        self.transmit(&format!("SD{:04};", ms))
+    }
+
+    /// Reads the CW break-in delay in milliseconds
+    pub fn read_break_in_delay(&mut self) -> RadioResult<u16> {
+        let params = self.read_parameters("SD")?;
+        parse_field(&params, 0..4)
+    }
+
+    /// Sets the DSP filter high-cut index, 0 - 11
+    pub fn set_high_cut(&mut self, index: u8) -> RadioResult<()> {
+        check_range("high-cut", index, 0, 11)?;
+        self.transmit(&format!("SH{:02};", index))
+    }
+
+    /// Reads the DSP filter high-cut index, 0 - 11
+    pub fn read_high_cut(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("SH")?;
+        parse_field(&params, 0..2)
+    }
+
+    /// Sets the DSP filter low-cut index, 0 - 11
+    pub fn set_low_cut(&mut self, index: u8) -> RadioResult<()> {
+        check_range("low-cut", index, 0, 11)?;
+        self.transmit(&format!("SL{:02};", index))
+    }
+
+    /// Reads the DSP filter low-cut index, 0 - 11
+    pub fn read_low_cut(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("SL")?;
+        parse_field(&params, 0..2)
+    }
+
+    /// Sets the squelch level, 0 - 255
+    pub fn set_squelch(&mut self, level: u8) -> RadioResult<()> {
+        self.transmit(&format!("SQ0{:03};", level))
+    }
+
+    /// Reads the squelch level, 0 - 255
+    pub fn read_squelch(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("SQ0")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Performs the VFO/memory transfer (M>V) function.
+    pub fn memory_transfer(&mut self) -> RadioResult<()> {
+        self.transmit("SV;")
+    }
+
+    /// Sets the tone frequency number, 0 - 41
+    pub fn set_tone_frequency(&mut self, number: u8) -> RadioResult<()> {
+        check_range("tone frequency number", number, 0, 41)?;
+        self.transmit(&format!("TN{:02};", number))
+    }
+
+    /// Reads the tone frequency number, 0 - 41
+    pub fn read_tone_frequency(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("TN")?;
+        parse_field(&params, 0..2)
+    }
+
+    /// Turns the tone function on or off.
+    pub fn set_tone(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("TO{};", on as u8))
+    }
+
+    /// Reads whether the tone function is on.
+    pub fn read_tone(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("TO")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Turns the TF-SET function on or off.
+    pub fn set_tf_set(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("TS{};", on as u8))
+    }
+
+    /// Reads whether the TF-SET function is on.
+    pub fn read_tf_set(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("TS")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Sets the VOX delay time in milliseconds, 0 - 3000
+    /// in steps of 150
+    pub fn set_vox_delay(&mut self, ms: u16) -> RadioResult<()> {
+        check_range("VOX delay", ms, 0, 3000)?;
+        if !ms.is_multiple_of(150) {
+            return Err(RadioError::InvalidParameter(
+                format!("VOX delay must be a multiple of 150, got {}", ms)
+            ));
+        }
+        self.transmit(&format!("VD{:04};", ms))
+    }
+
+    /// Reads the VOX delay time in milliseconds
+    pub fn read_vox_delay(&mut self) -> RadioResult<u16> {
+        let params = self.read_parameters("VD")?;
+        parse_field(&params, 0..4)
+    }
+
+    /// Sets the VOX gain, 0 - 9
+    pub fn set_vox_gain(&mut self, gain: u8) -> RadioResult<()> {
+        check_range("VOX gain", gain, 0, 9)?;
+        self.transmit(&format!("VG{:03};", gain))
+    }
+
+    /// Reads the VOX gain, 0 - 9
+    pub fn read_vox_gain(&mut self) -> RadioResult<u8> {
+        let params = self.read_parameters("VG")?;
+        parse_field(&params, 0..3)
+    }
+
+    /// Announces the current status using the voice synthesizer.
+    ///
+    /// p1: 1 = VOICE1; 2 = VOICE2
+    pub fn voice(&mut self, p1: u8) -> RadioResult<()> {
+        check_range("voice", p1, 1, 2)?;
+        self.transmit(&format!("VR{};", p1))
+    }
+
+    /// Turns the VOX function on or off.
+    pub fn set_vox(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("VX{};", on as u8))
+    }
+
+    /// Reads whether the VOX function is on.
+    pub fn read_vox(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("VX")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Turns the XIT function on or off.
+    pub fn set_xit(&mut self, on: bool) -> RadioResult<()> {
+        self.transmit(&format!("XT{};", on as u8))
+    }
+
+    /// Reads whether the XIT function is on.
+    pub fn read_xit(&mut self) -> RadioResult<bool> {
+        let params = self.read_parameters("XT")?;
+        parse_flag(&params, 0)
+    }
+
+    /// Sends `prefix;` and returns the parameters of the answer,
+    /// i.e. everything between `prefix` and the terminating `;`.
+    /// The query is repeated if the radio answers `E;` or `O;`.
+    ///
+    /// Other answers received before the expected one are skipped,
+    /// or kept as events in Auto Information mode.
+    pub(crate) fn read_parameters(&mut self, prefix: &str) -> RadioResult<String> {
+        let mut retries = self.options.retries;
+        loop {
+            match self.read_parameters_once(prefix) {
+                Err(RadioError::CommError) | Err(RadioError::ProcIncomplete) if retries > 0 => retries -= 1,
+                result => return result,
+            }
+        }
+    }
+
+    fn read_parameters_once(&mut self, prefix: &str) -> RadioResult<String> {
+        self.transmit(&format!("{};", prefix))?;
+        let deadline = Instant::now() + self.options.timeout;
+
+        loop {
+            let answer = self.receive()?;
+            let answer = answer.as_str();
+
+            if answer.starts_with(prefix) && answer.ends_with(';') {
+                return Ok(answer[prefix.len()..answer.len() - 1].to_owned());
+            }
+            // Without Auto Information, this is a late answer to an
+            // earlier query or a report from a radio left in AI mode
+            if self.auto_information {
+                self.queue_event(answer);
+            }
+            if Instant::now() >= deadline {
+                return Err(self.timed_out());
+            }
+        }
+    }
+}
+
+/// Returns an error unless `min <= value <= max`.
+pub(crate) fn check_range<N: PartialOrd + Display>(name: &str, value: N, min: N, max: N) -> RadioResult<()> {
+    if value < min || value > max {
+        return Err(RadioError::InvalidParameter(
+            format!("{} must be {} - {}, got {}", name, min, max, value)
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the characters of `params` in `range` as a number.
+pub(crate) fn parse_field<N: FromStr>(params: &str, range: Range<usize>) -> RadioResult<N> {
+    params.get(range)
+        .and_then(|field| field.parse().ok())
+        .ok_or_else(|| RadioError::UnexpectedAnswer(params.to_owned()))
+}
+
+/// Parses the character of `params` at `index` as a `0`/`1` flag.
+pub(crate) fn parse_flag(params: &str, index: usize) -> RadioResult<bool> {
+    match params.get(index..index + 1) {
+        Some("0") => Ok(false),
+        Some("1") => Ok(true),
+        _ => Err(RadioError::UnexpectedAnswer(params.to_owned())),
+    }
+}
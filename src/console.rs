@@ -0,0 +1,388 @@
+//! An interactive CAT console: sending raw commands, decoding
+//! the answers field by field, and completing command mnemonics.
+//!
+//! The `ts480-console` binary, built with the `console` feature,
+//! adds line editing and a history file.
+//!
+//! ```no_run
+//! use ts480::TS480;
+//! use ts480::console::{self, Console};
+//!
+//! let mut radio = TS480::new("/dev/ttyUSB0").unwrap();
+//! let mut console = Console::new(&mut radio);
+//! for answer in console.execute("fa;md").unwrap() {
+//!     print!("{}", console::describe(&answer));
+//! }
+//! ```
+
+use crate::menu::ExMenu;
+use crate::transport::Transport;
+use crate::types::{self, TONE_FREQUENCIES};
+use crate::{RadioError, RadioResult, TS480};
+
+use std::io;
+use std::time::Duration;
+
+/// How a field of an answer is decoded.
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    /// A number, followed by its unit
+    Number(&'static str),
+    /// `0` = Off; `1` = On
+    Flag,
+    /// The values and their meanings
+    Choices(&'static [(&'static str, &'static str)]),
+    Frequency,
+    /// RIT/XIT offset in Hz, e.g. `+0150`
+    Offset,
+    Mode,
+    Vfo,
+    ToneMode,
+    /// Tone or CTCSS frequency number
+    Tone,
+    Text,
+    /// Characters with no meaning
+    Reserved,
+}
+
+/// A field of an answer: its name, width, and how it is
+/// decoded. A width of 0 takes the rest of the answer.
+#[derive(Clone, Copy, Debug)]
+struct Field(&'static str, usize, Kind);
+
+/// A CAT command, as described in the TS-480 manual.
+#[derive(Clone, Copy, Debug)]
+pub struct CommandInfo {
+    pub mnemonic: &'static str,
+    pub description: &'static str,
+    /// Fields of the answer, or of the command if it is never answered
+    fields: &'static [Field],
+}
+
+/// A decoded field of an answer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedField {
+    pub name: &'static str,
+    /// The characters of the answer making up the field
+    pub raw: String,
+    /// What the field means, e.g. `14.074000 MHz`
+    pub meaning: String,
+}
+
+use self::Kind::*;
+
+const RECEIVER: Field = Field("receiver", 1, Reserved);
+const METERS: &[(&str, &str)] = &[("1", "SWR"), ("2", "COMP"), ("3", "ALC")];
+
+/// The commands of the TS-480, in alphabetical order
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo { mnemonic: "AC", description: "Internal antenna tuner status", fields: &[
+        Field("RX tuner", 1, Choices(&[("0", "RX-AT THRU"), ("1", "RX-AT IN")])),
+        Field("TX tuner", 1, Choices(&[("0", "TX-AT THRU"), ("1", "TX-AT IN")])),
+        Field("tuning", 1, Choices(&[("0", "Stopped"), ("1", "Tuning")])),
+    ] },
+    CommandInfo { mnemonic: "AG", description: "AF gain", fields: &[RECEIVER, Field("gain", 3, Number(""))] },
+    CommandInfo { mnemonic: "AI", description: "Auto Information function", fields: &[
+        Field("mode", 1, Choices(&[("0", "Off"), ("2", "On"), ("4", "On (with backup)")])),
+    ] },
+    CommandInfo { mnemonic: "AN", description: "Antenna connector", fields: &[
+        Field("antenna", 1, Choices(&[("0", "ANT1"), ("1", "ANT2")])),
+    ] },
+    CommandInfo { mnemonic: "BC", description: "Beat Cancel function", fields: &[
+        Field("beat cancel", 1, Choices(&[("0", "Off"), ("1", "BC1"), ("2", "BC2")])),
+    ] },
+    CommandInfo { mnemonic: "BD", description: "Frequency band down", fields: &[] },
+    CommandInfo { mnemonic: "BU", description: "Frequency band up", fields: &[] },
+    CommandInfo { mnemonic: "BY", description: "Busy (squelch open)", fields: &[Field("busy", 1, Flag), Field("", 1, Reserved)] },
+    CommandInfo { mnemonic: "CA", description: "CW Auto Zero-beat function", fields: &[Field("running", 1, Flag)] },
+    CommandInfo { mnemonic: "CH", description: "MULTI/CH encoder step", fields: &[
+        Field("direction", 1, Choices(&[("0", "Up"), ("1", "Down")])),
+    ] },
+    CommandInfo { mnemonic: "CN", description: "CTCSS frequency", fields: &[Field("CTCSS number", 2, Tone)] },
+    CommandInfo { mnemonic: "CT", description: "CTCSS function", fields: &[Field("CTCSS", 1, Flag)] },
+    CommandInfo { mnemonic: "DN", description: "Microphone DWN key", fields: &[] },
+    CommandInfo { mnemonic: "EX", description: "Extended menu", fields: &[
+        Field("menu", 3, Number("")),
+        Field("", 4, Reserved),
+        Field("value", 0, Text),
+    ] },
+    CommandInfo { mnemonic: "FA", description: "VFO A frequency", fields: &[Field("frequency", 11, Frequency)] },
+    CommandInfo { mnemonic: "FB", description: "VFO B frequency", fields: &[Field("frequency", 11, Frequency)] },
+    CommandInfo { mnemonic: "FR", description: "Receive VFO", fields: &[Field("VFO", 1, Vfo)] },
+    CommandInfo { mnemonic: "FS", description: "Fine Tuning function", fields: &[Field("fine tuning", 1, Flag)] },
+    CommandInfo { mnemonic: "FT", description: "Transmit VFO", fields: &[Field("VFO", 1, Vfo)] },
+    CommandInfo { mnemonic: "FV", description: "Firmware version", fields: &[Field("version", 0, Text)] },
+    CommandInfo { mnemonic: "FW", description: "DSP filter bandwidth", fields: &[Field("width", 4, Number("Hz"))] },
+    CommandInfo { mnemonic: "GT", description: "AGC time constant", fields: &[Field("time constant", 3, Number(""))] },
+    CommandInfo { mnemonic: "ID", description: "Transceiver ID", fields: &[
+        Field("ID", 3, Choices(&[("020", "TS-480")])),
+    ] },
+    CommandInfo { mnemonic: "IF", description: "Transceiver status", fields: &[
+        Field("frequency", 11, Frequency),
+        Field("", 5, Reserved),
+        Field("RIT/XIT offset", 5, Offset),
+        Field("RIT", 1, Flag),
+        Field("XIT", 1, Flag),
+        Field("memory channel", 3, Number("")),
+        Field("TX/RX", 1, Choices(&[("0", "RX"), ("1", "TX")])),
+        Field("mode", 1, Mode),
+        Field("VFO", 1, Vfo),
+        Field("scan", 1, Flag),
+        Field("split", 1, Flag),
+        Field("tone", 1, ToneMode),
+        Field("tone number", 2, Tone),
+        Field("", 1, Reserved),
+    ] },
+    CommandInfo { mnemonic: "IS", description: "IF shift", fields: &[Field("", 1, Reserved), Field("shift", 4, Number("Hz"))] },
+    CommandInfo { mnemonic: "KS", description: "Keyer speed", fields: &[Field("speed", 3, Number("WPM"))] },
+    CommandInfo { mnemonic: "KY", description: "CW message / keyer buffer", fields: &[
+        Field("buffer", 1, Choices(&[("0", "Space available"), ("1", "Full")])),
+    ] },
+    CommandInfo { mnemonic: "LK", description: "Front panel lock", fields: &[Field("locked", 1, Flag), Field("", 1, Reserved)] },
+    CommandInfo { mnemonic: "MC", description: "Memory channel", fields: &[Field("channel", 3, Number(""))] },
+    CommandInfo { mnemonic: "MD", description: "Operating mode", fields: &[Field("mode", 1, Mode)] },
+    CommandInfo { mnemonic: "MF", description: "Menu A/B", fields: &[Field("menu", 1, Choices(&[("0", "Menu A"), ("1", "Menu B")]))] },
+    CommandInfo { mnemonic: "MG", description: "Microphone gain", fields: &[Field("gain", 3, Number(""))] },
+    CommandInfo { mnemonic: "ML", description: "TX monitor level", fields: &[Field("level", 3, Number(""))] },
+    CommandInfo { mnemonic: "MR", description: "Memory channel read", fields: &[
+        Field("record", 1, Choices(&[("0", "RX"), ("1", "TX")])),
+        Field("channel", 3, Number("")),
+        Field("frequency", 11, Frequency),
+        Field("mode", 1, Mode),
+        Field("lockout", 1, Flag),
+        Field("tone", 1, ToneMode),
+        Field("tone number", 2, Tone),
+        Field("CTCSS number", 2, Tone),
+        Field("", 3, Reserved),
+        Field("FM narrow", 1, Flag),
+        Field("", 13, Reserved),
+        Field("name", 0, Text),
+    ] },
+    CommandInfo { mnemonic: "MW", description: "Memory channel write", fields: &[] },
+    CommandInfo { mnemonic: "NB", description: "Noise Blanker", fields: &[Field("noise blanker", 1, Flag)] },
+    CommandInfo { mnemonic: "NL", description: "Noise Blanker level", fields: &[Field("level", 3, Number(""))] },
+    CommandInfo { mnemonic: "NR", description: "Noise Reduction", fields: &[
+        Field("noise reduction", 1, Choices(&[("0", "Off"), ("1", "NR1"), ("2", "NR2")])),
+    ] },
+    CommandInfo { mnemonic: "NT", description: "Auto Notch", fields: &[Field("auto notch", 1, Flag)] },
+    CommandInfo { mnemonic: "PA", description: "Pre-amplifier", fields: &[Field("pre-amp", 1, Flag), Field("", 1, Reserved)] },
+    CommandInfo { mnemonic: "PC", description: "Output power", fields: &[Field("power", 3, Number("W"))] },
+    CommandInfo { mnemonic: "PR", description: "Speech Processor", fields: &[Field("processor", 1, Flag)] },
+    CommandInfo { mnemonic: "PS", description: "Power on/off", fields: &[Field("power", 1, Flag)] },
+    CommandInfo { mnemonic: "QI", description: "Quick Memory store", fields: &[] },
+    CommandInfo { mnemonic: "RA", description: "RF attenuator", fields: &[Field("attenuator", 2, Flag), Field("", 2, Reserved)] },
+    CommandInfo { mnemonic: "RC", description: "RIT/XIT clear", fields: &[] },
+    CommandInfo { mnemonic: "RD", description: "RIT/XIT down", fields: &[Field("step", 5, Number("Hz"))] },
+    CommandInfo { mnemonic: "RG", description: "RF gain", fields: &[Field("gain", 3, Number(""))] },
+    CommandInfo { mnemonic: "RL", description: "Noise Reduction level", fields: &[Field("level", 2, Number(""))] },
+    CommandInfo { mnemonic: "RM", description: "Meter", fields: &[Field("meter", 1, Choices(METERS)), Field("value", 4, Number("/ 30"))] },
+    CommandInfo { mnemonic: "RT", description: "RIT function", fields: &[Field("RIT", 1, Flag)] },
+    CommandInfo { mnemonic: "RU", description: "RIT/XIT up", fields: &[Field("step", 5, Number("Hz"))] },
+    CommandInfo { mnemonic: "RX", description: "Receive", fields: &[] },
+    CommandInfo { mnemonic: "SC", description: "Scan", fields: &[Field("scan", 1, Flag)] },
+    CommandInfo { mnemonic: "SD", description: "CW break-in delay", fields: &[Field("delay", 4, Number("ms"))] },
+    CommandInfo { mnemonic: "SH", description: "DSP filter high-cut", fields: &[Field("high-cut index", 2, Number(""))] },
+    CommandInfo { mnemonic: "SL", description: "DSP filter low-cut", fields: &[Field("low-cut index", 2, Number(""))] },
+    CommandInfo { mnemonic: "SM", description: "S-meter", fields: &[RECEIVER, Field("value", 4, Number("/ 30"))] },
+    CommandInfo { mnemonic: "SQ", description: "Squelch level", fields: &[RECEIVER, Field("level", 3, Number(""))] },
+    CommandInfo { mnemonic: "SV", description: "Memory transfer (M>V)", fields: &[] },
+    CommandInfo { mnemonic: "TN", description: "Tone frequency", fields: &[Field("tone number", 2, Tone)] },
+    CommandInfo { mnemonic: "TO", description: "Tone function", fields: &[Field("tone", 1, Flag)] },
+    CommandInfo { mnemonic: "TS", description: "TF-SET function", fields: &[Field("TF-SET", 1, Flag)] },
+    CommandInfo { mnemonic: "TX", description: "Transmit", fields: &[
+        Field("source", 1, Choices(&[("0", "SEND"), ("1", "DATA"), ("2", "TX TUNE")])),
+    ] },
+    CommandInfo { mnemonic: "UP", description: "Microphone UP key", fields: &[] },
+    CommandInfo { mnemonic: "VD", description: "VOX delay", fields: &[Field("delay", 4, Number("ms"))] },
+    CommandInfo { mnemonic: "VG", description: "VOX gain", fields: &[Field("gain", 3, Number(""))] },
+    CommandInfo { mnemonic: "VR", description: "Voice synthesizer", fields: &[
+        Field("voice", 1, Choices(&[("1", "VOICE1"), ("2", "VOICE2")])),
+    ] },
+    CommandInfo { mnemonic: "VX", description: "VOX function", fields: &[Field("VOX", 1, Flag)] },
+    CommandInfo { mnemonic: "XT", description: "XIT function", fields: &[Field("XIT", 1, Flag)] },
+];
+
+/// Returns the command with the given mnemonic, e.g. `FA`.
+pub fn command(mnemonic: &str) -> Option<&'static CommandInfo> {
+    COMMANDS.iter().find(|c| c.mnemonic.eq_ignore_ascii_case(mnemonic))
+}
+
+/// Returns the mnemonics starting with `prefix`, ignoring case.
+pub fn complete(prefix: &str) -> Vec<&'static str> {
+    let prefix = prefix.to_ascii_uppercase();
+    COMMANDS.iter().map(|c| c.mnemonic).filter(|m| m.starts_with(&prefix)).collect()
+}
+
+impl CommandInfo {
+    /// Describes the command and the fields of its answer,
+    /// one per line.
+    pub fn help(&self) -> String {
+        let mut help = format!("{} - {}\n", self.mnemonic, self.description);
+        for &Field(name, width, kind) in self.fields {
+            if let Reserved = kind {
+                continue;
+            }
+            let width = if width == 0 { "rest".to_owned() } else { width.to_string() };
+            help.push_str(&format!("  {:<16} {:>4}  {}\n", name, width, kind_help(kind)));
+        }
+        help
+    }
+}
+
+fn kind_help(kind: Kind) -> String {
+    match kind {
+        Number(unit) => format!("number {}", unit).trim_end().to_owned(),
+        Flag => "0 = Off; 1 = On".to_owned(),
+        Choices(choices) => {
+            choices.iter().map(|&(raw, meaning)| format!("{} = {}", raw, meaning)).collect::<Vec<_>>().join("; ")
+        },
+        Frequency => "frequency in Hz".to_owned(),
+        Offset => "offset in Hz, e.g. +0150".to_owned(),
+        Mode => "1 = LSB; 2 = USB; 3 = CW; 4 = FM; 5 = AM; 6 = FSK; 7 = CW-R; 9 = FSK-R".to_owned(),
+        Vfo => "0 = VFO A; 1 = VFO B; 2 = Memory".to_owned(),
+        ToneMode => "0 = Off; 1 = Tone; 2 = CTCSS".to_owned(),
+        Tone => "tone number, 00 - 41".to_owned(),
+        Text => "text".to_owned(),
+        Reserved => String::new(),
+    }
+}
+
+/// Decodes the fields of an answer such as `FA00014074000;`.
+/// Returns `None` for answers of unknown commands, and for
+/// answers that do not fit the command's fields.
+pub fn decode(answer: &str) -> Option<Vec<DecodedField>> {
+    let answer = answer.trim().trim_end_matches(';');
+    let info = command(answer.get(..2)?)?;
+    let mut params = &answer[2..];
+    let mut fields = Vec::new();
+
+    for &Field(name, width, kind) in info.fields {
+        let width = if width == 0 { params.len() } else { width };
+        let raw = params.get(..width)?;
+        params = &params[width..];
+
+        if let Reserved = kind {
+            continue;
+        }
+        let meaning = decode_field(kind, raw)?;
+        fields.push(DecodedField { name, raw: raw.to_owned(), meaning });
+    }
+    if !params.is_empty() {
+        return None;
+    }
+
+    // Menu values are described by the menu item they belong to
+    if info.mnemonic == "EX" {
+        let item = fields[0].raw.parse().ok().and_then(ExMenu::from_number)?;
+        let value = fields[1].raw.parse().ok()?;
+        fields[0].meaning = item.to_string();
+        fields[1].meaning = item.values().describe(value);
+    }
+
+    Some(fields)
+}
+
+fn decode_field(kind: Kind, raw: &str) -> Option<String> {
+    let number = || raw.parse::<u32>().ok();
+
+    Some(match kind {
+        Number(unit) => format!("{} {}", number()?, unit).trim_end().to_owned(),
+        Flag => match raw.parse::<u32>().ok()? {
+            0 => "Off".to_owned(),
+            1 => "On".to_owned(),
+            _ => return None,
+        },
+        Choices(choices) => choices.iter().find(|&&(value, _)| value == raw)?.1.to_owned(),
+        Frequency => match raw.parse::<u64>().ok()? {
+            0 => "not programmed".to_owned(),
+            _ => types::Frequency::from_cat(raw).ok()?.to_string(),
+        },
+        Offset => format!("{} Hz", raw.parse::<i32>().ok()?),
+        Mode => match raw {
+            "0" => "none".to_owned(),
+            _ => types::Mode::from_cat(raw).ok()?.to_string(),
+        },
+        Vfo => types::Vfo::from_cat(raw).ok()?.to_string(),
+        ToneMode => types::ToneMode::from_cat(raw).ok()?.to_string(),
+        Tone => {
+            let tenths = *TONE_FREQUENCIES.get(number()? as usize)?;
+            format!("{}.{} Hz", tenths / 10, tenths % 10)
+        },
+        Text => raw.trim_end().to_owned(),
+        Reserved => String::new(),
+    })
+}
+
+/// Formats an answer followed by its decoded fields, one
+/// per line, or the answer alone if it cannot be decoded.
+pub fn describe(answer: &str) -> String {
+    let mut description = format!("{}\n", answer);
+    if let Some(error) = RadioError::from_answer(answer) {
+        description.push_str(&format!("  {}\n", error));
+    }
+    for field in decode(answer).unwrap_or_default() {
+        description.push_str(&format!("  {:<16} {:<13} {}\n", field.name, field.raw, field.meaning));
+    }
+    description
+}
+
+/// Sends raw commands to the radio and collects the answers.
+pub struct Console<'a, T: Transport + 'a> {
+    radio: &'a mut TS480<T>,
+    wait: Duration,
+}
+
+impl<'a, T: Transport> Console<'a, T> {
+    /// Uses `radio`, waiting up to 300 ms for each answer.
+    pub fn new(radio: &'a mut TS480<T>) -> Self {
+        Console { radio, wait: Duration::from_millis(300) }
+    }
+
+    /// Sets how long to wait for further answers before
+    /// deciding the radio has answered everything.
+    pub fn set_wait(&mut self, wait: Duration) {
+        self.wait = wait;
+    }
+
+    /// Sends the commands in `line`, e.g. `fa;md`, and returns
+    /// every answer the radio sends before going quiet, including
+    /// `?;`, `E;` and `O;`. Mnemonics may be typed in lower case,
+    /// and the final `;` may be left out.
+    pub fn execute(&mut self, line: &str) -> RadioResult<Vec<String>> {
+        let mut commands = String::new();
+        for command in line.split(';').map(str::trim).filter(|c| !c.is_empty()) {
+            let split = command.char_indices().nth(2).map(|(i, _)| i).unwrap_or(command.len());
+            commands.push_str(&command[..split].to_ascii_uppercase());
+            commands.push_str(&command[split..]);
+            commands.push(';');
+        }
+        if commands.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let timeout = self.radio.timeout();
+        self.radio.set_timeout(self.wait);
+        let result = self.collect(&commands);
+        self.radio.set_timeout(timeout);
+        result
+    }
+
+    fn collect(&mut self, commands: &str) -> RadioResult<Vec<String>> {
+        self.radio.transmit(commands)?;
+
+        let mut answers = Vec::new();
+        loop {
+            answers.push(match self.radio.receive() {
+                Ok(answer) => answer.to_string(),
+                Err(RadioError::SyntaxOrStatus) => "?;".to_owned(),
+                Err(RadioError::CommError) => "E;".to_owned(),
+                Err(RadioError::ProcIncomplete) => "O;".to_owned(),
+                // In-memory transports such as the simulator report
+                // end of file when there is nothing more to read
+                Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::UnexpectedEof => return Ok(answers),
+                Err(e) => return Err(e),
+            });
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+pub type RadioResult<T> = Result<T, RadioError>;
+
+#[derive(Debug)]
+pub enum RadioError {
+    /// An error reported by the serial port itself, such as
+    /// failing to open or configure the device.
+    Serial(serial::Error),
+
+    /// An I/O error while reading from or writing to the radio.
+    Io(io::Error),
+
+    /// `?;` response from the radio.
+    ///
+    /// Indicates either the command syntax was incorrect or
+    /// the command was not executed due to the tranceiver's current status
+    SyntaxOrStatus,
+
+    /// `E;` response from the radio.
+    ///
+    /// Indicates a communcation error.
+    CommError,
+
+    /// `O;` response from the radio.
+    ///
+    /// Indicates receive data was sent but
+    /// processing was not completed.
+    ProcIncomplete,
+
+    /// The radio answered with something that could not be
+    /// parsed as a reply to the command that was sent.
+    UnexpectedAnswer(String),
+
+    /// A command parameter was outside the range accepted by the radio.
+    /// The command was not sent.
+    InvalidParameter(String),
+
+    /// The driver refused to key the radio, because the band
+    /// privileges do not allow transmitting there.
+    /// The command was not sent.
+    TransmitRefused(String),
+
+    /// The radio did not answer at any of the baud rates tried.
+    NotDetected,
+}
+
+impl RadioError {
+    /// Returns the error corresponding to one of the radio's
+    /// error answers, or `None` if `answer` is not an error.
+    pub fn from_answer(answer: &str) -> Option<RadioError> {
+        match answer {
+            "?;" => Some(RadioError::SyntaxOrStatus),
+            "E;" => Some(RadioError::CommError),
+            "O;" => Some(RadioError::ProcIncomplete),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RadioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RadioError::Serial(ref e) => write!(f, "serial port error: {}", e),
+            RadioError::Io(ref e) => write!(f, "I/O error: {}", e),
+            RadioError::SyntaxOrStatus => write!(f, "command syntax error or not executable in current status"),
+            RadioError::CommError => write!(f, "communication error"),
+            RadioError::ProcIncomplete => write!(f, "processing not completed"),
+            RadioError::UnexpectedAnswer(ref a) => write!(f, "unexpected answer from radio: {:?}", a),
+            RadioError::InvalidParameter(ref msg) => write!(f, "invalid parameter: {}", msg),
+            RadioError::TransmitRefused(ref msg) => write!(f, "transmit refused: {}", msg),
+            RadioError::NotDetected => write!(f, "no TS-480 answered at any baud rate"),
+        }
+    }
+}
+
+impl Error for RadioError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RadioError::Serial(ref e) => Some(e),
+            RadioError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serial::Error> for RadioError {
+    fn from(e: serial::Error) -> Self {
+        RadioError::Serial(e)
+    }
+}
+
+impl From<io::Error> for RadioError {
+    fn from(e: io::Error) -> Self {
+        RadioError::Io(e)
+    }
+}
@@ -0,0 +1,145 @@
+//! Unsolicited reports sent by the radio in
+//! Auto Information mode (`AI2;` or `AI4;`).
+
+use crate::status::TransceiverStatus;
+use crate::transport::Transport;
+use crate::types::{Frequency, Mode, Vfo};
+use crate::{RadioError, RadioResult, TS480};
+
+use std::collections::VecDeque;
+use std::io;
+
+/// A change reported by the radio without being asked.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RadioEvent {
+    /// A VFO was tuned to a new frequency.
+    FrequencyChanged { vfo: Vfo, frequency: Frequency },
+
+    /// The operating mode changed.
+    ModeChanged(Mode),
+
+    /// The radio switched to transmit (`true`) or receive (`false`).
+    TransmitChanged(bool),
+
+    /// Any other report, as received including its `;`.
+    Other(String),
+}
+
+/// Turns unsolicited answers into events. `IF` answers carry
+/// the complete status, so they are compared against the last
+/// one to report only what changed.
+#[derive(Debug, Default)]
+pub(crate) struct EventDecoder {
+    frequency: Option<(Vfo, Frequency)>,
+    mode: Option<Mode>,
+    transmitting: Option<bool>,
+}
+
+impl EventDecoder {
+    pub(crate) fn decode(&mut self, answer: &str, events: &mut VecDeque<RadioEvent>) {
+        let other = || RadioEvent::Other(answer.to_owned());
+        let (prefix, params) = match (answer.get(..2), answer.get(2..answer.len() - 1)) {
+            (Some(prefix), Some(params)) => (prefix, params),
+            _ => return events.push_back(other()),
+        };
+
+        match prefix {
+            "FA" | "FB" => match Frequency::from_cat(params) {
+                Ok(frequency) => {
+                    let vfo = if prefix == "FA" { Vfo::A } else { Vfo::B };
+                    self.frequency(vfo, frequency, events);
+                },
+                Err(_) => events.push_back(other()),
+            },
+            "MD" => match Mode::from_cat(params) {
+                Ok(mode) => self.mode(mode, events),
+                Err(_) => events.push_back(other()),
+            },
+            "IF" => match TransceiverStatus::from_cat(params) {
+                Ok(status) => {
+                    self.frequency(status.vfo, status.frequency, events);
+                    self.mode(status.mode, events);
+                    self.transmitting(status.transmitting, events);
+                },
+                Err(_) => events.push_back(other()),
+            },
+            _ => events.push_back(other()),
+        }
+    }
+
+    fn frequency(&mut self, vfo: Vfo, frequency: Frequency, events: &mut VecDeque<RadioEvent>) {
+        if self.frequency != Some((vfo, frequency)) {
+            self.frequency = Some((vfo, frequency));
+            events.push_back(RadioEvent::FrequencyChanged { vfo, frequency });
+        }
+    }
+
+    fn mode(&mut self, mode: Mode, events: &mut VecDeque<RadioEvent>) {
+        if self.mode != Some(mode) {
+            self.mode = Some(mode);
+            events.push_back(RadioEvent::ModeChanged(mode));
+        }
+    }
+
+    fn transmitting(&mut self, transmitting: bool, events: &mut VecDeque<RadioEvent>) {
+        if self.transmitting != Some(transmitting) {
+            self.transmitting = Some(transmitting);
+            events.push_back(RadioEvent::TransmitChanged(transmitting));
+        }
+    }
+}
+
+impl<T: Transport> TS480<T> {
+    /// Waits for the next unsolicited report from the radio.
+    ///
+    /// Reports that arrived while waiting for the answer to
+    /// another command are returned first.
+    pub fn next_event(&mut self) -> RadioResult<RadioEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+
+            // A report still arriving when the timeout passes is
+            // completed by the next call
+            let answer = self.poll_answer()?;
+            self.decoder.decode(answer.as_str(), &mut self.events);
+        }
+    }
+
+    /// Returns an iterator over the radio's unsolicited reports.
+    /// Read timeouts are skipped; the iterator ends when the
+    /// connection is closed.
+    ///
+    /// Auto Information mode must have been turned on with
+    /// `set_auto_information`.
+    pub fn events(&mut self) -> Events<'_, T> {
+        Events { radio: self }
+    }
+
+    /// Keeps an unsolicited answer received while waiting for
+    /// the answer to a command.
+    pub(crate) fn queue_event(&mut self, answer: &str) {
+        self.decoder.decode(answer, &mut self.events);
+    }
+}
+
+/// Iterator returned by `TS480::events`.
+pub struct Events<'a, T: Transport + 'a> {
+    radio: &'a mut TS480<T>,
+}
+
+impl<'a, T: Transport> Iterator for Events<'a, T> {
+    type Item = RadioResult<RadioEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.radio.next_event() {
+                Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => return None,
+                Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::WouldBlock => continue,
+                result => return Some(result),
+            }
+        }
+    }
+}
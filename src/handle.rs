@@ -0,0 +1,421 @@
+//! A cloneable handle for sharing one radio between threads.
+//!
+//! ```no_run
+//! use ts480::{RadioHandle, TS480};
+//! use std::thread;
+//!
+//! let radio = RadioHandle::spawn(TS480::new("/dev/ttyUSB0").unwrap());
+//!
+//! let logger = radio.clone();
+//! thread::spawn(move || {
+//!     let frequency = logger.call(TS480::read_frequency_a).unwrap();
+//!     println!("{}", frequency);
+//! });
+//!
+//! radio.call(|r| r.set_power(50)).unwrap();
+//! ```
+
+use crate::serial::SystemPort;
+use crate::transport::Transport;
+use crate::{RadioError, RadioEvent, RadioResult, TS480};
+
+use std::collections::VecDeque;
+use std::io;
+use std::ops::Deref;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the worker listens for Auto Information
+/// reports before checking for commands again
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+type Job<T> = Box<dyn FnOnce(&mut TS480<T>) + Send>;
+
+/// Handle to a radio owned by a worker thread, which runs the
+/// commands of all clones of the handle one at a time.
+///
+/// While anyone is subscribed to events and Auto Information mode
+/// is on, the worker listens for reports between commands. How soon
+/// it notices a new command then depends on the transport: a serial
+/// port may block for its whole read timeout.
+///
+/// The worker also enforces the maximum transmit time set with
+/// `TS480Options::max_transmit_time` while the handle is idle, for
+/// transports the driver's own watchdog thread cannot use.
+pub struct RadioHandle<T: Transport + Send + 'static = SystemPort> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T: Transport> {
+    queue: Mutex<Queue<T>>,
+    ready: Condvar,
+}
+
+struct Queue<T: Transport> {
+    /// Jobs run before any in `normal`, such as PTT off
+    priority: VecDeque<Job<T>>,
+    normal: VecDeque<Job<T>>,
+    subscribers: Vec<Subscriber>,
+    handles: usize,
+    stopped: bool,
+}
+
+/// Channel receiving the radio's reports, from `RadioHandle::subscribe`.
+/// Dereferences to the `Receiver`.
+pub struct Subscription {
+    receiver: Receiver<RadioEvent>,
+    /// Dropped with the subscription, telling the worker
+    /// to stop sending to it
+    _alive: Arc<()>,
+}
+
+struct Subscriber {
+    sender: Sender<RadioEvent>,
+    alive: Weak<()>,
+}
+
+impl<T: Transport + Send + 'static> RadioHandle<T> {
+    /// Moves `radio` to a new worker thread. The thread stops,
+    /// dropping the radio, when the last handle is dropped.
+    pub fn spawn(radio: TS480<T>) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                priority: VecDeque::new(),
+                normal: VecDeque::new(),
+                subscribers: Vec::new(),
+                handles: 1,
+                stopped: false,
+            }),
+            ready: Condvar::new(),
+        });
+
+        let worker = shared.clone();
+        thread::spawn(move || run(radio, &worker));
+
+        RadioHandle { shared }
+    }
+
+    /// Runs `command` on the worker thread after the commands
+    /// queued before it, and returns its result, e.g.
+    /// `handle.call(TS480::read_mode)`.
+    pub fn call<F, R>(&self, command: F) -> RadioResult<R>
+        where F: FnOnce(&mut TS480<T>) -> RadioResult<R> + Send + 'static, R: Send + 'static
+    {
+        self.enqueue(false, command)
+    }
+
+    /// Runs `command` ahead of all queued commands that were not
+    /// themselves given priority.
+    pub fn call_priority<F, R>(&self, command: F) -> RadioResult<R>
+        where F: FnOnce(&mut TS480<T>) -> RadioResult<R> + Send + 'static, R: Send + 'static
+    {
+        self.enqueue(true, command)
+    }
+
+    /// Switches the radio to receive ahead of queued commands.
+    pub fn ptt_off(&self) -> RadioResult<()> {
+        self.call_priority(TS480::ptt_off)
+    }
+
+    /// Returns a channel receiving the reports the radio sends in
+    /// Auto Information mode, which must be turned on with
+    /// `set_auto_information`. The worker stops listening for
+    /// reports soon after the last subscription is dropped.
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = mpsc::channel();
+        let alive = Arc::new(());
+        let mut queue = self.shared.lock();
+        queue.prune_subscribers();
+        queue.subscribers.push(Subscriber { sender, alive: Arc::downgrade(&alive) });
+        self.shared.ready.notify_one();
+        Subscription { receiver, _alive: alive }
+    }
+
+    fn enqueue<F, R>(&self, priority: bool, command: F) -> RadioResult<R>
+        where F: FnOnce(&mut TS480<T>) -> RadioResult<R> + Send + 'static, R: Send + 'static
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job: Job<T> = Box::new(move |radio| {
+            let _ = sender.send(command(radio));
+        });
+
+        {
+            let mut queue = self.shared.lock();
+            if queue.stopped {
+                return Err(stopped());
+            }
+            if priority {
+                queue.priority.push_back(job);
+            } else {
+                queue.normal.push_back(job);
+            }
+        }
+        self.shared.ready.notify_one();
+
+        receiver.recv().map_err(|_| stopped())?
+    }
+}
+
+impl<T: Transport + Send + 'static> Clone for RadioHandle<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().handles += 1;
+        RadioHandle { shared: self.shared.clone() }
+    }
+}
+
+impl<T: Transport + Send + 'static> Drop for RadioHandle<T> {
+    fn drop(&mut self) {
+        self.shared.lock().handles -= 1;
+        self.shared.ready.notify_one();
+    }
+}
+
+impl Deref for Subscription {
+    type Target = Receiver<RadioEvent>;
+
+    fn deref(&self) -> &Receiver<RadioEvent> {
+        &self.receiver
+    }
+}
+
+impl<T: Transport> Queue<T> {
+    /// Forgets the subscribers whose subscriptions were dropped.
+    fn prune_subscribers(&mut self) {
+        self.subscribers.retain(|subscriber| subscriber.alive.strong_count() > 0);
+    }
+}
+
+impl<T: Transport> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Marks the queue as stopped when the worker exits, even by
+/// panicking, so that callers get an error instead of waiting.
+struct StopGuard<'a, T: Transport + 'a>(&'a Shared<T>);
+
+impl<'a, T: Transport> Drop for StopGuard<'a, T> {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock();
+        queue.stopped = true;
+        queue.priority.clear();
+        queue.normal.clear();
+        queue.subscribers.clear();
+    }
+}
+
+/// What the worker does next
+enum Work<T: Transport> {
+    Run(Job<T>),
+    Listen,
+    Watchdog,
+}
+
+fn run<T: Transport>(mut radio: TS480<T>, shared: &Shared<T>) {
+    let _guard = StopGuard(shared);
+
+    loop {
+        let work = {
+            let mut queue = shared.lock();
+            loop {
+                if let Some(job) = queue.priority.pop_front().or_else(|| queue.normal.pop_front()) {
+                    break Work::Run(job);
+                }
+                if queue.handles == 0 {
+                    return;
+                }
+                // Checked on every poll, so listening stops with
+                // the last subscription
+                queue.prune_subscribers();
+                if radio.auto_information && !queue.subscribers.is_empty() {
+                    break Work::Listen;
+                }
+                queue = match radio.watchdog_deadline() {
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            break Work::Watchdog;
+                        }
+                        shared.ready.wait_timeout(queue, deadline - now).unwrap_or_else(|e| e.into_inner()).0
+                    },
+                    None => shared.ready.wait(queue).unwrap_or_else(|e| e.into_inner()),
+                };
+            }
+        };
+
+        match work {
+            Work::Run(job) => job(&mut radio),
+            Work::Listen => listen(&mut radio),
+            Work::Watchdog => {
+                let _ = radio.check_watchdog();
+            },
+        }
+
+        if !radio.events.is_empty() {
+            let mut queue = shared.lock();
+            for event in radio.events.drain(..) {
+                queue.subscribers.retain(|subscriber| subscriber.sender.send(event.clone()).is_ok());
+            }
+        }
+    }
+}
+
+/// Waits briefly for an Auto Information report.
+fn listen<T: Transport>(radio: &mut TS480<T>) {
+    let timeout = radio.timeout();
+    radio.set_timeout(POLL_INTERVAL);
+
+    // A report cut off by the short timeout is kept and
+    // completed on the next poll
+    match radio.poll_answer() {
+        Ok(answer) => radio.queue_event(answer.as_str()),
+        Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut => {},
+        Err(_) => thread::sleep(POLL_INTERVAL),
+    }
+
+    radio.set_timeout(timeout);
+}
+
+fn stopped() -> RadioError {
+    io::Error::new(io::ErrorKind::BrokenPipe, "radio worker thread stopped").into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Simulator;
+    use crate::{Frequency, Mode, PttSource, Vfo};
+
+    use std::sync::mpsc::RecvTimeoutError;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn spawn() -> RadioHandle<Simulator> {
+        RadioHandle::spawn(TS480::with_transport(Simulator::new()))
+    }
+
+    /// Waits until `check` holds for the queue.
+    fn wait_for<F: Fn(&Queue<Simulator>) -> bool>(handle: &RadioHandle<Simulator>, check: F) {
+        let start = Instant::now();
+        while !check(&handle.shared.lock()) {
+            assert!(start.elapsed() < TIMEOUT, "timed out waiting for the queue");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn runs_ptt_off_ahead_of_queued_commands() {
+        let handle = spawn();
+        handle.call(|r| r.ptt_on(PttSource::Microphone)).unwrap();
+
+        // Keeps the worker busy while commands queue up behind it
+        let (release, blocked) = mpsc::channel::<()>();
+        let (started, running) = mpsc::channel();
+        let busy = handle.clone();
+        let worker = thread::spawn(move || busy.call(move |_| {
+            started.send(()).unwrap();
+            let _ = blocked.recv();
+            Ok(())
+        }));
+        running.recv_timeout(TIMEOUT).unwrap();
+
+        let transmitting = Arc::new(Mutex::new(Vec::new()));
+        let mut clients = Vec::new();
+        for count in 1..=2 {
+            let client = handle.clone();
+            let transmitting = transmitting.clone();
+            clients.push(thread::spawn(move || client.call(move |r| {
+                transmitting.lock().unwrap().push(r.transport().state().transmitting);
+                Ok(())
+            })));
+            wait_for(&handle, |queue| queue.normal.len() == count);
+        }
+
+        let unkey = handle.clone();
+        let ptt_off = thread::spawn(move || unkey.ptt_off());
+        wait_for(&handle, |queue| !queue.priority.is_empty());
+        release.send(()).unwrap();
+
+        ptt_off.join().unwrap().unwrap();
+        worker.join().unwrap().unwrap();
+        for client in clients {
+            client.join().unwrap().unwrap();
+        }
+        // The commands queued first ran after the radio was unkeyed
+        assert_eq!(*transmitting.lock().unwrap(), vec![false, false]);
+    }
+
+    #[test]
+    fn sends_events_to_every_subscriber() {
+        let handle = spawn();
+        let first = handle.subscribe();
+        let second = handle.subscribe();
+        handle.call(|r| r.set_auto_information(2)).unwrap();
+        handle.call(|r| {
+            assert!(r.transport_mut().front_panel("FA00007074000"));
+            Ok(())
+        }).unwrap();
+
+        let expected = RadioEvent::FrequencyChanged { vfo: Vfo::A, frequency: Frequency::from_hz(7_074_000).unwrap() };
+        assert_eq!(first.recv_timeout(TIMEOUT).unwrap(), expected);
+        assert_eq!(second.recv_timeout(TIMEOUT).unwrap(), expected);
+
+        // Reports read along with an answer are sent too
+        handle.call(|r| {
+            assert!(r.transport_mut().front_panel("MD3"));
+            r.read_frequency_a()
+        }).unwrap();
+        for events in &[first, second] {
+            // After any other reports of the frequency change
+            while events.recv_timeout(TIMEOUT).unwrap() != RadioEvent::ModeChanged(Mode::Cw) {}
+        }
+    }
+
+    #[test]
+    fn prunes_dropped_subscribers() {
+        let handle = spawn();
+        handle.call(|r| r.set_auto_information(2)).unwrap();
+        let first = handle.subscribe();
+        let second = handle.subscribe();
+        drop(first);
+
+        let third = handle.subscribe();
+        assert_eq!(handle.shared.lock().subscribers.len(), 2);
+
+        // Without any event being sent
+        drop(second);
+        drop(third);
+        wait_for(&handle, |queue| queue.subscribers.is_empty());
+    }
+
+    #[test]
+    fn stops_when_last_handle_is_dropped() {
+        let handle = spawn();
+        let events = handle.subscribe();
+        let clone = handle.clone();
+        drop(handle);
+        assert_eq!(events.recv_timeout(Duration::from_millis(100)), Err(RecvTimeoutError::Timeout));
+
+        drop(clone);
+        assert_eq!(events.recv_timeout(TIMEOUT), Err(RecvTimeoutError::Disconnected));
+    }
+
+    #[test]
+    fn stops_when_worker_panics() {
+        let handle = spawn();
+        let events = handle.subscribe();
+
+        match handle.call(|_| -> RadioResult<()> { panic!("command failed") }) {
+            Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::BrokenPipe => {},
+            result => panic!("expected BrokenPipe, got {:?}", result),
+        }
+
+        // The caller may hear of it while the worker is unwinding
+        wait_for(&handle, |queue| queue.stopped);
+        assert!(handle.call(TS480::read_mode).is_err());
+        assert_eq!(events.recv_timeout(TIMEOUT), Err(RecvTimeoutError::Disconnected));
+    }
+}
@@ -2,146 +2,301 @@ extern crate ascii;
 use ascii::{AsciiString, ToAsciiChar};
 
 extern crate serial;
-use serial::{
-    SystemPort, SerialPort, PortSettings,
-    BaudRate, CharSize, Parity,
-    StopBits, FlowControl
-};
+use serial::SystemPort;
+pub use serial::{BaudRate, FlowControl, StopBits};
+
+#[cfg(unix)]
+extern crate libc;
 
-use std::io::{Read, Write};
+use std::collections::VecDeque;
+use std::io;
 use std::ffi::{OsStr, OsString};
+use std::time::{Duration, Instant};
 
-pub type RadioResult<T> = serial::Result<T>;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod channels;
+mod commands;
+pub mod console;
+mod error;
+pub mod events;
+pub mod handle;
+mod memory;
+mod menu;
+mod meter;
+mod options;
+mod ptt;
+pub mod recording;
+mod safety;
+pub mod sim;
+mod snapshot;
+mod status;
+pub mod transport;
+mod types;
+mod watchdog;
+pub use crate::error::{RadioError, RadioResult};
+pub use crate::events::RadioEvent;
+pub use crate::handle::{RadioHandle, Subscription};
+pub use crate::memory::{MemoryChannel, MEMORY_CHANNELS, MEMORY_NAME_LENGTH};
+pub use crate::menu::{ExMenu, MenuValues, LAST_MENU};
+pub use crate::meter::{
+    MeterKind, MeterSample, MeterSampler, MeterStatistics, SMeterReading, METER_MAX,
+};
+pub use crate::options::{TS480Options, BAUD_RATES};
+pub use crate::ptt::{LineUse, PttSource};
+pub use crate::safety::{BandPrivileges, BandSegment};
+pub use crate::snapshot::{Difference, RigSnapshot};
+pub use crate::status::TransceiverStatus;
+pub use crate::transport::Transport;
+pub use crate::types::{Antenna, Frequency, Mode, ToneMode, Vfo, TONE_FREQUENCIES};
 
-pub struct TS480 {
-    port: SystemPort,
-    port_name: OsString,
+pub struct TS480<T: Transport = SystemPort> {
+    port: T,
+    port_name: Option<OsString>,
+    options: TS480Options,
+    buffer: Vec<u8>,
+    auto_information: bool,
+    events: VecDeque<RadioEvent>,
+    decoder: events::EventDecoder,
+    transmitting: Option<Instant>,
+    privileges: Option<safety::BandPrivileges>,
+    watchdog: Option<watchdog::Watchdog>,
+    /// Set when the watchdog switched the radio to receive, so
+    /// that it is not keyed again until `ptt_off` or `RX;`
+    watchdog_fired: bool,
+    /// Set when an answer timed out, so that a late answer is
+    /// discarded before the next command
+    stale_input: bool,
 }
 
-impl TS480 {
+impl TS480<SystemPort> {
     /// Attempts to connect to the radio using the specified port.
     /// On *nix systems, this should be the path to a
     /// device file, such as `/dev/ttyS0`.
     ///
     /// On Windows, this should be the name of a
     /// COM port, such as `COM1`
-    pub fn new<T: AsRef<OsStr> + ?Sized>(port: &T) -> serial::Result<Self> {
-        let mut serial_port = serial::open(port)?;
+    ///
+    /// The port is configured for 4800 baud, 8N2 with hardware
+    /// flow control; use `TS480Options` for other settings.
+    pub fn new<T: AsRef<OsStr> + ?Sized>(port: &T) -> RadioResult<Self> {
+        TS480Options::new().open(port)
+    }
 
-        let settings = PortSettings {
-            baud_rate: BaudRate::Baud4800,
-            char_size: CharSize::Bits8,
-            parity: Parity::ParityNone,
-            stop_bits: StopBits::Stop2,
-            flow_control: FlowControl::FlowHardware,
-        };
+    /// Attempts to reconnect to the radio using the originally-specified
+    /// port, applying the serial port settings it was opened with.
+    pub fn reconnect(&mut self) -> RadioResult<()> {
+        if let Some(ref port_name) = self.port_name {
+            let mut port = serial::open(port_name)?;
+            self.options.configure(&mut port)?;
+            self.port = port;
+            // Its handle to the old port is useless now
+            self.watchdog = None;
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+}
 
-        let _ = serial_port.configure(&settings);
+impl<T: Transport> TS480<T> {
+    /// Uses an already-open connection to the radio, such as
+    /// a TCP stream or a `transport::MemoryTransport`.
+    pub fn with_transport(transport: T) -> Self {
+        TS480 {
+            port: transport,
+            port_name: None,
+            options: TS480Options::default(),
+            buffer: Vec::new(),
+            auto_information: false,
+            events: VecDeque::new(),
+            decoder: events::EventDecoder::default(),
+            transmitting: None,
+            privileges: None,
+            watchdog: None,
+            watchdog_fired: false,
+            stale_input: false,
+        }
+    }
 
-        Ok(TS480 {
-            port: serial_port,
-            port_name: OsString::from(port),
-        })
+    /// Returns a reference to the underlying transport.
+    pub fn transport(&self) -> &T {
+        &self.port
     }
 
-    /// Attempts to reconnect to the radio using the originally-specified port
-    pub fn reconnect(&mut self) -> serial::Result<()> {
-        self.port = serial::open(&self.port_name)?;
-        Ok(())
+    /// Returns a mutable reference to the underlying transport.
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.port
     }
 
-    // /// Sets the internal antenna tuner status.
-    // ///
-    // /// p1: 0 = RX-AT THRU; 1 = RX-AT IN
-    // ///
-    // /// p2: 0 = TX-AT THRU; 1 = TX-AT IN
-    // ///
-    // /// p3: 0 = Stop tuning; 1 = Start tuning
-    // pub fn set_tuner_status(&mut self, p1: u8, p2: u8, p3: u8) -> serial::Result<()> {
-    //     self.transmit(&format!("AC{}{}{};", p1, p2, p3))?;
-    //     Ok(Ok(()))
-    // }
+    /// Returns how long to wait for each answer.
+    pub fn timeout(&self) -> Duration {
+        self.options.timeout
+    }
 
-    /// Selects the antenna connector ANT1/ANT2
-    ///
-    /// p1: 0 = ANT1; 1 = ANT2
-    pub fn set_antenna(&mut self, p1: u8) -> serial::Result<()> {
-        Ok(self.transmit(&format!("AN{};", p1))?)
+    /// Sets how long to wait for each answer before failing with
+    /// a `TimedOut` error. The transport must not block longer than
+    /// this in a single read; serial ports opened by this crate use
+    /// the timeout set with `TS480Options::timeout`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.options.timeout = timeout;
+    }
+
+    /// Sets how many times a query is repeated when the radio
+    /// answers `E;` or `O;`.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.options.retries = retries;
     }
 
-    // pub fn read_antenna(&mut self) -> serial::Result<u8> {
-    //     let _ = self.transmit("AN;")?;
-    //     let data = self.receive()?;
-    // }
+    /// Sends `command` and waits for the radio's answer.
+    ///
+    /// In Auto Information mode the next answer may be an
+    /// unsolicited report rather than the answer to `command`.
+    pub fn query(&mut self, command: &str) -> RadioResult<AsciiString> {
+        self.transmit(command)?;
+        self.receive()
+    }
 
-    /// Moves down the frequency band
-    pub fn frequency_down(&mut self) -> serial::Result<()> {
-        self.transmit("BD;")
+    /// Receives a single answer from the radio, including its
+    /// terminating `;`. Any bytes read past the terminator are
+    /// kept for the next call.
+    ///
+    /// Bytes that cannot start an answer are skipped, and frames
+    /// containing non-printable characters are discarded, so the
+    /// driver resynchronises at the next `;` after line noise. If no
+    /// answer arrives within the timeout, a partly received answer
+    /// is discarded as well, and so is any input still unread when
+    /// the next command is sent.
+    ///
+    /// The radio's `?;`, `E;` and `O;` answers are returned as the
+    /// corresponding `RadioError`.
+    pub fn receive(&mut self) -> RadioResult<AsciiString> {
+        self.check_watchdog()?;
+        match self.read_answer() {
+            Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut => Err(self.timed_out()),
+            result => result,
+        }
     }
 
-    /// Moves up the frequency band
-    pub fn frequency_up(&mut self) -> serial::Result<()> {
-        self.transmit("BU;")
+    /// Receives a single answer like `receive`, but keeps a partly
+    /// received answer when the timeout passes, so that reports can
+    /// be polled for with a timeout shorter than an answer takes.
+    pub(crate) fn poll_answer(&mut self) -> RadioResult<AsciiString> {
+        self.check_watchdog()?;
+        self.read_answer()
     }
 
-    /// Attempts to receive data from the radio. Currently, this
-    /// blocks indefinitely until the serial port's CTS pin goes true.
-    pub fn receive(&mut self) -> serial::Result<AsciiString> {
-        let mut buf = Vec::new();
-        self.port.set_rts(false)?;
-        // while ! self.port.read_cts()? {}
-        self.port.read_to_end(&mut buf)?;
+    fn read_answer(&mut self) -> RadioResult<AsciiString> {
+        let deadline = Instant::now() + self.options.timeout;
 
-        let mut ascii = AsciiString::new();
-        for num in buf {
-            if let Ok(ascii_char) = num.to_ascii_char() {
-                ascii.push(ascii_char);
+        loop {
+            if let Some(ascii) = take_frame(&mut self.buffer) {
+                return match RadioError::from_answer(ascii.as_str()) {
+                    Some(e) => Err(e),
+                    None => Ok(ascii),
+                };
+            }
+
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for answer").into());
+            }
+
+            let mut chunk = [0; 64];
+            match self.port.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "connection closed before answer was terminated",
+                ).into()),
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e.into()),
             }
         }
+    }
 
-        Ok(ascii)
+    /// Discards the partly received answer, and arranges for a late
+    /// answer to be discarded before the next command is sent.
+    pub(crate) fn timed_out(&mut self) -> RadioError {
+        self.buffer.clear();
+        self.stale_input = true;
+        io::Error::new(io::ErrorKind::TimedOut, "timed out waiting for answer").into()
     }
 
-    pub fn transmit(&mut self, data: &str) -> serial::Result<()> {
-        self.port.set_rts(true)?;
-        self.port.write(data.as_bytes())?;
-        Ok(())
+    /// Sends `data`, one or more commands each terminated by `;`.
+    ///
+    /// `TX` and `RX` commands update the transmit state used by the
+    /// watchdog and `Drop`, and `TX` is refused if the band privileges
+    /// do not allow transmitting on the current frequency, as are
+    /// commands that would retune the radio out of them while
+    /// transmitting. After the watchdog has switched the radio to
+    /// receive, `TX` is refused until `RX` is sent.
+    pub fn transmit(&mut self, data: &str) -> RadioResult<()> {
+        self.check_watchdog()?;
+
+        let mut keyed = None;
+        let mut refused = self.watchdog_fired;
+        for command in data.split(';').map(str::trim) {
+            if command.starts_with("TX") {
+                if refused {
+                    return Err(ptt::watchdog_refused());
+                }
+                keyed = Some(true);
+            } else if command == "RX" {
+                keyed = Some(false);
+                refused = false;
+            }
+        }
+        self.check_commands(data)?;
+
+        if self.stale_input {
+            self.stale_input = false;
+            self.buffer.clear();
+            self.port.discard_input()?;
+        }
+        self.write_port(keyed, |port| port.write_all(data.as_bytes()))
     }
+}
+
+/// Takes the first complete answer, including its `;`, out of
+/// bytes received from the radio.
+///
+/// Bytes that cannot start an answer are skipped, and frames
+/// containing non-printable characters are discarded, so that
+/// line noise only costs the answer it hit.
+pub(crate) fn take_frame(buffer: &mut Vec<u8>) -> Option<AsciiString> {
+    while let Some(end) = buffer.iter().position(|&b| b == b';') {
+        let frame: Vec<u8> = buffer.drain(..end + 1).collect();
+        let start = frame.iter().position(|&b| b.is_ascii_uppercase() || b == b'?').unwrap_or(end);
+        if end - start < 1 || !frame[start..].iter().all(|&b| b == b' ' || b.is_ascii_graphic()) {
+            continue;
+        }
 
-    // #[allow(dead_code)]
-    // fn check_for_error(e: &str) -> Option<RadioError> {
-    //     match e {
-    //         "?;" => Some(RadioError::SyntaxOrStatus),
-    //         "E;" => Some(RadioError::CommError),
-    //         "O;" => Some(RadioError::ProcIncomplete),
-    //         _ => None,
-    //     }
-    // }
+        let mut ascii = AsciiString::new();
+        for &num in &frame[start..] {
+            if let Ok(ascii_char) = num.to_ascii_char() {
+                ascii.push(ascii_char);
+            }
+        }
+        return Some(ascii);
+    }
+    None
 }
 
-impl Drop for TS480 {
+impl<T: Transport> Drop for TS480<T> {
+    /// Switches the radio back to receive if the driver keyed it,
+    /// and releases the lines used for keying. This also happens
+    /// when a panic unwinds past the `TS480`, but not when the
+    /// process aborts or exits.
+    ///
+    /// `RX;` is not sent otherwise, so that a program that only
+    /// reads from the radio does not cut off the operator.
     #[allow(unused_must_use)]
     fn drop(&mut self) {
-        self.port.set_rts(false);
-        self.port.set_dtr(true);
+        if self.transmitting.is_some() {
+            self.ptt_off();
+        } else {
+            self.release_ptt_lines();
+        }
     }
 }
-
-//pub enum RadioError {
-//    /// `?;` response from the radio.
-//    ///
-//    /// Indicates either the command syntax was incorrect or
-//    /// the command was not executed due to the tranceiver's current status
-//    SyntaxOrStatus,
-
-//    /// `E;` response from the radio.
-//    ///
-//    /// Indicates a communcation error.
-//    CommError,
-
-//    /// `O;` response from the radio.
-//    ///
-//    /// Indicates receive data was sent but
-//    /// processing was not completed.
-//    ProcIncomplete,
-//}
@@ -0,0 +1,217 @@
+//! Memory channels, read and written with the `MR` and `MW` commands.
+
+use crate::commands::{check_range, parse_field, parse_flag};
+use crate::transport::Transport;
+use crate::types::{Frequency, Mode, ToneMode, TONE_FREQUENCIES};
+use crate::{RadioError, RadioResult, TS480};
+
+use std::fmt;
+
+/// Number of memory channels: 00 - 99, then the
+/// program scan channels P0 - P9 as 100 - 109.
+pub const MEMORY_CHANNELS: u8 = 110;
+
+/// Longest name the radio stores for a memory channel
+pub const MEMORY_NAME_LENGTH: usize = 8;
+
+/// The contents of a programmed memory channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryChannel {
+    pub rx_frequency: Frequency,
+
+    /// Transmit frequency of a split channel; `None`
+    /// to transmit on the receive frequency
+    pub tx_frequency: Option<Frequency>,
+
+    pub mode: Mode,
+
+    /// Skipped while scanning
+    pub lockout: bool,
+
+    pub tone: ToneMode,
+
+    /// Tone frequency number, 0 - 41
+    pub tone_number: u8,
+
+    /// CTCSS frequency number, 0 - 41
+    pub ctcss_number: u8,
+
+    /// Narrow FM filter
+    pub fm_narrow: bool,
+
+    /// Channel name, up to 8 characters
+    pub name: String,
+}
+
+impl MemoryChannel {
+    /// Creates a simplex channel with tones off and no name.
+    pub fn new(frequency: Frequency, mode: Mode) -> Self {
+        MemoryChannel {
+            rx_frequency: frequency,
+            tx_frequency: None,
+            mode,
+            lockout: false,
+            tone: ToneMode::Off,
+            tone_number: 0,
+            ctcss_number: 0,
+            fm_narrow: false,
+            name: String::new(),
+        }
+    }
+
+    /// Checks the channel against the limits of the radio.
+    pub fn validate(&self) -> RadioResult<()> {
+        check_range("tone frequency number", self.tone_number, 0, 41)?;
+        check_range("CTCSS frequency number", self.ctcss_number, 0, 41)?;
+
+        if self.name.chars().count() > MEMORY_NAME_LENGTH {
+            return Err(RadioError::InvalidParameter(
+                format!("memory name must be at most {} characters, got {:?}", MEMORY_NAME_LENGTH, self.name)
+            ));
+        }
+        if self.name.chars().any(|c| !(' '..='~').contains(&c) || c == ';') {
+            return Err(RadioError::InvalidParameter(
+                format!("memory name must be printable ASCII without ';', got {:?}", self.name)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the parameters of the `MW` command storing
+    /// `frequency` with this channel's settings.
+    fn to_cat(&self, frequency: Frequency) -> String {
+        format!(
+            "{}{}{}{}{:02}{:02}000{}0000000000000{}",
+            frequency.to_cat(),
+            self.mode.to_cat(),
+            self.lockout as u8,
+            self.tone.to_cat(),
+            self.tone_number,
+            self.ctcss_number,
+            self.fm_narrow as u8,
+            self.name,
+        )
+    }
+}
+
+/// Formats the channel on one line, e.g.
+/// `29.620000 MHz FM, TX 29.520000 MHz, Tone 88.5 Hz, "REPEATER"`
+impl fmt::Display for MemoryChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.rx_frequency, self.mode)?;
+        if let Some(tx_frequency) = self.tx_frequency {
+            write!(f, ", TX {}", tx_frequency)?;
+        }
+        let number = match self.tone {
+            ToneMode::Off => None,
+            ToneMode::Tone => Some(self.tone_number),
+            ToneMode::Ctcss => Some(self.ctcss_number),
+        };
+        if let Some(number) = number {
+            let tenths = TONE_FREQUENCIES.get(number as usize).cloned().unwrap_or(0);
+            write!(f, ", {} {}.{} Hz", self.tone, tenths / 10, tenths % 10)?;
+        }
+        if self.fm_narrow {
+            f.write_str(", narrow")?;
+        }
+        if self.lockout {
+            f.write_str(", lockout")?;
+        }
+        if !self.name.is_empty() {
+            write!(f, ", {:?}", self.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the fields of an `MR` answer following the channel
+/// number. Returns `None` for an unprogrammed channel.
+///
+/// | Characters | Field                         |
+/// |------------|-------------------------------|
+/// | 0 - 10     | Frequency in Hz               |
+/// | 11         | Mode                          |
+/// | 12         | Lockout on/off                |
+/// | 13         | 0 = Off; 1 = Tone; 2 = CTCSS  |
+/// | 14 - 15    | Tone frequency number         |
+/// | 16 - 17    | CTCSS frequency number        |
+/// | 18 - 20    | Always 000                    |
+/// | 21         | 0 = FM wide; 1 = FM narrow    |
+/// | 22 - 34    | Always 0                      |
+/// | 35 -       | Name, up to 8 characters      |
+fn parse_memory(params: &str) -> RadioResult<Option<MemoryChannel>> {
+    if params.len() < 35 || !params.is_ascii() {
+        return Err(RadioError::UnexpectedAnswer(params.to_owned()));
+    }
+
+    let hz: u64 = parse_field(params, 0..11)?;
+    if hz == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(MemoryChannel {
+        rx_frequency: Frequency::from_cat(&params[0..11])?,
+        tx_frequency: None,
+        mode: Mode::from_cat(&params[11..12])?,
+        lockout: parse_flag(params, 12)?,
+        tone: ToneMode::from_cat(&params[13..14])?,
+        tone_number: parse_field(params, 14..16)?,
+        ctcss_number: parse_field(params, 16..18)?,
+        fm_narrow: parse_flag(params, 21)?,
+        name: params[35..].trim_end().to_owned(),
+    }))
+}
+
+impl<T: Transport> TS480<T> {
+    /// Reads a memory channel, 0 - 109. Returns `None`
+    /// if the channel is not programmed.
+    pub fn read_memory(&mut self, channel: u8) -> RadioResult<Option<MemoryChannel>> {
+        check_range("memory channel", channel, 0, MEMORY_CHANNELS - 1)?;
+
+        let mut memory = match self.read_memory_record(0, channel)? {
+            Some(memory) => memory,
+            None => return Ok(None),
+        };
+
+        let tx_frequency = self.read_memory_record(1, channel)?.map(|tx| tx.rx_frequency);
+        if tx_frequency != Some(memory.rx_frequency) {
+            memory.tx_frequency = tx_frequency;
+        }
+        Ok(Some(memory))
+    }
+
+    /// Programs a memory channel, 0 - 109. Both the receive
+    /// and transmit frequency are written, so a channel that
+    /// was split before becomes simplex if `tx_frequency` is `None`.
+    pub fn write_memory(&mut self, channel: u8, memory: &MemoryChannel) -> RadioResult<()> {
+        check_range("memory channel", channel, 0, MEMORY_CHANNELS - 1)?;
+        memory.validate()?;
+
+        let tx_frequency = memory.tx_frequency.unwrap_or(memory.rx_frequency);
+        self.transmit(&format!("MW0{:03}{};", channel, memory.to_cat(memory.rx_frequency)))?;
+        self.transmit(&format!("MW1{:03}{};", channel, memory.to_cat(tx_frequency)))
+    }
+
+    /// Reads all memory channels, calling `progress` with the
+    /// number of channels read so far and the total after each one.
+    /// Element `n` of the result is channel `n`.
+    pub fn dump_memories<F>(&mut self, mut progress: F) -> RadioResult<Vec<Option<MemoryChannel>>>
+        where F: FnMut(usize, usize)
+    {
+        let total = MEMORY_CHANNELS as usize;
+        let mut memories = Vec::with_capacity(total);
+
+        for channel in 0..MEMORY_CHANNELS {
+            memories.push(self.read_memory(channel)?);
+            progress(memories.len(), total);
+        }
+        Ok(memories)
+    }
+
+    /// p1: 0 = Receive frequency; 1 = Transmit frequency
+    fn read_memory_record(&mut self, p1: u8, channel: u8) -> RadioResult<Option<MemoryChannel>> {
+        let prefix = format!("MR{}{:03}", p1, channel);
+        let params = self.read_parameters(&prefix)?;
+        parse_memory(&params)
+    }
+}
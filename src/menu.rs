@@ -0,0 +1,395 @@
+//! The extended menu, read and written with the `EX` command.
+
+use crate::transport::Transport;
+use crate::{RadioError, RadioResult, TS480};
+
+use std::fmt;
+
+/// Number of the highest menu item
+pub const LAST_MENU: u16 = 62;
+
+const OFF_1_TO_9: &[&str] = &["Off", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+const OFF_1_TO_7: &[&str] = &["Off", "1", "2", "3", "4", "5", "6", "7"];
+const OFF_1_2: &[&str] = &["Off", "1", "2"];
+const EQUALIZER: &[&str] = &["Off", "Hb1", "Hb2", "FP", "bb1", "bb2", "c", "U"];
+const KEYING_WEIGHT: &[&str] = &[
+    "Auto", "2.5", "2.6", "2.7", "2.8", "2.9", "3.0", "3.1",
+    "3.2", "3.3", "3.4", "3.5", "3.6", "3.7", "3.8", "3.9", "4.0",
+];
+
+/// An item of the extended menu. The discriminant is the menu number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ExMenu {
+    DisplayBrightness = 0,
+    KeyIllumination = 1,
+    TuningSteps = 2,
+    MultiChannelTuning = 3,
+    MultiChannelRounding = 4,
+    BroadcastStep9kHz = 5,
+    MemoryTemporaryChange = 6,
+    ProgramScanSlowdown = 7,
+    SlowdownRange = 8,
+    ProgramScanHold = 9,
+    ScanResume = 10,
+    BeepLevel = 11,
+    SidetoneVolume = 12,
+    MessageVolume = 13,
+    VoiceGuideVolume = 14,
+    VoiceGuideSpeed = 15,
+    VoiceGuideLanguage = 16,
+    AutoAnnouncement = 17,
+    MicUpDownSpeed = 18,
+    RxEqualizer = 19,
+    TxEqualizer = 20,
+    TxFilterBandwidth = 21,
+    FmMicGain = 22,
+    AutoMode = 23,
+    TxInhibit = 24,
+    SplitQuickOffset = 25,
+    AmCarrierLevel = 26,
+    FmDeviation = 27,
+    PowerOnMessage = 28,
+    DtmfMemory = 29,
+    DtmfSpeed = 30,
+    DtmfPause = 31,
+    DtmfMicControl = 32,
+    CwReverseSideband = 33,
+    CwPitch = 34,
+    CwRiseTime = 35,
+    CwKeyingWeight = 36,
+    CwReverseWeight = 37,
+    BugKey = 38,
+    TxPowerFineAdjustment = 39,
+    SsbToCwCorrection = 40,
+    AutoCwTx = 41,
+    TimeOutTimer = 42,
+    Transverter = 43,
+    TxHoldAfterTuning = 44,
+    TunerWhileReceiving = 45,
+    LinearDelayHf = 46,
+    LinearDelay50MHz = 47,
+    ConstantRecording = 48,
+    PlaybackRepeat = 49,
+    PlaybackInterval = 50,
+    KeyingPriority = 51,
+    DataInputLevel = 52,
+    DataOutputLevel = 53,
+    SplitTransfer = 54,
+    SplitTransferWrite = 55,
+    ComBaudRate = 56,
+    AutoPowerOff = 57,
+    PfAKey = 58,
+    PfBKey = 59,
+    MicPf1Key = 60,
+    MicPf2Key = 61,
+    MicPf3Key = 62,
+}
+
+/// The values an extended menu item accepts. Values are
+/// always numbered from 0, as sent in the `EX` command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuValues {
+    /// 0 = Off; 1 = On
+    OffOn,
+
+    /// Value `n` means `start + n * step` in `unit`,
+    /// for `n` below `count`
+    Steps { start: i32, step: i32, count: u16, unit: &'static str },
+
+    /// Value `n` means the `n`th choice
+    Choices(&'static [&'static str]),
+}
+
+impl MenuValues {
+    /// Returns the number of values, which are 0 to one less.
+    pub fn count(&self) -> u16 {
+        match *self {
+            MenuValues::OffOn => 2,
+            MenuValues::Steps { count, .. } => count,
+            MenuValues::Choices(choices) => choices.len() as u16,
+        }
+    }
+
+    /// Describes a value, e.g. `600 Hz` for value 4 of the CW pitch.
+    pub fn describe(&self, value: u16) -> String {
+        match *self {
+            MenuValues::OffOn => if value == 0 { "Off" } else { "On" }.to_owned(),
+            MenuValues::Steps { start, step, unit, .. } => {
+                let n = start + value as i32 * step;
+                if unit.is_empty() { n.to_string() } else { format!("{} {}", n, unit) }
+            },
+            MenuValues::Choices(choices) => {
+                choices.get(value as usize).map(|&choice| choice.to_owned()).unwrap_or_else(|| value.to_string())
+            },
+        }
+    }
+
+    /// Number of digits of the values in the `EX` command
+    fn width(&self) -> usize {
+        (self.count() - 1).to_string().len()
+    }
+}
+
+impl ExMenu {
+    /// All menu items, in menu number order.
+    pub const ALL: [ExMenu; 63] = [
+        ExMenu::DisplayBrightness, ExMenu::KeyIllumination, ExMenu::TuningSteps,
+        ExMenu::MultiChannelTuning, ExMenu::MultiChannelRounding, ExMenu::BroadcastStep9kHz,
+        ExMenu::MemoryTemporaryChange, ExMenu::ProgramScanSlowdown, ExMenu::SlowdownRange,
+        ExMenu::ProgramScanHold, ExMenu::ScanResume, ExMenu::BeepLevel,
+        ExMenu::SidetoneVolume, ExMenu::MessageVolume, ExMenu::VoiceGuideVolume,
+        ExMenu::VoiceGuideSpeed, ExMenu::VoiceGuideLanguage, ExMenu::AutoAnnouncement,
+        ExMenu::MicUpDownSpeed, ExMenu::RxEqualizer, ExMenu::TxEqualizer,
+        ExMenu::TxFilterBandwidth, ExMenu::FmMicGain, ExMenu::AutoMode,
+        ExMenu::TxInhibit, ExMenu::SplitQuickOffset, ExMenu::AmCarrierLevel,
+        ExMenu::FmDeviation, ExMenu::PowerOnMessage, ExMenu::DtmfMemory,
+        ExMenu::DtmfSpeed, ExMenu::DtmfPause, ExMenu::DtmfMicControl,
+        ExMenu::CwReverseSideband, ExMenu::CwPitch, ExMenu::CwRiseTime,
+        ExMenu::CwKeyingWeight, ExMenu::CwReverseWeight, ExMenu::BugKey,
+        ExMenu::TxPowerFineAdjustment, ExMenu::SsbToCwCorrection, ExMenu::AutoCwTx,
+        ExMenu::TimeOutTimer, ExMenu::Transverter, ExMenu::TxHoldAfterTuning,
+        ExMenu::TunerWhileReceiving, ExMenu::LinearDelayHf, ExMenu::LinearDelay50MHz,
+        ExMenu::ConstantRecording, ExMenu::PlaybackRepeat, ExMenu::PlaybackInterval,
+        ExMenu::KeyingPriority, ExMenu::DataInputLevel, ExMenu::DataOutputLevel,
+        ExMenu::SplitTransfer, ExMenu::SplitTransferWrite, ExMenu::ComBaudRate,
+        ExMenu::AutoPowerOff, ExMenu::PfAKey, ExMenu::PfBKey,
+        ExMenu::MicPf1Key, ExMenu::MicPf2Key, ExMenu::MicPf3Key,
+    ];
+
+    /// Returns the menu number, 0 - 62
+    pub fn number(&self) -> u16 {
+        *self as u16
+    }
+
+    /// Returns the item with menu number `number`, 0 - 62
+    pub fn from_number(number: u16) -> Option<ExMenu> {
+        ExMenu::ALL.get(number as usize).cloned()
+    }
+
+    /// Returns the description of the item
+    pub fn description(&self) -> &'static str {
+        self.info().0
+    }
+
+    /// Returns the values the item accepts
+    pub fn values(&self) -> MenuValues {
+        self.info().1
+    }
+
+    /// Returns the factory default value
+    pub fn default_value(&self) -> u16 {
+        self.info().2
+    }
+
+    /// Description, values and factory default
+    fn info(&self) -> (&'static str, MenuValues, u16) {
+        use self::MenuValues::*;
+
+        match *self {
+            ExMenu::DisplayBrightness => ("Display brightness", Choices(&["Off", "1", "2", "3", "4"]), 3),
+            ExMenu::KeyIllumination => ("Key illumination", OffOn, 1),
+            ExMenu::TuningSteps => ("Tuning control change per revolution", Choices(&["500", "1000"]), 1),
+            ExMenu::MultiChannelTuning => ("Tuning with MULTI/CH control", OffOn, 0),
+            ExMenu::MultiChannelRounding => ("Round off frequencies changed with MULTI/CH", OffOn, 1),
+            ExMenu::BroadcastStep9kHz => ("9 kHz step in the AM broadcast band", OffOn, 0),
+            ExMenu::MemoryTemporaryChange => ("Temporary change of memory channel frequencies", OffOn, 0),
+            ExMenu::ProgramScanSlowdown => ("Program scan partially slowed", OffOn, 1),
+            ExMenu::SlowdownRange => ("Slow-down frequency range", Steps { start: 100, step: 100, count: 5, unit: "Hz" }, 2),
+            ExMenu::ProgramScanHold => ("Program scan hold", OffOn, 0),
+            ExMenu::ScanResume => ("Scan resume method", Choices(&["Time-operated", "Carrier-operated"]), 0),
+            ExMenu::BeepLevel => ("Beep output level", Choices(OFF_1_TO_9), 4),
+            ExMenu::SidetoneVolume => ("Sidetone volume", Choices(OFF_1_TO_9), 5),
+            ExMenu::MessageVolume => ("Message playback volume", Choices(OFF_1_TO_9), 4),
+            ExMenu::VoiceGuideVolume => ("Voice guide volume", Choices(OFF_1_TO_7), 4),
+            ExMenu::VoiceGuideSpeed => ("Voice guide speed", Steps { start: 1, step: 1, count: 4, unit: "" }, 0),
+            ExMenu::VoiceGuideLanguage => ("Voice guide language", Choices(&["English", "Japanese"]), 0),
+            ExMenu::AutoAnnouncement => ("Automatic announcement", Choices(OFF_1_2), 1),
+            ExMenu::MicUpDownSpeed => ("Microphone UP/DWN key speed", Choices(&["Slow", "Fast"]), 0),
+            ExMenu::RxEqualizer => ("RX equalizer", Choices(EQUALIZER), 0),
+            ExMenu::TxEqualizer => ("TX equalizer", Choices(EQUALIZER), 0),
+            ExMenu::TxFilterBandwidth => ("TX filter bandwidth for SSB and AM", Choices(&["2.4 kHz", "2.7 kHz"]), 0),
+            ExMenu::FmMicGain => ("Microphone gain for FM", Choices(&["Low", "Middle", "High"]), 0),
+            ExMenu::AutoMode => ("Auto mode", OffOn, 0),
+            ExMenu::TxInhibit => ("TX inhibit", OffOn, 0),
+            ExMenu::SplitQuickOffset => ("Split frequency quick offset", Steps { start: 0, step: 1, count: 10, unit: "kHz" }, 0),
+            ExMenu::AmCarrierLevel => ("AM carrier level", Steps { start: 0, step: 10, count: 11, unit: "%" }, 5),
+            ExMenu::FmDeviation => ("FM deviation", Choices(&["Wide", "Narrow"]), 0),
+            ExMenu::PowerOnMessage => ("Power-on message", OffOn, 1),
+            ExMenu::DtmfMemory => ("DTMF number memory", Steps { start: 0, step: 1, count: 10, unit: "" }, 0),
+            ExMenu::DtmfSpeed => ("DTMF transmit speed", Choices(&["Slow", "Fast"]), 1),
+            ExMenu::DtmfPause => ("DTMF pause duration", Steps { start: 100, step: 50, count: 39, unit: "ms" }, 8),
+            ExMenu::DtmfMicControl => ("DTMF microphone remote control", OffOn, 0),
+            ExMenu::CwReverseSideband => ("Sideband for CW reception", Choices(&["USB", "LSB"]), 0),
+            ExMenu::CwPitch => ("CW pitch and sidetone frequency", Steps { start: 400, step: 50, count: 13, unit: "Hz" }, 8),
+            ExMenu::CwRiseTime => ("CW rise time", Choices(&["1 ms", "2 ms", "4 ms", "6 ms"]), 2),
+            ExMenu::CwKeyingWeight => ("CW keying weight ratio", Choices(KEYING_WEIGHT), 0),
+            ExMenu::CwReverseWeight => ("Reverse CW keying auto weight ratio", OffOn, 0),
+            ExMenu::BugKey => ("Bug key function", OffOn, 0),
+            ExMenu::TxPowerFineAdjustment => ("TX power fine adjustment", OffOn, 0),
+            ExMenu::SsbToCwCorrection => ("Frequency correction when changing from SSB to CW", OffOn, 0),
+            ExMenu::AutoCwTx => ("Auto CW TX in SSB mode", OffOn, 0),
+            ExMenu::TimeOutTimer => ("Time-out timer", Choices(&["Off", "3 min", "5 min", "10 min", "20 min", "30 min"]), 0),
+            ExMenu::Transverter => ("Transverter frequency display", OffOn, 0),
+            ExMenu::TxHoldAfterTuning => ("TX hold when AT completes tuning", OffOn, 0),
+            ExMenu::TunerWhileReceiving => ("In-line AT while receiving", OffOn, 0),
+            ExMenu::LinearDelayHf => ("Linear amplifier control delay for HF", Choices(OFF_1_2), 0),
+            ExMenu::LinearDelay50MHz => ("Linear amplifier control delay for 50 MHz", Choices(OFF_1_2), 0),
+            ExMenu::ConstantRecording => ("Constant recording", OffOn, 1),
+            ExMenu::PlaybackRepeat => ("Voice and message playback repeat", OffOn, 0),
+            ExMenu::PlaybackInterval => ("Playback repeat interval", Steps { start: 0, step: 1, count: 61, unit: "s" }, 10),
+            ExMenu::KeyingPriority => ("Keying priority over playback", OffOn, 0),
+            ExMenu::DataInputLevel => ("Audio input level for data communications", Steps { start: 0, step: 1, count: 10, unit: "" }, 4),
+            ExMenu::DataOutputLevel => ("Audio output level for data communications", Steps { start: 0, step: 1, count: 10, unit: "" }, 4),
+            ExMenu::SplitTransfer => ("Split frequency transfer in master/slave operation", OffOn, 0),
+            ExMenu::SplitTransferWrite => ("Write transferred split frequencies to the VFO", OffOn, 0),
+            ExMenu::ComBaudRate => ("COM connector baud rate", Choices(&["4800 bps", "9600 bps", "19200 bps", "38400 bps", "57600 bps"]), 0),
+            ExMenu::AutoPowerOff => ("Auto power off", Choices(&["Off", "60 min", "120 min", "180 min"]), 0),
+            ExMenu::PfAKey => ("PF A key function", Steps { start: 0, step: 1, count: 100, unit: "" }, 0),
+            ExMenu::PfBKey => ("PF B key function", Steps { start: 0, step: 1, count: 100, unit: "" }, 0),
+            ExMenu::MicPf1Key => ("Microphone PF1 key function", Steps { start: 0, step: 1, count: 100, unit: "" }, 0),
+            ExMenu::MicPf2Key => ("Microphone PF2 key function", Steps { start: 0, step: 1, count: 100, unit: "" }, 0),
+            ExMenu::MicPf3Key => ("Microphone PF3 key function", Steps { start: 0, step: 1, count: 100, unit: "" }, 0),
+        }
+    }
+
+    /// Returns the `EX` representation of `value`, e.g. `08` for
+    /// value 8 of the CW pitch, whose values have two digits.
+    pub fn format_value(&self, value: u16) -> RadioResult<String> {
+        let values = self.values();
+        if value >= values.count() {
+            return Err(RadioError::InvalidParameter(
+                format!("menu {:02} must be 0 - {}, got {}", self.number(), values.count() - 1, value)
+            ));
+        }
+        Ok(format!("{:0width$}", value, width = values.width()))
+    }
+}
+
+/// Formats the item as in the radio's menu, e.g. `34 CW pitch and sidetone frequency`
+impl fmt::Display for ExMenu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02} {}", self.number(), self.description())
+    }
+}
+
+impl<T: Transport> TS480<T> {
+    /// Sets a menu item to `value`, numbered as described
+    /// by `item.values()`.
+    pub fn write_menu(&mut self, item: ExMenu, value: u16) -> RadioResult<()> {
+        let value = item.format_value(value)?;
+        self.set_extended_menu(item.number(), &value)
+    }
+
+    /// Reads the value of a menu item, numbered as described
+    /// by `item.values()`.
+    pub fn read_menu(&mut self, item: ExMenu) -> RadioResult<u16> {
+        let raw = self.read_extended_menu(item.number())?;
+        raw.parse().ok()
+            .filter(|&value| value < item.values().count())
+            .ok_or(RadioError::UnexpectedAnswer(raw))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Simulator;
+
+    #[test]
+    fn numbers_items() {
+        for (number, item) in ExMenu::ALL.iter().enumerate() {
+            assert_eq!(item.number(), number as u16);
+            assert_eq!(ExMenu::from_number(number as u16), Some(*item));
+        }
+        assert_eq!(ExMenu::from_number(34), Some(ExMenu::CwPitch));
+        assert_eq!(ExMenu::from_number(LAST_MENU), Some(ExMenu::MicPf3Key));
+        assert_eq!(ExMenu::from_number(LAST_MENU + 1), None);
+        assert_eq!(ExMenu::CwPitch.to_string(), "34 CW pitch and sidetone frequency");
+        assert_eq!(ExMenu::DisplayBrightness.to_string(), "00 Display brightness");
+    }
+
+    #[test]
+    fn describes_values() {
+        assert_eq!(ExMenu::KeyIllumination.values().describe(0), "Off");
+        assert_eq!(ExMenu::KeyIllumination.values().describe(1), "On");
+
+        assert_eq!(ExMenu::CwPitch.values().describe(4), "600 Hz");
+        assert_eq!(ExMenu::CwPitch.values().describe(12), "1000 Hz");
+        assert_eq!(ExMenu::VoiceGuideSpeed.values().describe(0), "1");
+        assert_eq!(ExMenu::AmCarrierLevel.values().describe(5), "50 %");
+
+        assert_eq!(ExMenu::CwKeyingWeight.values().describe(0), "Auto");
+        assert_eq!(ExMenu::CwKeyingWeight.values().describe(16), "4.0");
+        assert_eq!(ExMenu::RxEqualizer.values().describe(3), "FP");
+        // Past the last choice, the number itself
+        assert_eq!(ExMenu::RxEqualizer.values().describe(8), "8");
+    }
+
+    #[test]
+    fn every_default_is_a_value() {
+        for item in ExMenu::ALL.iter() {
+            assert!(item.default_value() < item.values().count(), "{}", item);
+            assert!(item.format_value(item.default_value()).is_ok(), "{}", item);
+        }
+    }
+
+    #[test]
+    fn pads_values_to_width_of_largest() {
+        // Two values
+        assert_eq!(ExMenu::KeyIllumination.format_value(1).unwrap(), "1");
+        // Steps, 13 and 100 values
+        assert_eq!(ExMenu::CwPitch.format_value(8).unwrap(), "08");
+        assert_eq!(ExMenu::CwPitch.format_value(12).unwrap(), "12");
+        assert_eq!(ExMenu::SplitQuickOffset.format_value(9).unwrap(), "9");
+        assert_eq!(ExMenu::PfAKey.format_value(5).unwrap(), "05");
+        assert_eq!(ExMenu::PfAKey.format_value(99).unwrap(), "99");
+        // Choices, 8 and 17 values
+        assert_eq!(ExMenu::TxEqualizer.format_value(7).unwrap(), "7");
+        assert_eq!(ExMenu::CwKeyingWeight.format_value(3).unwrap(), "03");
+    }
+
+    #[test]
+    fn refuses_values_out_of_range() {
+        for &(item, value) in &[
+            (ExMenu::KeyIllumination, 2),
+            (ExMenu::CwPitch, 13),
+            (ExMenu::PfAKey, 100),
+            (ExMenu::CwKeyingWeight, 17),
+        ] {
+            match item.format_value(value) {
+                Err(RadioError::InvalidParameter(_)) => {},
+                result => panic!("expected InvalidParameter for {}, got {:?}", item, result),
+            }
+        }
+        assert!(ExMenu::CwPitch.format_value(13).unwrap_err().to_string().contains("menu 34 must be 0 - 12, got 13"));
+    }
+
+    #[test]
+    fn writes_and_reads_menu() {
+        let mut radio = TS480::with_transport(Simulator::new());
+        for &(item, value, raw) in &[
+            (ExMenu::TxInhibit, 1, "1"),
+            (ExMenu::CwPitch, 3, "03"),
+            (ExMenu::DtmfPause, 38, "38"),
+            (ExMenu::MicPf2Key, 7, "07"),
+            (ExMenu::FmMicGain, 2, "2"),
+            (ExMenu::CwKeyingWeight, 9, "09"),
+        ] {
+            radio.write_menu(item, value).unwrap();
+            assert_eq!(radio.transport().state().menu[&item.number()], raw);
+            assert_eq!(radio.read_extended_menu(item.number()).unwrap(), raw);
+            assert_eq!(radio.read_menu(item).unwrap(), value);
+        }
+
+        assert_eq!(radio.read_menu(ExMenu::AmCarrierLevel).unwrap(), 5);
+        assert!(radio.write_menu(ExMenu::CwPitch, 13).is_err());
+        assert_eq!(radio.read_menu(ExMenu::CwPitch).unwrap(), 3);
+
+        // A value the item does not accept is an unexpected answer
+        radio.set_extended_menu(ExMenu::CwPitch.number(), "20").unwrap();
+        match radio.read_menu(ExMenu::CwPitch) {
+            Err(RadioError::UnexpectedAnswer(raw)) => assert_eq!(raw, "20"),
+            result => panic!("expected UnexpectedAnswer, got {:?}", result),
+        }
+    }
+}
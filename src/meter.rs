@@ -0,0 +1,362 @@
+//! Reading the S-meter and the SWR, COMP and ALC meters, and
+//! sampling them over time.
+
+use crate::commands::parse_field;
+use crate::transport::Transport;
+use crate::{RadioError, RadioResult, TS480};
+
+use std::fmt;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Highest reading of any meter
+pub const METER_MAX: u16 = 30;
+
+/// Meters selectable with `RM`, shown while transmitting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MeterKind {
+    Swr,
+    Comp,
+    Alc,
+}
+
+impl MeterKind {
+    /// Returns the CAT representation, e.g. `1` for SWR
+    pub fn to_cat(&self) -> char {
+        match *self {
+            MeterKind::Swr => '1',
+            MeterKind::Comp => '2',
+            MeterKind::Alc => '3',
+        }
+    }
+
+    /// Parses the CAT representation
+    pub fn from_cat(params: &str) -> RadioResult<Self> {
+        match params {
+            "1" => Ok(MeterKind::Swr),
+            "2" => Ok(MeterKind::Comp),
+            "3" => Ok(MeterKind::Alc),
+            _ => Err(RadioError::UnexpectedAnswer(params.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for MeterKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            MeterKind::Swr => "SWR",
+            MeterKind::Comp => "COMP",
+            MeterKind::Alc => "ALC",
+        })
+    }
+}
+
+impl FromStr for MeterKind {
+    type Err = RadioError;
+
+    fn from_str(s: &str) -> RadioResult<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "SWR" => Ok(MeterKind::Swr),
+            "COMP" => Ok(MeterKind::Comp),
+            "ALC" => Ok(MeterKind::Alc),
+            _ => Err(RadioError::InvalidParameter(format!("invalid meter {:?}", s))),
+        }
+    }
+}
+
+/// An S-meter reading, with its raw value converted to S-units
+/// and dBm following the scale on the radio's display: 0 - 15
+/// covers S0 - S9 in steps of 3.6 dB, 15 - 30 covers S9 to
+/// S9+60dB in steps of 4 dB. S9 is taken as -73 dBm, the IARU
+/// standard for HF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SMeterReading(u16);
+
+impl SMeterReading {
+    /// Converts a raw reading, 0 - 30
+    pub fn from_raw(raw: u16) -> Self {
+        SMeterReading(raw.min(METER_MAX))
+    }
+
+    /// Returns the raw reading, 0 - 30
+    pub fn raw(&self) -> u16 {
+        self.0
+    }
+
+    /// Returns the S-units, 0 - 9. Readings above S9 are
+    /// given by `db_over_s9`.
+    pub fn s_units(&self) -> f32 {
+        f32::from(self.0.min(15)) * 9.0 / 15.0
+    }
+
+    /// Returns the signal strength relative to S9 in dB,
+    /// negative below S9.
+    pub fn db_over_s9(&self) -> f32 {
+        let raw = f32::from(self.0);
+        if self.0 <= 15 {
+            (raw - 15.0) * 3.6
+        } else {
+            (raw - 15.0) * 4.0
+        }
+    }
+
+    /// Returns the signal strength in dBm.
+    pub fn dbm(&self) -> f32 {
+        -73.0 + self.db_over_s9()
+    }
+}
+
+impl fmt::Display for SMeterReading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 <= 15 {
+            write!(f, "S{}", self.s_units().round())
+        } else {
+            write!(f, "S9+{}dB", self.db_over_s9().round())
+        }
+    }
+}
+
+impl<T: Transport> TS480<T> {
+    /// Reads the S-meter, with the raw reading and its value
+    /// in S-units and dBm.
+    ///
+    /// While transmitting the radio reports the power meter
+    /// instead; its raw reading, 0 - 30, is `SMeterReading::raw`.
+    pub fn read_smeter(&mut self) -> RadioResult<SMeterReading> {
+        let params = self.read_parameters("SM0")?;
+        Ok(SMeterReading::from_raw(parse_field(&params, 0..4)?))
+    }
+
+    /// Selects the meter shown on the front panel.
+    pub fn set_meter(&mut self, meter: MeterKind) -> RadioResult<()> {
+        self.transmit(&format!("RM{};", meter.to_cat()))
+    }
+
+    /// Reads a meter, 0 - 30, selecting it on the front panel
+    /// if another meter is selected. The radio only updates
+    /// these meters while transmitting.
+    pub fn read_meter(&mut self, meter: MeterKind) -> RadioResult<u16> {
+        let (selected, value) = self.read_selected_meter()?;
+        if selected == meter {
+            return Ok(value);
+        }
+        self.set_meter(meter)?;
+        Ok(self.read_selected_meter()?.1)
+    }
+
+    /// Reads the meter selected on the front panel and its value.
+    fn read_selected_meter(&mut self) -> RadioResult<(MeterKind, u16)> {
+        let params = self.read_parameters("RM")?;
+        Ok((MeterKind::from_cat(params.get(0..1).unwrap_or(""))?, parse_field(&params, 1..5)?))
+    }
+}
+
+/// A meter reading and when it was taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeterSample {
+    pub time: Instant,
+    pub value: u16,
+}
+
+/// Minimum, maximum and average of meter readings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeterStatistics {
+    count: u64,
+    min: u16,
+    max: u16,
+    sum: u64,
+}
+
+impl MeterStatistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a reading.
+    pub fn add(&mut self, value: u16) {
+        if self.count == 0 || value < self.min {
+            self.min = value;
+        }
+        if self.count == 0 || value > self.max {
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += u64::from(value);
+    }
+
+    /// Returns the number of readings.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the lowest reading, if there are any.
+    pub fn min(&self) -> Option<u16> {
+        if self.count > 0 { Some(self.min) } else { None }
+    }
+
+    /// Returns the highest reading, if there are any.
+    pub fn max(&self) -> Option<u16> {
+        if self.count > 0 { Some(self.max) } else { None }
+    }
+
+    /// Returns the average reading, if there are any.
+    pub fn average(&self) -> Option<f64> {
+        if self.count > 0 { Some(self.sum as f64 / self.count as f64) } else { None }
+    }
+}
+
+/// Reads a meter at a fixed rate, keeping statistics of the
+/// readings. Iterating yields a sample every `interval`, sleeping
+/// until the next is due; the first is taken immediately.
+///
+/// `read` takes the reading, so any meter can be sampled:
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use ts480::{MeterKind, MeterSampler, TS480};
+///
+/// let mut radio = TS480::new("/dev/ttyUSB0").unwrap();
+/// let mut sampler = MeterSampler::new(&mut radio, Duration::from_millis(100),
+///     |radio| radio.read_meter(MeterKind::Swr));
+/// for sample in sampler.by_ref().take(50) {
+///     println!("{:?}", sample.unwrap());
+/// }
+/// println!("average {:?}", sampler.statistics().average());
+/// ```
+pub struct MeterSampler<'a, T, F>
+    where T: Transport + 'a,
+          F: FnMut(&mut TS480<T>) -> RadioResult<u16>
+{
+    radio: &'a mut TS480<T>,
+    read: F,
+    interval: Duration,
+    next: Option<Instant>,
+    statistics: MeterStatistics,
+}
+
+impl<'a, T, F> MeterSampler<'a, T, F>
+    where T: Transport + 'a,
+          F: FnMut(&mut TS480<T>) -> RadioResult<u16>
+{
+    /// Samples `read` every `interval`.
+    pub fn new(radio: &'a mut TS480<T>, interval: Duration, read: F) -> Self {
+        MeterSampler { radio, read, interval, next: None, statistics: MeterStatistics::new() }
+    }
+
+    /// Returns the statistics of the samples taken so far.
+    pub fn statistics(&self) -> &MeterStatistics {
+        &self.statistics
+    }
+
+    /// Clears the statistics, e.g. to start a new measurement.
+    pub fn reset_statistics(&mut self) {
+        self.statistics = MeterStatistics::new();
+    }
+}
+
+impl<'a, T, F> Iterator for MeterSampler<'a, T, F>
+    where T: Transport + 'a,
+          F: FnMut(&mut TS480<T>) -> RadioResult<u16>
+{
+    type Item = RadioResult<MeterSample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = Instant::now();
+        if let Some(next) = self.next {
+            if next > now {
+                thread::sleep(next - now);
+            }
+        }
+        // Samples keep to the schedule, but a slow reading
+        // delays the next one rather than causing a burst
+        let due = self.next.unwrap_or(now) + self.interval;
+        self.next = Some(due.max(Instant::now()));
+
+        let time = Instant::now();
+        Some((self.read)(self.radio).map(|value| {
+            self.statistics.add(value);
+            MeterSample { time, value }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibrates_smeter() {
+        let s0 = SMeterReading::from_raw(0);
+        assert_eq!(s0.s_units(), 0.0);
+        assert!((s0.db_over_s9() + 54.0).abs() < 1e-4);
+        assert_eq!(s0.to_string(), "S0");
+
+        let s9 = SMeterReading::from_raw(15);
+        assert_eq!(s9.s_units(), 9.0);
+        assert_eq!(s9.db_over_s9(), 0.0);
+        assert_eq!(s9.dbm(), -73.0);
+        assert_eq!(s9.to_string(), "S9");
+
+        let top = SMeterReading::from_raw(30);
+        assert_eq!(top.s_units(), 9.0);
+        assert_eq!(top.db_over_s9(), 60.0);
+        assert_eq!(top.dbm(), -13.0);
+        assert_eq!(top.to_string(), "S9+60dB");
+    }
+
+    #[test]
+    fn steps_3_6_db_below_s9_and_4_db_above() {
+        for raw in 1..=15 {
+            let step = SMeterReading::from_raw(raw).db_over_s9() - SMeterReading::from_raw(raw - 1).db_over_s9();
+            assert!((step - 3.6).abs() < 1e-4, "step to {} is {}", raw, step);
+        }
+        for raw in 16..=30 {
+            let step = SMeterReading::from_raw(raw).db_over_s9() - SMeterReading::from_raw(raw - 1).db_over_s9();
+            assert!((step - 4.0).abs() < 1e-4, "step to {} is {}", raw, step);
+        }
+        assert_eq!(SMeterReading::from_raw(10).to_string(), "S6");
+        assert_eq!(SMeterReading::from_raw(20).to_string(), "S9+20dB");
+    }
+
+    #[test]
+    fn clamps_smeter() {
+        assert_eq!(SMeterReading::from_raw(45), SMeterReading::from_raw(METER_MAX));
+        assert_eq!(SMeterReading::from_raw(45).raw(), METER_MAX);
+    }
+
+    #[test]
+    fn statistics_without_readings() {
+        let statistics = MeterStatistics::new();
+        assert_eq!(statistics.count(), 0);
+        assert_eq!(statistics.min(), None);
+        assert_eq!(statistics.max(), None);
+        assert_eq!(statistics.average(), None);
+    }
+
+    #[test]
+    fn statistics_of_readings() {
+        let mut statistics = MeterStatistics::new();
+        for &value in &[7, 3, 12, 0, 8] {
+            statistics.add(value);
+        }
+        assert_eq!(statistics.count(), 5);
+        assert_eq!(statistics.min(), Some(0));
+        assert_eq!(statistics.max(), Some(12));
+        assert_eq!(statistics.average(), Some(6.0));
+
+        let mut single = MeterStatistics::new();
+        single.add(30);
+        assert_eq!((single.min(), single.max(), single.average()), (Some(30), Some(30), Some(30.0)));
+    }
+
+    #[test]
+    fn parses_meter_kinds() {
+        for &kind in &[MeterKind::Swr, MeterKind::Comp, MeterKind::Alc] {
+            assert_eq!(MeterKind::from_cat(&kind.to_cat().to_string()).unwrap(), kind);
+            assert_eq!(kind.to_string().parse::<MeterKind>().unwrap(), kind);
+        }
+        assert!(MeterKind::from_cat("0").is_err());
+        assert!("power".parse::<MeterKind>().is_err());
+    }
+}
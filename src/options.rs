@@ -0,0 +1,214 @@
+//! Serial port settings used when connecting to the radio.
+
+use serial::{
+    self, SystemPort, SerialPort, PortSettings,
+    BaudRate, CharSize, Parity,
+    StopBits, FlowControl
+};
+
+use crate::ptt::LineUse;
+use crate::transport::Transport;
+use crate::{RadioError, RadioResult, TS480};
+
+use std::ffi::{OsStr, OsString};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// The baud rates selectable with menu 56, in the
+/// order `TS480Options::autodetect` tries them.
+pub const BAUD_RATES: [BaudRate; 5] = [
+    BaudRate::Baud4800,
+    BaudRate::Baud9600,
+    BaudRate::Baud19200,
+    BaudRate::Baud38400,
+    BaudRate::Baud57600,
+];
+
+/// Longest a single read from a TCP connection may block. The
+/// driver enforces the answer timeout itself, so this only limits
+/// how long polling for Auto Information reports takes.
+const TCP_READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Builder for connecting to the radio with non-default
+/// serial port settings.
+///
+/// ```no_run
+/// use ts480::{BaudRate, TS480Options};
+///
+/// let radio = TS480Options::new()
+///     .baud_rate(BaudRate::Baud38400)
+///     .open("/dev/ttyUSB0")
+///     .unwrap();
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct TS480Options {
+    baud_rate: BaudRate,
+    stop_bits: StopBits,
+    flow_control: FlowControl,
+    pub(crate) timeout: Duration,
+    pub(crate) retries: u8,
+    pub(crate) rts: LineUse,
+    pub(crate) dtr: LineUse,
+    pub(crate) max_transmit_time: Option<Duration>,
+}
+
+impl Default for TS480Options {
+    fn default() -> Self {
+        TS480Options {
+            baud_rate: BaudRate::Baud4800,
+            stop_bits: StopBits::Stop2,
+            flow_control: FlowControl::FlowHardware,
+            timeout: Duration::from_secs(1),
+            retries: 2,
+            rts: LineUse::Unused,
+            dtr: LineUse::Unused,
+            max_transmit_time: None,
+        }
+    }
+}
+
+impl TS480Options {
+    /// Creates options matching the radio's factory
+    /// settings: 4800 baud, 8N2, hardware flow control.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the baud rate. This must match menu 56 on the radio.
+    pub fn baud_rate(mut self, baud_rate: BaudRate) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Sets the number of stop bits.
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Sets the flow control method.
+    pub fn flow_control(mut self, flow_control: FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    /// Sets how long to wait for each answer from the radio
+    /// before giving up.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many times a query is repeated when the radio
+    /// answers `E;` (communication error) or `O;` (processing
+    /// not completed).
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets what the RTS line is used for. By default the
+    /// driver never changes it.
+    ///
+    /// Hardware flow control also drives RTS, so it must be
+    /// turned off to use the line for PTT or CW keying.
+    pub fn rts(mut self, rts: LineUse) -> Self {
+        self.rts = rts;
+        self
+    }
+
+    /// Sets what the DTR line is used for. By default the
+    /// driver never changes it.
+    pub fn dtr(mut self, dtr: LineUse) -> Self {
+        self.dtr = dtr;
+        self
+    }
+
+    /// Sets how long the radio may transmit before the driver
+    /// switches it back to receive, see `TS480::check_watchdog`.
+    pub fn max_transmit_time(mut self, max_transmit_time: Duration) -> Self {
+        self.max_transmit_time = Some(max_transmit_time);
+        self
+    }
+
+    /// Attempts to connect to the radio using the specified port.
+    pub fn open<T: AsRef<OsStr> + ?Sized>(&self, port: &T) -> RadioResult<TS480> {
+        let mut radio = self.connect(self.open_port(port)?)?;
+        radio.port_name = Some(OsString::from(port));
+        Ok(radio)
+    }
+
+    /// Opens the specified port with these settings, without
+    /// connecting to the radio. This allows wrapping the port,
+    /// e.g. in a `recording::Recorder`, before passing it to
+    /// `connect`.
+    pub fn open_port<T: AsRef<OsStr> + ?Sized>(&self, port: &T) -> RadioResult<SystemPort> {
+        let mut serial_port = serial::open(port)?;
+        self.configure(&mut serial_port)?;
+        Ok(serial_port)
+    }
+
+    /// Connects to a radio shared over TCP, e.g. with ser2net or
+    /// `ts480-sim`, without connecting the driver to it, like
+    /// `open_port`.
+    pub fn open_tcp<A: ToSocketAddrs>(&self, address: A) -> RadioResult<TcpStream> {
+        let stream = TcpStream::connect(address)?;
+        // Reads must return for answer timeouts to take effect
+        stream.set_read_timeout(Some(self.timeout.min(TCP_READ_TIMEOUT)))?;
+        Ok(stream)
+    }
+
+    /// Attempts to connect to a radio shared over TCP.
+    pub fn connect_tcp<A: ToSocketAddrs>(&self, address: A) -> RadioResult<TS480<TcpStream>> {
+        self.connect(self.open_tcp(address)?)
+    }
+
+    /// Connects to the radio over an already-open transport,
+    /// applying the timeout, retries and line use settings.
+    pub fn connect<T: Transport>(&self, transport: T) -> RadioResult<TS480<T>> {
+        let mut radio = TS480::with_transport(transport);
+        radio.options = *self;
+        radio.set_line_use(self.rts, self.dtr)?;
+        Ok(radio)
+    }
+
+    /// Attempts to connect to the radio using the specified port,
+    /// trying each of the radio's baud rates until it answers `ID;`.
+    /// The baud rate set with `baud_rate` is ignored.
+    pub fn autodetect<T: AsRef<OsStr> + ?Sized>(&self, port: &T) -> RadioResult<TS480> {
+        let mut radio = self.open(port)?;
+
+        for &baud_rate in BAUD_RATES.iter() {
+            let options = self.baud_rate(baud_rate);
+            options.configure(radio.transport_mut())?;
+            radio.buffer.clear();
+
+            match radio.read_id() {
+                Ok(20) => {
+                    radio.options = options;
+                    return Ok(radio);
+                },
+                Ok(_) | Err(RadioError::Io(_)) | Err(RadioError::UnexpectedAnswer(_)) => {},
+                Err(RadioError::SyntaxOrStatus) | Err(RadioError::CommError) => {},
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(RadioError::NotDetected)
+    }
+
+    /// Applies the serial port settings and timeout to `port`.
+    pub(crate) fn configure(&self, port: &mut SystemPort) -> RadioResult<()> {
+        let settings = PortSettings {
+            baud_rate: self.baud_rate,
+            char_size: CharSize::Bits8,
+            parity: Parity::ParityNone,
+            stop_bits: self.stop_bits,
+            flow_control: self.flow_control,
+        };
+
+        port.configure(&settings)?;
+        port.set_timeout(self.timeout)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Keying the transmitter by CAT command or modem control line.
+
+use crate::commands::check_range;
+use crate::transport::Transport;
+use crate::watchdog::{Hold, Watchdog};
+use crate::{RadioError, RadioResult, TS480};
+
+use std::io;
+use std::time::{Duration, Instant};
+
+/// What a modem control line is connected to on the interface.
+///
+/// The driver only changes a line according to its use, so an
+/// interface that keys the radio on RTS or DTR is never keyed by
+/// accident.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineUse {
+    /// Never changed by the driver
+    Unused,
+    /// Held at the given level while the driver is connected
+    Fixed(bool),
+    /// Asserted to transmit, with `PttSource::Rts` or `PttSource::Dtr`
+    Ptt,
+    /// Asserted to key CW, with `TS480::key_cw`
+    CwKey,
+}
+
+/// How to switch the radio to transmit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PttSource {
+    /// `TX0;`, transmitting microphone audio
+    Microphone,
+    /// `TX1;`, transmitting ACC2 or USB audio
+    Data,
+    /// `TX2;`, TX TUNE
+    Tune,
+    /// Asserting RTS, which must be used for `LineUse::Ptt`
+    Rts,
+    /// Asserting DTR, which must be used for `LineUse::Ptt`
+    Dtr,
+}
+
+impl PttSource {
+    /// Returns the source for TX p1, 0 - 2
+    pub fn from_cat(p1: u8) -> RadioResult<Self> {
+        check_range("transmit source", p1, 0, 2)?;
+        Ok([PttSource::Microphone, PttSource::Data, PttSource::Tune][p1 as usize])
+    }
+}
+
+impl<T: Transport> TS480<T> {
+    /// Sets what the RTS and DTR lines are used for, and sets
+    /// the lines to their idle levels.
+    pub fn set_line_use(&mut self, rts: LineUse, dtr: LineUse) -> RadioResult<()> {
+        self.options.rts = rts;
+        self.options.dtr = dtr;
+
+        match rts {
+            LineUse::Unused => {},
+            LineUse::Fixed(level) => self.port.set_rts(level)?,
+            LineUse::Ptt | LineUse::CwKey => self.port.set_rts(false)?,
+        }
+        match dtr {
+            LineUse::Unused => {},
+            LineUse::Fixed(level) => self.port.set_dtr(level)?,
+            LineUse::Ptt | LineUse::CwKey => self.port.set_dtr(false)?,
+        }
+        self.update_watchdog();
+        Ok(())
+    }
+
+    /// Sets how long the radio may transmit before the driver
+    /// switches it back to receive; `None` to never.
+    pub fn set_max_transmit_time(&mut self, max_transmit_time: Option<Duration>) {
+        self.options.max_transmit_time = max_transmit_time;
+        self.update_watchdog();
+    }
+
+    /// Switches to transmit.
+    ///
+    /// Keying with a modem line requires that line to be used for
+    /// `LineUse::Ptt`. Either way, the radio is not keyed if the
+    /// band privileges do not allow transmitting on its current
+    /// frequency and mode, or if the watchdog has switched it to
+    /// receive since `ptt_off` was last called.
+    pub fn ptt_on(&mut self, source: PttSource) -> RadioResult<()> {
+        match source {
+            PttSource::Microphone => self.transmit("TX0;"),
+            PttSource::Data => self.transmit("TX1;"),
+            PttSource::Tune => self.transmit("TX2;"),
+            PttSource::Rts => {
+                self.check_watchdog()?;
+                if self.watchdog_fired {
+                    return Err(watchdog_refused());
+                }
+                if self.options.rts != LineUse::Ptt {
+                    return Err(RadioError::InvalidParameter("RTS is not used for PTT".to_string()));
+                }
+                self.check_privileges()?;
+                self.write_port(Some(true), |port| port.set_rts(true))
+            },
+            PttSource::Dtr => {
+                self.check_watchdog()?;
+                if self.watchdog_fired {
+                    return Err(watchdog_refused());
+                }
+                if self.options.dtr != LineUse::Ptt {
+                    return Err(RadioError::InvalidParameter("DTR is not used for PTT".to_string()));
+                }
+                self.check_privileges()?;
+                self.write_port(Some(true), |port| port.set_dtr(true))
+            },
+        }
+    }
+
+    /// Switches to receive, releasing any line used for PTT as
+    /// well, however the radio was switched to transmit.
+    ///
+    /// `RX;` is sent first and the lines are released even if it
+    /// cannot be sent, so that neither failure leaves the radio
+    /// transmitting; the first error is returned afterwards.
+    pub fn ptt_off(&mut self) -> RadioResult<()> {
+        let sent = self.write_port(Some(false), |port| port.write_all(b"RX;"));
+        let released = self.release_ptt_lines();
+        sent?;
+        released
+    }
+
+    /// Returns whether the driver switched the radio to
+    /// transmit and not back yet.
+    pub fn is_transmitting(&self) -> bool {
+        self.transmitting.is_some() && !self.watchdog.as_ref().is_some_and(Watchdog::fired)
+    }
+
+    /// Presses or releases the CW key on the line used
+    /// for `LineUse::CwKey`.
+    pub fn key_cw(&mut self, down: bool) -> RadioResult<()> {
+        if self.options.rts == LineUse::CwKey {
+            self.port.set_rts(down)?;
+        } else if self.options.dtr == LineUse::CwKey {
+            self.port.set_dtr(down)?;
+        } else {
+            return Err(RadioError::InvalidParameter("no line is used for CW keying".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Switches the radio back to receive if it has transmitted
+    /// for longer than the maximum transmit time, returning whether
+    /// it did, or whether the watchdog thread did since the last
+    /// check.
+    ///
+    /// When the transport supports `Transport::try_clone_port`, as
+    /// serial ports and TCP streams do, a watchdog thread switches
+    /// the radio to receive on time by itself. Otherwise this is only
+    /// checked before every command is sent or answer received, and
+    /// programs that key the radio and then stop talking to it should
+    /// call this periodically; `RadioHandle` does so by itself.
+    ///
+    /// Either way, the radio is not keyed again until it is switched
+    /// to receive with `ptt_off` or `RX;`.
+    pub fn check_watchdog(&mut self) -> RadioResult<bool> {
+        if self.watchdog.as_ref().is_some_and(Watchdog::take_fired) {
+            // The thread has sent `RX;` and released the lines already
+            self.transmitting = None;
+            self.watchdog_fired = true;
+            return Ok(true);
+        }
+
+        match self.watchdog_deadline() {
+            Some(deadline) if Instant::now() >= deadline => {
+                let result = self.ptt_off();
+                self.watchdog_fired = true;
+                result.map(|_| true)
+            },
+            _ => Ok(false),
+        }
+    }
+
+    /// Returns when the watchdog switches the radio back to receive.
+    pub(crate) fn watchdog_deadline(&self) -> Option<Instant> {
+        match (self.transmitting, self.options.max_transmit_time) {
+            (Some(since), Some(max)) => Some(since + max),
+            _ => None,
+        }
+    }
+
+    /// Writes to the port with `write`, holding off the watchdog
+    /// thread so that it cannot unkey the radio halfway through, and
+    /// updates the transmit state if `keyed` says the write switched
+    /// the radio to transmit or receive.
+    ///
+    /// If the thread has just unkeyed the radio, a write that would
+    /// key it again is refused.
+    pub(crate) fn write_port<F>(&mut self, keyed: Option<bool>, write: F) -> RadioResult<()>
+        where F: FnOnce(&mut T) -> io::Result<()>
+    {
+        if keyed == Some(true) && self.watchdog.is_none() && self.options.max_transmit_time.is_some() {
+            self.watchdog = self.port.try_clone_port()?.map(Watchdog::spawn);
+        }
+
+        let mut hold = self.watchdog.as_ref().map(Watchdog::hold);
+        if hold.as_ref().is_some_and(Hold::fired) {
+            drop(hold);
+            self.check_watchdog()?;
+            if keyed == Some(true) {
+                return Err(watchdog_refused());
+            }
+            return self.write_port(keyed, write);
+        }
+
+        let result = write(&mut self.port);
+        match keyed {
+            Some(true) if result.is_ok() && self.transmitting.is_none() => self.transmitting = Some(Instant::now()),
+            Some(false) => {
+                self.transmitting = None;
+                self.watchdog_fired = false;
+            },
+            _ => {},
+        }
+        if let Some(ref mut hold) = hold {
+            hold.set_deadline(self.watchdog_deadline(), self.options.rts, self.options.dtr);
+        }
+        Ok(result?)
+    }
+
+    /// Tells the watchdog thread, if there is one, of a change to
+    /// the maximum transmit time or the use of the lines.
+    fn update_watchdog(&self) {
+        if let Some(ref watchdog) = self.watchdog {
+            watchdog.hold().set_deadline(self.watchdog_deadline(), self.options.rts, self.options.dtr);
+        }
+    }
+
+    /// Releases the lines used for PTT and CW keying.
+    pub(crate) fn release_ptt_lines(&mut self) -> RadioResult<()> {
+        if let LineUse::Ptt | LineUse::CwKey = self.options.rts {
+            self.port.set_rts(false)?;
+        }
+        if let LineUse::Ptt | LineUse::CwKey = self.options.dtr {
+            self.port.set_dtr(false)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error for keying the radio after the watchdog switched it
+/// to receive.
+pub(crate) fn watchdog_refused() -> RadioError {
+    RadioError::TransmitRefused(
+        "the maximum transmit time ran out; switch to receive before transmitting again".to_string(),
+    )
+}
@@ -0,0 +1,481 @@
+//! Recording CAT traffic to a file and replaying it, for
+//! reproducing problems seen with a real radio.
+//!
+//! A `Recorder` wraps any transport and logs every byte sent
+//! and received, and every change of the modem control lines,
+//! with the time since the recording started:
+//!
+//! ```text
+//! # ts480 session started 1760000000.250
+//! 0.000000 > FA;
+//! 0.011873 < FA00014074000;
+//! 0.250102 RTS 1
+//! 1.250415 TIMEOUT
+//! ```
+//!
+//! Bytes outside printable ASCII are written as `\xNN`, and a
+//! backslash as `\\`. `TIMEOUT` marks a read that timed out,
+//! logged once until there is other traffic. A `Replay` plays a recorded `Session` back
+//! to the driver, checking that it sends exactly what was sent
+//! when recording, and `Session::transcript` annotates the traffic
+//! with the meaning of each command.
+//!
+//! ```no_run
+//! use ts480::{TS480Options, TS480};
+//! use ts480::recording::{Recorder, Replay};
+//!
+//! let options = TS480Options::new();
+//! let port = options.open_port("/dev/ttyUSB0").unwrap();
+//! let mut radio = options.connect(Recorder::create(port, "session.log").unwrap()).unwrap();
+//! radio.read_frequency_a().unwrap();
+//! drop(radio);
+//!
+//! let mut radio = TS480::with_transport(Replay::open("session.log").unwrap());
+//! radio.read_frequency_a().unwrap();
+//! assert!(radio.transport().is_finished());
+//! ```
+
+use crate::console;
+use crate::transport::Transport;
+use crate::RadioError;
+
+use std::collections::VecDeque;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// One event on the connection to the radio.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Traffic {
+    /// Bytes written to the radio
+    Sent(Vec<u8>),
+    /// Bytes read from the radio
+    Received(Vec<u8>),
+    /// The RTS line was set to this level
+    Rts(bool),
+    /// The DTR line was set to this level
+    Dtr(bool),
+    /// A read timed out
+    Timeout,
+}
+
+/// An event and when it happened, relative to the start
+/// of the recording.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub time: Duration,
+    pub traffic: Traffic,
+}
+
+/// A transport that logs all traffic passing through it.
+///
+/// Failing to write the log is reported as an error of the
+/// operation being logged.
+pub struct Recorder<T: Transport, W: Write = File> {
+    transport: T,
+    log: W,
+    started: Instant,
+    timed_out: bool,
+}
+
+impl<T: Transport> Recorder<T, File> {
+    /// Records the traffic of `transport` to a new file at `path`,
+    /// replacing any existing file.
+    pub fn create<P: AsRef<Path>>(transport: T, path: P) -> io::Result<Self> {
+        Recorder::new(transport, File::create(path)?)
+    }
+}
+
+impl<T: Transport, W: Write> Recorder<T, W> {
+    /// Records the traffic of `transport` to `log`.
+    ///
+    /// Each entry is written to `log` as it happens, so that a
+    /// recording survives a crash; `log` should not be buffered.
+    pub fn new(transport: T, mut log: W) -> io::Result<Self> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        writeln!(log, "# ts480 session started {}.{:03}", now.as_secs(), now.subsec_millis())?;
+        Ok(Recorder { transport, log, started: Instant::now(), timed_out: false })
+    }
+
+    /// Returns a reference to the wrapped transport.
+    pub fn get_ref(&self) -> &T {
+        &self.transport
+    }
+
+    /// Returns a mutable reference to the wrapped transport.
+    /// Traffic that bypasses the recorder is not logged.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    fn record(&mut self, traffic: &Traffic) -> io::Result<()> {
+        self.timed_out = *traffic == Traffic::Timeout;
+        let time = self.started.elapsed();
+        let line = format!("{}.{:06} {}\n", time.as_secs(), time.subsec_micros(), format_traffic(traffic));
+        self.log.write_all(line.as_bytes())
+    }
+}
+
+impl<T: Transport, W: Write> Read for Recorder<T, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = match self.transport.read(buf) {
+            Ok(read) => read,
+            Err(e) => {
+                if is_timeout(&e) && !self.timed_out {
+                    self.record(&Traffic::Timeout)?;
+                }
+                return Err(e);
+            },
+        };
+        if read > 0 {
+            self.record(&Traffic::Received(buf[..read].to_vec()))?;
+        }
+        Ok(read)
+    }
+}
+
+impl<T: Transport, W: Write> Write for Recorder<T, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.transport.write(buf)?;
+        if written > 0 {
+            self.record(&Traffic::Sent(buf[..written].to_vec()))?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.transport.flush()?;
+        self.log.flush()
+    }
+}
+
+impl<T: Transport, W: Write> Transport for Recorder<T, W> {
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.transport.set_rts(level)?;
+        self.record(&Traffic::Rts(level))
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.transport.set_dtr(level)?;
+        self.record(&Traffic::Dtr(level))
+    }
+
+    fn discard_input(&mut self) -> io::Result<()> {
+        self.transport.discard_input()
+    }
+}
+
+/// Returns whether `e` is a read timing out, which some
+/// transports report as `WouldBlock`.
+fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock
+}
+
+fn format_traffic(traffic: &Traffic) -> String {
+    match *traffic {
+        Traffic::Sent(ref data) => format!("> {}", escape(data)),
+        Traffic::Received(ref data) => format!("< {}", escape(data)),
+        Traffic::Rts(level) => format!("RTS {}", level as u8),
+        Traffic::Dtr(level) => format!("DTR {}", level as u8),
+        Traffic::Timeout => "TIMEOUT".to_owned(),
+    }
+}
+
+fn escape(data: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in data {
+        match byte {
+            b'\\' => escaped.push_str("\\\\"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\x{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            data.push(byte);
+            continue;
+        }
+        match bytes.next()? {
+            b'\\' => data.push(b'\\'),
+            b'x' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                data.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            },
+            _ => return None,
+        }
+    }
+    Some(data)
+}
+
+/// A recorded session, as written by a `Recorder`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Session {
+    /// When the recording started, as time since the Unix
+    /// epoch, if the header was present
+    pub started: Option<Duration>,
+    pub entries: Vec<Entry>,
+}
+
+impl Session {
+    /// Reads the recording at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Session::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a recording. Fails with `InvalidData` naming
+    /// the first line that cannot be parsed.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut session = Session::default();
+        for (number, line) in text.lines().enumerate() {
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some(started) = comment.trim().strip_prefix("ts480 session started ") {
+                    session.started = parse_time(started);
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_entry(line) {
+                Some(entry) => session.entries.push(entry),
+                None => {
+                    let message = format!("line {}: invalid entry: {}", number + 1, line);
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                },
+            }
+        }
+        Ok(session)
+    }
+
+    /// Formats the session as a readable transcript: one line per
+    /// command or answer, followed by the meaning of its fields.
+    /// Commands without parameters, such as reads, are described
+    /// by name instead.
+    pub fn transcript(&self) -> String {
+        let mut transcript = String::new();
+        if let Some(started) = self.started {
+            let _ = writeln!(transcript, "# started {}.{:03}", started.as_secs(), started.subsec_millis());
+        }
+
+        // Commands may be split across reads and writes, so each
+        // direction is collected until its `;`
+        let mut sent = (Duration::default(), Vec::new());
+        let mut received = (Duration::default(), Vec::new());
+        for entry in &self.entries {
+            match entry.traffic {
+                Traffic::Sent(ref data) => collect(&mut transcript, &mut sent, entry.time, data, '>'),
+                Traffic::Received(ref data) => collect(&mut transcript, &mut received, entry.time, data, '<'),
+                Traffic::Rts(level) => line(&mut transcript, entry.time, '|', &format!("RTS {}", on_off(level))),
+                Traffic::Dtr(level) => line(&mut transcript, entry.time, '|', &format!("DTR {}", on_off(level))),
+                Traffic::Timeout => line(&mut transcript, entry.time, '|', "timed out"),
+            }
+        }
+        for &(time, ref partial) in [&sent, &received].iter() {
+            if !partial.is_empty() {
+                line(&mut transcript, *time, '?', &format!("{} (incomplete)", escape(partial)));
+            }
+        }
+        transcript
+    }
+}
+
+fn parse_time(text: &str) -> Option<Duration> {
+    let mut parts = text.splitn(2, '.');
+    let secs = parts.next()?.parse().ok()?;
+    let fraction = parts.next().unwrap_or("0");
+    if fraction.is_empty() || fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32);
+    Some(Duration::new(secs, nanos))
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut parts = line.splitn(3, ' ');
+    let time = parse_time(parts.next()?)?;
+    let kind = parts.next()?;
+    let rest = parts.next().unwrap_or("");
+    let level = || match rest {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    };
+
+    let traffic = match kind {
+        ">" => Traffic::Sent(unescape(rest)?),
+        "<" => Traffic::Received(unescape(rest)?),
+        "RTS" => Traffic::Rts(level()?),
+        "DTR" => Traffic::Dtr(level()?),
+        "TIMEOUT" if rest.is_empty() => Traffic::Timeout,
+        _ => return None,
+    };
+    Some(Entry { time, traffic })
+}
+
+fn on_off(level: bool) -> &'static str {
+    if level { "on" } else { "off" }
+}
+
+fn line(transcript: &mut String, time: Duration, direction: char, text: &str) {
+    let _ = writeln!(transcript, "{:>4}.{:03} {} {}", time.as_secs(), time.subsec_millis(), direction, text);
+}
+
+fn collect(transcript: &mut String, partial: &mut (Duration, Vec<u8>), time: Duration, data: &[u8], direction: char) {
+    for &byte in data {
+        if partial.1.is_empty() {
+            partial.0 = time;
+        }
+        partial.1.push(byte);
+        if byte != b';' {
+            continue;
+        }
+
+        let command = escape(&partial.1);
+        line(transcript, partial.0, direction, &command);
+        let fields = console::decode(&command).unwrap_or_default();
+        for field in &fields {
+            let _ = writeln!(transcript, "           {:<16} {:<13} {}", field.name, field.raw, field.meaning);
+        }
+        if let Some(error) = RadioError::from_answer(&command) {
+            let _ = writeln!(transcript, "           {}", error);
+        } else if fields.is_empty() {
+            if let Some(info) = command.get(..2).and_then(console::command) {
+                let _ = writeln!(transcript, "           {}", info.description);
+            }
+        }
+        partial.1.clear();
+    }
+}
+
+/// A transport that plays a recorded session back to the
+/// driver. Everything the driver writes must match what was
+/// sent when recording, and changes of the modem control lines
+/// must happen in the recorded order; otherwise the operation
+/// fails with `InvalidData`.
+///
+/// Recorded answers are read back in order, but only once
+/// everything sent before them has been written. A recorded
+/// timeout is reported as a `TimedOut` error until the driver
+/// sends something else, so that it waits out its own timeout as
+/// it did when recording. Reading when no answer is due reports
+/// end of file otherwise, as `MemoryTransport` does.
+#[derive(Debug, Default)]
+pub struct Replay {
+    traffic: VecDeque<Traffic>,
+    timed_out: bool,
+}
+
+impl Replay {
+    /// Plays back the recording at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Replay::new(Session::open(path)?))
+    }
+
+    /// Plays back `session`.
+    pub fn new(session: Session) -> Self {
+        Replay {
+            traffic: session.entries.into_iter().map(|e| e.traffic).collect(),
+            timed_out: false,
+        }
+    }
+
+    /// Returns whether the whole session has been played back.
+    pub fn is_finished(&self) -> bool {
+        self.traffic.is_empty()
+    }
+
+    /// Returns the traffic not yet played back.
+    pub fn remaining(&self) -> &VecDeque<Traffic> {
+        &self.traffic
+    }
+
+    fn mismatch(&self, actual: &Traffic) -> io::Error {
+        let expected = match self.traffic.front() {
+            Some(traffic) => format_traffic(traffic),
+            None => "end of recording".to_owned(),
+        };
+        let message = format!("replay expected {}, got {}", expected, format_traffic(actual));
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+
+    /// Skips a timeout the driver has stopped waiting out.
+    fn skip_timeout(&mut self) {
+        self.timed_out = false;
+        if self.traffic.front() == Some(&Traffic::Timeout) {
+            self.traffic.pop_front();
+        }
+    }
+
+    fn expect_line(&mut self, traffic: Traffic) -> io::Result<()> {
+        self.skip_timeout();
+        if self.traffic.front() != Some(&traffic) {
+            return Err(self.mismatch(&traffic));
+        }
+        self.traffic.pop_front();
+        Ok(())
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = match self.traffic.front_mut() {
+            Some(Traffic::Received(data)) => data,
+            Some(Traffic::Timeout) => {
+                self.traffic.pop_front();
+                self.timed_out = true;
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "recorded timeout"));
+            },
+            _ if self.timed_out => return Err(io::Error::new(io::ErrorKind::TimedOut, "recorded timeout")),
+            _ => return Ok(0),
+        };
+        self.timed_out = false;
+        let read = data.len().min(buf.len());
+        buf[..read].copy_from_slice(&data[..read]);
+        data.drain(..read);
+        if data.is_empty() {
+            self.traffic.pop_front();
+        }
+        Ok(read)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.skip_timeout();
+        let data = match self.traffic.front_mut() {
+            Some(Traffic::Sent(data)) if data.iter().zip(buf).all(|(a, b)| a == b) => data,
+            _ => return Err(self.mismatch(&Traffic::Sent(buf.to_vec()))),
+        };
+        let written = data.len().min(buf.len());
+        data.drain(..written);
+        if data.is_empty() {
+            self.traffic.pop_front();
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replay {
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.expect_line(Traffic::Rts(level))
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.expect_line(Traffic::Dtr(level))
+    }
+}
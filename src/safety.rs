@@ -0,0 +1,375 @@
+//! Interlocks that stop the driver keying the radio where the
+//! station is not allowed to transmit.
+//!
+//! A `BandPrivileges` table lists the segments the licence allows,
+//! optionally limited to some modes, and frequencies that must never
+//! be transmitted on. It can be loaded from a text file:
+//!
+//! ```text
+//! # 40 m, US General class
+//! allow 7.025MHz-7.125MHz CW FSK FSK-R
+//! allow 7.175MHz-7.300MHz
+//! block 7.074MHz
+//! ```
+//!
+//! `block` takes a single frequency or a range. Once a table is set
+//! with `TS480::set_band_privileges`, the driver checks the transmit
+//! frequency and mode before every `TX` command it sends, and before
+//! every command that would change them while transmitting.
+
+use crate::commands::parse_field;
+use crate::transport::Transport;
+use crate::types::{Frequency, Mode, Vfo};
+use crate::{RadioError, RadioResult, TS480};
+
+use std::fmt;
+
+/// A range of frequencies the station may transmit on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BandSegment {
+    pub low: Frequency,
+    pub high: Frequency,
+    /// The modes allowed in the segment; empty for all modes
+    pub modes: Vec<Mode>,
+}
+
+impl BandSegment {
+    fn contains(&self, frequency: Frequency) -> bool {
+        frequency >= self.low && frequency <= self.high
+    }
+
+    fn allows(&self, mode: Mode) -> bool {
+        self.modes.is_empty() || self.modes.contains(&mode)
+    }
+}
+
+impl fmt::Display for BandSegment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} - {}", self.low, self.high)?;
+        for mode in &self.modes {
+            write!(f, " {}", mode)?;
+        }
+        Ok(())
+    }
+}
+
+/// The frequencies and modes the station may transmit on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BandPrivileges {
+    pub allowed: Vec<BandSegment>,
+    /// Ranges never transmitted on, even within an allowed segment
+    pub blocked: Vec<(Frequency, Frequency)>,
+}
+
+impl BandPrivileges {
+    /// Creates a table that allows nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows transmitting from `low` to `high` in `modes`,
+    /// or in all modes if `modes` is empty.
+    pub fn allow(mut self, low: Frequency, high: Frequency, modes: &[Mode]) -> Self {
+        self.allowed.push(BandSegment { low, high, modes: modes.to_vec() });
+        self
+    }
+
+    /// Refuses transmitting from `low` to `high`.
+    pub fn block(mut self, low: Frequency, high: Frequency) -> Self {
+        self.blocked.push((low, high));
+        self
+    }
+
+    /// Returns an error saying why transmitting on `frequency`
+    /// in `mode` is not allowed, if it is not.
+    pub fn check(&self, frequency: Frequency, mode: Mode) -> RadioResult<()> {
+        if let Some(&(low, high)) = self.blocked.iter().find(|&&(low, high)| frequency >= low && frequency <= high) {
+            return Err(RadioError::TransmitRefused(if low == high {
+                format!("{} is blocked", frequency)
+            } else {
+                format!("{} is within blocked range {} - {}", frequency, low, high)
+            }));
+        }
+
+        let mut segments = self.allowed.iter().filter(|segment| segment.contains(frequency)).peekable();
+        if segments.peek().is_none() {
+            Err(RadioError::TransmitRefused(format!("{} is outside the allowed bands", frequency)))
+        } else if segments.any(|segment| segment.allows(mode)) {
+            Ok(())
+        } else {
+            Err(RadioError::TransmitRefused(format!("{} is not allowed on {}", mode, frequency)))
+        }
+    }
+
+    /// Reads a table from `allow` and `block` lines, ignoring
+    /// blank lines and `#` comments.
+    pub fn parse(text: &str) -> RadioResult<Self> {
+        let mut privileges = BandPrivileges::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| RadioError::InvalidParameter(format!("line {}: {}", index + 1, message));
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let range = words.next().ok_or_else(|| error(format!("missing frequency after {:?}", keyword)))?;
+            let (low, high) = parse_range(range).map_err(|e| error(message(e)))?;
+
+            match keyword {
+                "allow" => {
+                    let modes = words.map(str::parse::<Mode>).collect::<RadioResult<Vec<_>>>()
+                        .map_err(|e| error(message(e)))?;
+                    privileges = privileges.allow(low, high, &modes);
+                },
+                "block" => {
+                    if let Some(extra) = words.next() {
+                        return Err(error(format!("unexpected {:?} after blocked frequency", extra)));
+                    }
+                    privileges = privileges.block(low, high);
+                },
+                _ => return Err(error(format!("unknown keyword {:?}", keyword))),
+            }
+        }
+
+        Ok(privileges)
+    }
+}
+
+/// Parses `low-high` or a single frequency.
+fn parse_range(range: &str) -> RadioResult<(Frequency, Frequency)> {
+    let (low, high) = match range.find('-') {
+        Some(dash) => (range[..dash].parse::<Frequency>()?, range[dash + 1..].parse::<Frequency>()?),
+        None => {
+            let frequency = range.parse::<Frequency>()?;
+            (frequency, frequency)
+        },
+    };
+
+    if low > high {
+        return Err(RadioError::InvalidParameter(format!("range {:?} ends below its start", range)));
+    }
+    Ok((low, high))
+}
+
+fn message(e: RadioError) -> String {
+    match e {
+        RadioError::InvalidParameter(message) => message,
+        e => e.to_string(),
+    }
+}
+
+impl<T: Transport> TS480<T> {
+    /// Sets the frequencies and modes the driver may key the radio
+    /// on; `None`, the default, to transmit anywhere.
+    pub fn set_band_privileges(&mut self, privileges: Option<BandPrivileges>) {
+        self.privileges = privileges;
+    }
+
+    /// Returns the band privilege table, if one is set.
+    pub fn band_privileges(&self) -> Option<&BandPrivileges> {
+        self.privileges.as_ref()
+    }
+
+    /// Reads the frequency and mode the radio would transmit on,
+    /// including split and XIT.
+    pub fn read_tx_frequency(&mut self) -> RadioResult<(Frequency, Mode)> {
+        let status = self.status()?;
+
+        let mut frequency = status.frequency;
+        if status.split && status.vfo != Vfo::Memory {
+            frequency = match self.read_tx_vfo()? {
+                Vfo::A => self.read_frequency_a()?,
+                Vfo::B => self.read_frequency_b()?,
+                Vfo::Memory => frequency,
+            };
+        }
+        if status.xit {
+            let hz = frequency.hz() as i64 + i64::from(status.rit_xit_offset);
+            frequency = Frequency::from_hz(hz.max(0) as u64)?;
+        }
+
+        Ok((frequency, status.mode))
+    }
+
+    /// Checks the band privileges before keying the radio.
+    pub(crate) fn check_privileges(&mut self) -> RadioResult<()> {
+        if self.privileges.is_none() {
+            return Ok(());
+        }
+
+        let (frequency, mode) = self.read_tx_frequency()?;
+        self.check_transmit(frequency, mode)
+    }
+
+    /// Checks the band privileges before sending `data`. `TX` is
+    /// checked against the frequency and mode the radio would
+    /// transmit on, and so, while the radio is transmitting, are
+    /// commands that change them.
+    ///
+    /// A command that changes them followed by `TX` in the same
+    /// `data` is refused, since the transmit frequency cannot be
+    /// known until the radio has acted on it.
+    pub(crate) fn check_commands(&mut self, data: &str) -> RadioResult<()> {
+        if self.privileges.is_none() {
+            return Ok(());
+        }
+
+        let mut keyed = self.transmitting.is_some();
+        let mut retuned = None;
+        for command in data.split(';').map(str::trim) {
+            let prefix = command.get(..2).unwrap_or(command);
+            let params = command.get(2..).unwrap_or("");
+            if prefix == "TX" {
+                if let Some(retune) = retuned {
+                    return Err(RadioError::TransmitRefused(format!("{} must be sent before TX, not with it", retune)));
+                }
+                self.check_privileges()?;
+                keyed = true;
+            } else if command == "RX" {
+                keyed = false;
+            } else if retunes(prefix, params) {
+                if keyed {
+                    self.check_retune(prefix, params)?;
+                } else {
+                    retuned = Some(prefix);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a command that changes the transmit frequency or
+    /// mode while transmitting.
+    fn check_retune(&mut self, prefix: &str, params: &str) -> RadioResult<()> {
+        match prefix {
+            // Either VFO could be the transmit VFO, so both are checked
+            "FA" | "FB" => {
+                let frequency = Frequency::from_cat(params)?;
+                let mode = self.read_mode()?;
+                self.check_transmit(frequency, mode)
+            },
+            "MD" => {
+                let mode = Mode::from_cat(params)?;
+                let (frequency, _) = self.read_tx_frequency()?;
+                self.check_transmit(frequency, mode)
+            },
+            "MC" => match self.read_memory(parse_field(params, 0..3)?)? {
+                Some(memory) => self.check_transmit(memory.tx_frequency.unwrap_or(memory.rx_frequency), memory.mode),
+                // The radio refuses to recall an empty channel
+                None => Ok(()),
+            },
+            _ => Err(RadioError::TransmitRefused(format!("{} is not allowed while transmitting", prefix))),
+        }
+    }
+
+    fn check_transmit(&self, frequency: Frequency, mode: Mode) -> RadioResult<()> {
+        match self.privileges {
+            Some(ref privileges) => privileges.check(frequency, mode),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns whether a command changes the transmit frequency or
+/// mode; some only read them when sent without parameters.
+fn retunes(prefix: &str, params: &str) -> bool {
+    match prefix {
+        "BD" | "BU" | "DN" | "RC" | "RD" | "RU" | "SV" | "UP" => true,
+        "CH" | "FA" | "FB" | "FR" | "FT" | "MC" | "MD" | "TS" | "XT" => !params.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mhz(mhz: f64) -> Frequency {
+        Frequency::from_hz((mhz * 1_000_000.0).round() as u64).unwrap()
+    }
+
+    fn refusal(result: RadioResult<()>) -> String {
+        match result {
+            Err(RadioError::TransmitRefused(reason)) => reason,
+            result => panic!("expected TransmitRefused, got {:?}", result),
+        }
+    }
+
+    fn parse_error(text: &str) -> String {
+        match BandPrivileges::parse(text) {
+            Err(RadioError::InvalidParameter(message)) => message,
+            result => panic!("expected InvalidParameter, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn parses_table() {
+        let privileges = BandPrivileges::parse(
+            "# 40 m\n\nallow 7.025MHz-7.125MHz CW FSK FSK-R  # CW and RTTY\nallow 7175kHz-7.3MHz\nblock 7.074MHz\nblock 7.2MHz-7.21MHz\n",
+        ).unwrap();
+
+        assert_eq!(privileges, BandPrivileges::new()
+            .allow(mhz(7.025), mhz(7.125), &[Mode::Cw, Mode::Fsk, Mode::FskReverse])
+            .allow(mhz(7.175), mhz(7.3), &[])
+            .block(mhz(7.074), mhz(7.074))
+            .block(mhz(7.2), mhz(7.21)));
+    }
+
+    #[test]
+    fn reports_line_of_parse_error() {
+        assert_eq!(parse_error("allow 7MHz-7.3MHz\nallow\n"), "line 2: missing frequency after \"allow\"");
+        assert_eq!(parse_error("\npermit 7MHz"), "line 2: unknown keyword \"permit\"");
+        assert!(parse_error("allow 7.3MHz-7MHz").starts_with("line 1: range \"7.3MHz-7MHz\" ends below"));
+        assert_eq!(parse_error("block 7MHz CW"), "line 1: unexpected \"CW\" after blocked frequency");
+        assert!(parse_error("allow 7MHz-7.3MHz XYZ").starts_with("line 1: "));
+        assert!(parse_error("# ok\nallow 7MHz-lots").starts_with("line 2: "));
+    }
+
+    #[test]
+    fn checks_segments_and_modes() {
+        let privileges = BandPrivileges::new()
+            .allow(mhz(7.0), mhz(7.125), &[Mode::Cw])
+            .allow(mhz(7.1), mhz(7.3), &[Mode::Lsb]);
+
+        assert!(privileges.check(mhz(7.0), Mode::Cw).is_ok());
+        assert!(privileges.check(mhz(7.3), Mode::Lsb).is_ok());
+        // Overlapping segments allow the modes of both
+        assert!(privileges.check(mhz(7.11), Mode::Cw).is_ok());
+        assert!(privileges.check(mhz(7.11), Mode::Lsb).is_ok());
+
+        assert_eq!(refusal(privileges.check(mhz(7.05), Mode::Lsb)), "LSB is not allowed on 7.050000 MHz");
+        assert_eq!(refusal(privileges.check(mhz(6.999), Mode::Cw)), "6.999000 MHz is outside the allowed bands");
+        assert!(refusal(privileges.check(mhz(7.301), Mode::Lsb)).contains("outside"));
+        assert!(refusal(BandPrivileges::new().check(mhz(7.0), Mode::Cw)).contains("outside"));
+    }
+
+    #[test]
+    fn blocked_frequencies_override_segments() {
+        let privileges = BandPrivileges::new()
+            .allow(mhz(7.0), mhz(7.3), &[])
+            .block(mhz(7.074), mhz(7.074))
+            .block(mhz(7.2), mhz(7.21));
+
+        assert_eq!(refusal(privileges.check(mhz(7.074), Mode::Usb)), "7.074000 MHz is blocked");
+        assert_eq!(
+            refusal(privileges.check(mhz(7.205), Mode::Lsb)),
+            "7.205000 MHz is within blocked range 7.200000 MHz - 7.210000 MHz",
+        );
+        assert!(privileges.check(mhz(7.075), Mode::Usb).is_ok());
+        assert!(privileges.check(mhz(7.211), Mode::Lsb).is_ok());
+    }
+
+    #[test]
+    fn recognises_retuning_commands() {
+        assert!(retunes("FA", "00007074000"));
+        assert!(!retunes("FA", ""));
+        assert!(retunes("BU", ""));
+        assert!(retunes("MC", " 01"));
+        assert!(!retunes("MC", ""));
+        assert!(!retunes("AG", "0100"));
+        assert!(!retunes("IF", ""));
+    }
+}
@@ -0,0 +1,575 @@
+//! A software TS-480 that answers CAT commands, for
+//! testing without a radio attached.
+//!
+//! `Simulator` implements `Transport`, so it can be handed
+//! straight to `TS480::with_transport`. The `ts480-sim` binary
+//! serves it over a TCP port or a pseudo-terminal.
+
+use crate::menu::{ExMenu, LAST_MENU};
+use crate::status::TransceiverStatus;
+use crate::transport::Transport;
+use crate::types::{Antenna, Frequency, Mode, ToneMode, Vfo};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Read, Write};
+
+/// Format of the parameters of a simple setting.
+#[derive(Clone, Copy)]
+enum Param {
+    /// `width` digits between `min` and `max`
+    Digits(usize, u32, u32),
+    /// `n` characters, each `0` or `1`
+    Flags(usize),
+    /// `n` printable characters
+    Text(usize),
+}
+
+impl Param {
+    fn accepts(&self, params: &str) -> bool {
+        match *self {
+            Param::Digits(width, min, max) => {
+                params.len() == width
+                    && params.bytes().all(|b| b.is_ascii_digit())
+                    && params.parse::<u32>().map(|n| n >= min && n <= max).unwrap_or(false)
+            },
+            Param::Flags(n) => params.len() == n && params.bytes().all(|b| b == b'0' || b == b'1'),
+            Param::Text(n) => params.len() == n,
+        }
+    }
+}
+
+/// A setting that is simply stored and read back.
+struct Setting {
+    prefix: &'static str,
+    param: Param,
+    default: &'static str,
+    /// Fixed characters the radio appends when answering
+    suffix: &'static str,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting { prefix: "AC", param: Param::Flags(3), default: "000", suffix: "" },
+    Setting { prefix: "AG0", param: Param::Digits(3, 0, 255), default: "100", suffix: "" },
+    Setting { prefix: "BC", param: Param::Digits(1, 0, 2), default: "0", suffix: "" },
+    Setting { prefix: "CA", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "CN", param: Param::Digits(2, 0, 41), default: "08", suffix: "" },
+    Setting { prefix: "CT", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "FS", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "FW", param: Param::Digits(4, 0, 9999), default: "2400", suffix: "" },
+    Setting { prefix: "GT", param: Param::Digits(3, 0, 20), default: "004", suffix: "" },
+    Setting { prefix: "IS", param: Param::Text(5), default: " 0000", suffix: "" },
+    Setting { prefix: "KS", param: Param::Digits(3, 10, 60), default: "020", suffix: "" },
+    Setting { prefix: "LK", param: Param::Flags(2), default: "00", suffix: "" },
+    Setting { prefix: "MF", param: Param::Digits(1, 0, 1), default: "0", suffix: "" },
+    Setting { prefix: "MG", param: Param::Digits(3, 0, 100), default: "050", suffix: "" },
+    Setting { prefix: "ML", param: Param::Digits(3, 0, 9), default: "000", suffix: "" },
+    Setting { prefix: "NB", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "NL", param: Param::Digits(3, 1, 10), default: "005", suffix: "" },
+    Setting { prefix: "NR", param: Param::Digits(1, 0, 2), default: "0", suffix: "" },
+    Setting { prefix: "NT", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "PA", param: Param::Flags(1), default: "0", suffix: "0" },
+    Setting { prefix: "PC", param: Param::Digits(3, 5, 100), default: "100", suffix: "" },
+    Setting { prefix: "PR", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "RA", param: Param::Digits(2, 0, 1), default: "00", suffix: "00" },
+    Setting { prefix: "RG", param: Param::Digits(3, 0, 255), default: "255", suffix: "" },
+    Setting { prefix: "RL", param: Param::Digits(2, 1, 10), default: "01", suffix: "" },
+    Setting { prefix: "RT", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "SC", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "SD", param: Param::Digits(4, 0, 1000), default: "0050", suffix: "" },
+    Setting { prefix: "SH", param: Param::Digits(2, 0, 11), default: "07", suffix: "" },
+    Setting { prefix: "SL", param: Param::Digits(2, 0, 11), default: "00", suffix: "" },
+    Setting { prefix: "SQ0", param: Param::Digits(3, 0, 255), default: "000", suffix: "" },
+    Setting { prefix: "TN", param: Param::Digits(2, 0, 41), default: "08", suffix: "" },
+    Setting { prefix: "TO", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "TS", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "VD", param: Param::Digits(4, 0, 3000), default: "0750", suffix: "" },
+    Setting { prefix: "VG", param: Param::Digits(3, 0, 9), default: "004", suffix: "" },
+    Setting { prefix: "VX", param: Param::Flags(1), default: "0", suffix: "" },
+    Setting { prefix: "XT", param: Param::Flags(1), default: "0", suffix: "" },
+];
+
+/// Commands after which a radio in Auto Information
+/// mode also sends its `IF` status.
+const IF_TRIGGERS: &[&str] = &["FA", "FB", "FR", "FT", "MD", "RC", "RD", "RU", "RT", "XT", "TX", "RX"];
+
+/// Lower edges of the bands stepped through by `BU`/`BD`, in Hz
+const BAND_EDGES: &[u64] = &[
+    1_800_000, 3_500_000, 7_000_000, 10_100_000, 14_000_000,
+    18_068_000, 21_000_000, 24_890_000, 28_000_000, 50_000_000,
+];
+
+/// Parameters of an unprogrammed memory channel, after the channel number
+const EMPTY_MEMORY: &str = "00000000000000000000000000000000000";
+
+/// The state of the simulated radio.
+#[derive(Clone, Debug)]
+pub struct SimulatorState {
+    pub power_on: bool,
+    pub frequency_a: Frequency,
+    pub frequency_b: Frequency,
+    pub mode: Mode,
+    pub rx_vfo: Vfo,
+    pub tx_vfo: Vfo,
+    pub antenna: Antenna,
+    pub memory_channel: u8,
+    pub transmitting: bool,
+    pub auto_information: u8,
+    pub rit_offset: i16,
+
+    /// S-meter reading, 0 - 30
+    pub smeter: u16,
+
+    /// Meter selected with `RM`: 1 = SWR; 2 = COMP; 3 = ALC
+    pub selected_meter: u8,
+
+    /// SWR, COMP and ALC meter readings, 0 - 30
+    pub meters: [u16; 3],
+
+    /// Memory channel parameters following `MRp1ccc`, keyed by `p1ccc`
+    pub memories: BTreeMap<String, String>,
+
+    /// Menu values keyed by menu number
+    pub menu: BTreeMap<u16, String>,
+
+    /// Number of commands still to be answered with `O;`, as
+    /// the radio does while it is busy processing
+    pub busy: u8,
+
+    /// Number of commands still to be answered with `E;`, as
+    /// if they had been garbled on the line
+    pub garbled: u8,
+
+    settings: BTreeMap<&'static str, String>,
+}
+
+impl Default for SimulatorState {
+    fn default() -> Self {
+        SimulatorState {
+            power_on: true,
+            frequency_a: Frequency::from_hz(14_074_000).unwrap(),
+            frequency_b: Frequency::from_hz(7_074_000).unwrap(),
+            mode: Mode::Usb,
+            rx_vfo: Vfo::A,
+            tx_vfo: Vfo::A,
+            antenna: Antenna::Ant1,
+            memory_channel: 0,
+            transmitting: false,
+            auto_information: 0,
+            rit_offset: 0,
+            smeter: 0,
+            selected_meter: 1,
+            meters: [0; 3],
+            memories: BTreeMap::new(),
+            menu: ExMenu::ALL.iter()
+                .map(|item| (item.number(), item.format_value(item.default_value()).unwrap()))
+                .collect(),
+            busy: 0,
+            garbled: 0,
+            settings: SETTINGS.iter().map(|s| (s.prefix, s.default.to_owned())).collect(),
+        }
+    }
+}
+
+impl SimulatorState {
+    /// Returns the stored parameters of a simple setting,
+    /// e.g. `setting("AG0")` for the AF gain.
+    pub fn setting(&self, prefix: &str) -> Option<&str> {
+        self.settings.get(prefix).map(|s| s.as_str())
+    }
+
+    /// Returns the frequency of the VFO used for receiving.
+    pub fn rx_frequency(&self) -> Frequency {
+        match self.rx_vfo {
+            Vfo::B => self.frequency_b,
+            _ => self.frequency_a,
+        }
+    }
+
+    fn set_rx_frequency(&mut self, frequency: Frequency) {
+        match self.rx_vfo {
+            Vfo::B => self.frequency_b = frequency,
+            _ => self.frequency_a = frequency,
+        }
+    }
+
+    fn flag(&self, prefix: &str) -> char {
+        self.setting(prefix).and_then(|s| s.chars().next()).unwrap_or('0')
+    }
+}
+
+/// A simulated TS-480.
+///
+/// Bytes written to the simulator are executed as commands
+/// once their `;` arrives, and the answers are queued to be read.
+/// Reading when no answer is queued reports end of file.
+#[derive(Debug, Default)]
+pub struct Simulator {
+    state: SimulatorState,
+    pending: Vec<u8>,
+    output: VecDeque<u8>,
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> &SimulatorState {
+        &self.state
+    }
+
+    /// Changes the state directly. Nothing is reported in
+    /// Auto Information mode; use `front_panel` for that.
+    pub fn state_mut(&mut self) -> &mut SimulatorState {
+        &mut self.state
+    }
+
+    /// Processes bytes received from the PC.
+    pub fn feed(&mut self, data: &[u8]) {
+        for &byte in data {
+            if byte != b';' {
+                self.pending.push(byte);
+                continue;
+            }
+
+            let command = ::std::mem::take(&mut self.pending);
+            let answer = match String::from_utf8(command) {
+                _ if self.state.garbled > 0 => {
+                    self.state.garbled -= 1;
+                    Some("E".to_owned())
+                },
+                _ if self.state.busy > 0 => {
+                    self.state.busy -= 1;
+                    Some("O".to_owned())
+                },
+                Ok(ref command) if command.bytes().all(|b| b.is_ascii_graphic() || b == b' ') => {
+                    self.execute(command.trim_start_matches(|c: char| c.is_ascii_whitespace()))
+                },
+                _ => Some("E".to_owned()),
+            };
+
+            if let Some(answer) = answer {
+                self.push_answer(&answer);
+            }
+        }
+    }
+
+    /// Returns and clears all queued answers.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.drain(..).collect()
+    }
+
+    /// Applies a set command, without its `;`, as if the operator
+    /// had made the change on the radio. In Auto Information mode
+    /// the change is reported to the PC.
+    ///
+    /// Returns `false` if the command was rejected.
+    pub fn front_panel(&mut self, command: &str) -> bool {
+        match self.execute(command) {
+            Some(ref answer) if answer == "?" || answer == "E" => return false,
+            _ => {},
+        }
+
+        if self.state.auto_information != 0 {
+            if let Some(prefix) = self.read_prefix(command) {
+                if let Some(answer) = self.execute(&prefix) {
+                    self.push_answer(&answer);
+                }
+            }
+            if IF_TRIGGERS.iter().any(|p| command.starts_with(p)) {
+                let answer = self.information();
+                self.push_answer(&answer);
+            }
+        }
+
+        true
+    }
+
+    fn push_answer(&mut self, answer: &str) {
+        self.output.extend(answer.bytes());
+        self.output.push_back(b';');
+    }
+
+    /// Returns the command that reads back what `command` set.
+    fn read_prefix(&self, command: &str) -> Option<String> {
+        if let Some(setting) = SETTINGS.iter().find(|s| command.starts_with(s.prefix)) {
+            return Some(setting.prefix.to_owned());
+        }
+        match command.get(..2) {
+            Some("EX") => command.get(..9).map(|s| s.to_owned()),
+            Some("TX") | Some("RX") | Some("RC") | Some("RD") | Some("RU") => None,
+            Some(p) => Some(p.to_owned()),
+            None => None,
+        }
+    }
+
+    /// Executes one command, without its `;`, and returns
+    /// the answer without its `;`, if any.
+    fn execute(&mut self, command: &str) -> Option<String> {
+        let (prefix, params) = match (command.get(..2), command.get(2..)) {
+            (Some(prefix), Some(params)) => (prefix, params),
+            _ => return Some("?".to_owned()),
+        };
+
+        if !self.state.power_on && prefix != "PS" {
+            return None;
+        }
+
+        let answer = match prefix {
+            "AI" => match params {
+                "" => Ok(Some(format!("AI{}", self.state.auto_information))),
+                "0" | "2" | "4" => {
+                    self.state.auto_information = params.parse().unwrap();
+                    Ok(None)
+                },
+                _ => Err(()),
+            },
+            "AN" => match params {
+                "" => Ok(Some(format!("AN{}", self.state.antenna.to_cat()))),
+                _ => Antenna::from_cat(params).map(|a| { self.state.antenna = a; None }).map_err(|_| ()),
+            },
+            "BD" | "BU" if params.is_empty() => {
+                self.step_band(prefix == "BU");
+                Ok(None)
+            },
+            "BY" if params.is_empty() => Ok(Some(format!("BY{}0", self.state.smeter.min(1)))),
+            "CH" => match params {
+                "0" => self.step_frequency(1_000),
+                "1" => self.step_frequency(-1_000),
+                _ => Err(()),
+            },
+            "DN" if params.is_empty() => self.step_frequency(-10),
+            "UP" if params.is_empty() => self.step_frequency(10),
+            "EX" => self.menu(params),
+            "FA" | "FB" => match params {
+                "" => Ok(Some(format!("{}{}", prefix, if prefix == "FA" {
+                    self.state.frequency_a.to_cat()
+                } else {
+                    self.state.frequency_b.to_cat()
+                }))),
+                _ => Frequency::from_cat(params).map(|f| {
+                    if prefix == "FA" {
+                        self.state.frequency_a = f;
+                    } else {
+                        self.state.frequency_b = f;
+                    }
+                    None
+                }).map_err(|_| ()),
+            },
+            "FR" | "FT" => match params {
+                "" => Ok(Some(format!("{}{}", prefix, if prefix == "FR" {
+                    self.state.rx_vfo.to_cat()
+                } else {
+                    self.state.tx_vfo.to_cat()
+                }))),
+                _ => Vfo::from_cat(params).map(|vfo| {
+                    if prefix == "FR" {
+                        self.state.rx_vfo = vfo;
+                    }
+                    self.state.tx_vfo = vfo;
+                    None
+                }).map_err(|_| ()),
+            },
+            "FV" if params.is_empty() => Ok(Some("FV1.00".to_owned())),
+            "ID" if params.is_empty() => Ok(Some("ID020".to_owned())),
+            "IF" if params.is_empty() => Ok(Some(self.information())),
+            "KY" => match params {
+                "" => Ok(Some("KY0".to_owned())),
+                _ if params.len() <= 25 => Ok(None),
+                _ => Err(()),
+            },
+            "MC" => match params {
+                "" => Ok(Some(format!("MC{:03}", self.state.memory_channel))),
+                _ if Param::Digits(3, 0, 109).accepts(params) => {
+                    self.state.memory_channel = params.parse().unwrap();
+                    Ok(None)
+                },
+                _ => Err(()),
+            },
+            "MD" => match params {
+                "" => Ok(Some(format!("MD{}", self.state.mode.to_cat()))),
+                _ => Mode::from_cat(params).map(|m| { self.state.mode = m; None }).map_err(|_| ()),
+            },
+            "MR" => self.read_memory(params),
+            "MW" => self.write_memory(params),
+            "PS" => match params {
+                "" => Ok(Some(format!("PS{}", self.state.power_on as u8))),
+                "0" | "1" => {
+                    self.state.power_on = params == "1";
+                    Ok(None)
+                },
+                _ => Err(()),
+            },
+            "QI" | "SV" if params.is_empty() => Ok(None),
+            "RC" if params.is_empty() => {
+                self.state.rit_offset = 0;
+                Ok(None)
+            },
+            "RD" | "RU" => match params {
+                _ if Param::Digits(5, 0, 9999).accepts(params) => {
+                    let step: i16 = params.parse().unwrap();
+                    let offset = if prefix == "RU" { step } else { -step };
+                    self.state.rit_offset = (self.state.rit_offset + offset).clamp(-9999, 9999);
+                    Ok(None)
+                },
+                _ => Err(()),
+            },
+            "RM" => match params {
+                // `selected_meter` is public, so it may be out of range
+                "" => match (self.state.selected_meter as usize).checked_sub(1).and_then(|i| self.state.meters.get(i)) {
+                    Some(value) => Ok(Some(format!("RM{}{:04}", self.state.selected_meter, value))),
+                    None => Err(()),
+                },
+                _ => match params {
+                    "1" => Ok(1),
+                    "2" => Ok(2),
+                    "3" => Ok(3),
+                    _ => Err(()),
+                }.map(|meter| { self.state.selected_meter = meter; None }),
+            },
+            "SM" => match params {
+                "" | "0" => Ok(Some(format!("SM0{:04}", self.state.smeter))),
+                _ => Err(()),
+            },
+            "TX" => match params {
+                "" | "0" | "1" | "2" => {
+                    self.state.transmitting = true;
+                    Ok(None)
+                },
+                _ => Err(()),
+            },
+            "RX" if params.is_empty() => {
+                self.state.transmitting = false;
+                Ok(None)
+            },
+            "VR" => match params {
+                "1" | "2" => Ok(None),
+                _ => Err(()),
+            },
+            _ => self.setting(command),
+        };
+
+        match answer {
+            Ok(answer) => answer,
+            Err(()) => Some("?".to_owned()),
+        }
+    }
+
+    /// Reads or changes one of the simple settings.
+    fn setting(&mut self, command: &str) -> Result<Option<String>, ()> {
+        let setting = SETTINGS.iter().find(|s| command.starts_with(s.prefix)).ok_or(())?;
+        let params = &command[setting.prefix.len()..];
+
+        if params.is_empty() {
+            let value = &self.state.settings[setting.prefix];
+            return Ok(Some(format!("{}{}{}", setting.prefix, value, setting.suffix)));
+        }
+
+        if !setting.param.accepts(params) {
+            return Err(());
+        }
+        self.state.settings.insert(setting.prefix, params.to_owned());
+        Ok(None)
+    }
+
+    fn menu(&mut self, params: &str) -> Result<Option<String>, ()> {
+        let number = params.get(..3).and_then(|n| n.parse::<u16>().ok()).ok_or(())?;
+        if number > LAST_MENU || params.get(3..7) != Some("0000") {
+            return Err(());
+        }
+
+        let value = &params[7..];
+        if value.is_empty() {
+            return Ok(Some(format!("EX{}{}", &params[..7], self.state.menu[&number])));
+        }
+        self.state.menu.insert(number, value.to_owned());
+        Ok(None)
+    }
+
+    fn read_memory(&mut self, params: &str) -> Result<Option<String>, ()> {
+        if params.len() != 4 || !Param::Digits(1, 0, 1).accepts(&params[..1])
+            || !Param::Digits(3, 0, 109).accepts(&params[1..]) {
+            return Err(());
+        }
+
+        let memory = self.state.memories.get(params).map(|m| m.as_str()).unwrap_or(EMPTY_MEMORY);
+        Ok(Some(format!("MR{}{}", params, memory)))
+    }
+
+    fn write_memory(&mut self, params: &str) -> Result<Option<String>, ()> {
+        let fields = EMPTY_MEMORY.len();
+        if params.len() < 4 + fields || params.len() > 4 + fields + 8
+            || !Param::Digits(1, 0, 1).accepts(&params[..1])
+            || !Param::Digits(3, 0, 109).accepts(&params[1..4]) {
+            return Err(());
+        }
+
+        self.state.memories.insert(params[..4].to_owned(), params[4..].to_owned());
+        Ok(None)
+    }
+
+    fn step_frequency(&mut self, hz: i64) -> Result<Option<String>, ()> {
+        let current = self.state.rx_frequency().hz() as i64;
+        let frequency = Frequency::from_hz((current + hz) as u64).map_err(|_| ())?;
+        self.state.set_rx_frequency(frequency);
+        Ok(None)
+    }
+
+    fn step_band(&mut self, up: bool) {
+        let current = self.state.rx_frequency().hz();
+        let band = BAND_EDGES.iter().rposition(|&edge| edge <= current);
+        let next = match (band, up) {
+            (Some(i), true) => BAND_EDGES[(i + 1) % BAND_EDGES.len()],
+            (None, true) => BAND_EDGES[0],
+            (Some(0), false) | (None, false) => BAND_EDGES[BAND_EDGES.len() - 1],
+            (Some(i), false) => BAND_EDGES[i - 1],
+        };
+        self.state.set_rx_frequency(Frequency::from_hz(next).unwrap());
+    }
+
+    /// Builds the `IF` answer, without its `;`.
+    fn information(&self) -> String {
+        let state = &self.state;
+        let tone = match (state.flag("TO"), state.flag("CT")) {
+            ('1', _) => ToneMode::Tone,
+            (_, '1') => ToneMode::Ctcss,
+            _ => ToneMode::Off,
+        };
+        let tone_number = if tone == ToneMode::Ctcss { state.setting("CN") } else { state.setting("TN") };
+
+        let status = TransceiverStatus {
+            frequency: state.rx_frequency(),
+            rit_xit_offset: state.rit_offset,
+            rit: state.flag("RT") == '1',
+            xit: state.flag("XT") == '1',
+            memory_channel: state.memory_channel,
+            transmitting: state.transmitting,
+            mode: state.mode,
+            vfo: state.rx_vfo,
+            scanning: state.flag("SC") == '1',
+            split: state.rx_vfo != state.tx_vfo,
+            tone,
+            tone_number: tone_number.and_then(|n| n.parse().ok()).unwrap_or(0),
+        };
+        format!("IF{}", status.to_cat())
+    }
+}
+
+impl Read for Simulator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.output.read(buf)
+    }
+}
+
+impl Write for Simulator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.feed(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Simulator {}
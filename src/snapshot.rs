@@ -0,0 +1,513 @@
+//! Backup and restore of the radio's configuration: panel
+//! settings, the `EX` menu and the memory channels.
+//!
+//! Snapshots are saved as TOML, with settings kept as the raw
+//! parameters of their command and menu items keyed by number:
+//!
+//! ```toml
+//! [settings]
+//! FA = "00014074000"  # VFO A frequency
+//! MD = "2"  # Mode
+//!
+//! [menu]
+//! 005 = "1"
+//!
+//! [memories.003]
+//! rx_frequency = 29620000
+//! tx_frequency = 29520000
+//! mode = "FM"
+//! tone = "Tone"
+//! tone_number = 8
+//! name = "RPT"
+//! ```
+//!
+//! The radio keeps two menu banks, A and B, selected with `MF`. Only
+//! the bank selected when the snapshot is taken is captured, and the
+//! `MF` setting records which one it was; `restore` selects that bank
+//! before writing the menu, leaving the other bank as it is. To back
+//! up both, take a snapshot with each bank selected.
+
+use crate::memory::{MemoryChannel, MEMORY_CHANNELS};
+use crate::menu::LAST_MENU;
+use crate::transport::Transport;
+use crate::types::{Frequency, Mode};
+use crate::{RadioError, RadioResult, TS480};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+
+/// The settings captured in a snapshot, in the order they are
+/// restored: command, number of parameter characters it is set
+/// with, and description. Menu bank comes first, as the `EX` values
+/// are those of the selected bank, and the receive VFO before the
+/// transmit VFO, since selecting it also selects the transmit VFO.
+const SETTINGS: &[(&str, usize, &str)] = &[
+    ("MF", 1, "Menu bank"),
+    ("FR", 1, "Receive VFO"),
+    ("FT", 1, "Transmit VFO"),
+    ("FA", 11, "VFO A frequency"),
+    ("FB", 11, "VFO B frequency"),
+    ("MD", 1, "Mode"),
+    ("AN", 1, "Antenna"),
+    ("FW", 4, "Filter width"),
+    ("SH", 2, "High cut"),
+    ("SL", 2, "Low cut"),
+    ("IS", 5, "IF shift"),
+    ("GT", 3, "AGC time constant"),
+    ("PA", 1, "Preamp"),
+    ("RA", 2, "Attenuator"),
+    ("NB", 1, "Noise blanker"),
+    ("NL", 3, "Noise blanker level"),
+    ("NR", 1, "Noise reduction"),
+    ("RL", 2, "Noise reduction level"),
+    ("NT", 1, "Auto notch"),
+    ("BC", 1, "Beat cancel"),
+    ("AG0", 3, "AF gain"),
+    ("RG", 3, "RF gain"),
+    ("SQ0", 3, "Squelch"),
+    ("PC", 3, "Output power"),
+    ("MG", 3, "Microphone gain"),
+    ("PR", 1, "Speech processor"),
+    ("ML", 3, "Monitor level"),
+    ("KS", 3, "Keying speed"),
+    ("SD", 4, "Break-in delay"),
+    ("VX", 1, "VOX"),
+    ("VG", 3, "VOX gain"),
+    ("VD", 4, "VOX delay"),
+    ("TO", 1, "Tone"),
+    ("TN", 2, "Tone frequency"),
+    ("CT", 1, "CTCSS"),
+    ("CN", 2, "CTCSS frequency"),
+    ("FS", 1, "Fine step"),
+];
+
+/// The configuration of the radio at one point in time.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RigSnapshot {
+    /// Raw parameters of the panel settings, keyed by
+    /// command, e.g. `"00014074000"` for `"FA"`
+    pub settings: BTreeMap<String, String>,
+
+    /// Raw menu values of the bank selected by the `MF`
+    /// setting, keyed by number
+    pub menu: BTreeMap<u16, String>,
+
+    /// Programmed memory channels, keyed by number
+    pub memories: BTreeMap<u8, MemoryChannel>,
+}
+
+/// An item that differs between two snapshots.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// The setting, menu item or memory channel, e.g. `"Menu 05"`
+    pub item: String,
+
+    /// The value in the first snapshot, if it has the item
+    pub old: Option<String>,
+
+    /// The value in the second snapshot, if it has the item
+    pub new: Option<String>,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let none = "(none)".to_owned();
+        write!(f, "{}: {} -> {}", self.item, self.old.as_ref().unwrap_or(&none), self.new.as_ref().unwrap_or(&none))
+    }
+}
+
+impl RigSnapshot {
+    /// Lists the items that differ between this snapshot and `other`.
+    pub fn diff(&self, other: &RigSnapshot) -> Vec<Difference> {
+        let mut differences = Vec::new();
+
+        diff_maps(&self.settings, &other.settings, &mut differences, |command| {
+            match SETTINGS.iter().find(|&&(prefix, _, _)| prefix == command) {
+                Some(&(_, _, description)) => format!("{} ({})", command, description),
+                None => command.to_owned(),
+            }
+        });
+        diff_maps(&self.menu, &other.menu, &mut differences, |number| format!("Menu {:02}", number));
+        diff_maps(&self.memories, &other.memories, &mut differences, |channel| format!("Memory {:03}", channel));
+
+        differences
+    }
+
+    /// Returns the snapshot as a TOML document.
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::from("# TS-480 configuration snapshot\n\n[settings]\n");
+
+        for &(prefix, _, description) in SETTINGS {
+            if let Some(value) = self.settings.get(prefix) {
+                writeln!(toml, "{} = {}  # {}", prefix, quote(value), description).unwrap();
+            }
+        }
+        for (prefix, value) in &self.settings {
+            if !SETTINGS.iter().any(|&(known, _, _)| known == prefix) {
+                writeln!(toml, "{} = {}", prefix, quote(value)).unwrap();
+            }
+        }
+
+        toml.push_str("\n[menu]\n");
+        for (number, value) in &self.menu {
+            writeln!(toml, "{:03} = {}", number, quote(value)).unwrap();
+        }
+
+        for (channel, memory) in &self.memories {
+            writeln!(toml, "\n[memories.{:03}]", channel).unwrap();
+            writeln!(toml, "rx_frequency = {}", memory.rx_frequency.hz()).unwrap();
+            if let Some(tx_frequency) = memory.tx_frequency {
+                writeln!(toml, "tx_frequency = {}", tx_frequency.hz()).unwrap();
+            }
+            writeln!(toml, "mode = {}", quote(&memory.mode.to_string())).unwrap();
+            writeln!(toml, "lockout = {}", memory.lockout).unwrap();
+            writeln!(toml, "tone = {}", quote(&memory.tone.to_string())).unwrap();
+            writeln!(toml, "tone_number = {}", memory.tone_number).unwrap();
+            writeln!(toml, "ctcss_number = {}", memory.ctcss_number).unwrap();
+            writeln!(toml, "fm_narrow = {}", memory.fm_narrow).unwrap();
+            writeln!(toml, "name = {}", quote(&memory.name)).unwrap();
+        }
+
+        toml
+    }
+
+    /// Reads a snapshot from a TOML document as written by `to_toml`.
+    pub fn from_toml(toml: &str) -> RadioResult<Self> {
+        let mut snapshot = RigSnapshot::default();
+        let mut section = String::new();
+        let mut fields = BTreeSet::new();
+        let mut sections = BTreeSet::new();
+
+        for (index, line) in toml.lines().enumerate() {
+            let error = |message: String| RadioError::InvalidParameter(format!("line {}: {}", index + 1, message));
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                section = line.trim_start_matches('[').trim_end_matches(']').trim().to_owned();
+                if let Some(channel) = section.strip_prefix("memories.") {
+                    let channel = channel.trim_matches('"').parse::<u8>().ok()
+                        .filter(|&channel| channel < MEMORY_CHANNELS)
+                        .ok_or_else(|| error(format!("invalid memory channel {:?}", channel)))?;
+                    let placeholder = MemoryChannel::new(Frequency::from_hz(Frequency::MIN_HZ)?, Mode::Usb);
+                    snapshot.memories.insert(channel, placeholder);
+                    section = format!("memories.{}", channel);
+                } else if section != "settings" && section != "menu" {
+                    return Err(error(format!("unknown section {:?}", section)));
+                }
+                if !sections.insert(section.clone()) {
+                    return Err(error(format!("duplicate section [{}]", section)));
+                }
+                continue;
+            }
+
+            let (key, value) = parse_line(line).map_err(&error)?;
+            if section == "settings" {
+                let value = value.string().map_err(&error)?;
+                snapshot.settings.insert(key.to_owned(), value);
+            } else if section == "menu" {
+                let number = key.parse::<u16>().ok()
+                    .filter(|&number| number <= LAST_MENU)
+                    .ok_or_else(|| error(format!("invalid menu number {:?}", key)))?;
+                snapshot.menu.insert(number, value.string().map_err(&error)?);
+            } else if let Some(channel) = section.strip_prefix("memories.") {
+                let channel: u8 = channel.parse().unwrap();
+                let memory = snapshot.memories.get_mut(&channel).unwrap();
+                set_memory_field(memory, key, value).map_err(&error)?;
+                fields.insert((channel, key));
+            } else {
+                return Err(error(format!("{} is outside of a section", key)));
+            }
+        }
+
+        for (channel, memory) in &snapshot.memories {
+            for field in &["rx_frequency", "mode"] {
+                if !fields.contains(&(*channel, *field)) {
+                    return Err(RadioError::InvalidParameter(format!("memory {:03} has no {}", channel, field)));
+                }
+            }
+            memory.validate().map_err(|e| {
+                RadioError::InvalidParameter(format!("memory {:03}: {}", channel, e))
+            })?;
+        }
+        Ok(snapshot)
+    }
+}
+
+impl<T: Transport> TS480<T> {
+    /// Captures the panel settings, the menu items of the
+    /// selected bank and all programmed memory channels. The
+    /// other menu bank is not captured.
+    pub fn snapshot(&mut self) -> RadioResult<RigSnapshot> {
+        let mut snapshot = RigSnapshot::default();
+
+        for &(prefix, length, _) in SETTINGS {
+            let params = self.read_parameters(prefix)?;
+            let value = params.get(..length).ok_or_else(|| RadioError::UnexpectedAnswer(params.clone()))?;
+            snapshot.settings.insert(prefix.to_owned(), value.to_owned());
+        }
+
+        for number in 0..=LAST_MENU {
+            snapshot.menu.insert(number, self.read_extended_menu(number)?);
+        }
+
+        for channel in 0..MEMORY_CHANNELS {
+            if let Some(memory) = self.read_memory(channel)? {
+                snapshot.memories.insert(channel, memory);
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Restores a snapshot, then reads the configuration back to
+    /// verify it. Returns the items that do not have the value from
+    /// the snapshot, i.e. nothing if the restore succeeded.
+    ///
+    /// Settings not in the snapshot are left as they are, as are
+    /// memory channels that are unprogrammed in the snapshot.
+    pub fn restore(&mut self, snapshot: &RigSnapshot) -> RadioResult<Vec<Difference>> {
+        for &(prefix, length, _) in SETTINGS {
+            if let Some(value) = snapshot.settings.get(prefix) {
+                if value.len() != length || !value.bytes().all(|b| b == b' ' || (b.is_ascii_graphic() && b != b';')) {
+                    return Err(RadioError::InvalidParameter(format!("invalid {} setting {:?}", prefix, value)));
+                }
+                self.transmit(&format!("{}{};", prefix, value))?;
+            }
+        }
+
+        for (&number, value) in &snapshot.menu {
+            self.set_extended_menu(number, value)?;
+        }
+
+        for (&channel, memory) in &snapshot.memories {
+            self.write_memory(channel, memory)?;
+        }
+
+        let actual = self.snapshot()?;
+        Ok(snapshot.diff(&actual).into_iter().filter(|difference| difference.old.is_some()).collect())
+    }
+}
+
+fn diff_maps<K, V, F>(old: &BTreeMap<K, V>, new: &BTreeMap<K, V>, differences: &mut Vec<Difference>, name: F)
+    where K: Ord, V: PartialEq + fmt::Display, F: Fn(&K) -> String
+{
+    for (key, value) in old {
+        match new.get(key) {
+            Some(other) if other == value => {},
+            other => differences.push(Difference {
+                item: name(key),
+                old: Some(value.to_string()),
+                new: other.map(|other| other.to_string()),
+            }),
+        }
+    }
+    for (key, value) in new {
+        if !old.contains_key(key) {
+            differences.push(Difference { item: name(key), old: None, new: Some(value.to_string()) });
+        }
+    }
+}
+
+/// A TOML value of the types used in snapshots
+enum Value {
+    Str(String),
+    Int(u64),
+    Bool(bool),
+}
+
+impl Value {
+    fn string(self) -> Result<String, String> {
+        match self {
+            Value::Str(s) => Ok(s),
+            _ => Err("expected a string".to_owned()),
+        }
+    }
+
+    fn int(self) -> Result<u64, String> {
+        match self {
+            Value::Int(n) => Ok(n),
+            _ => Err("expected an integer".to_owned()),
+        }
+    }
+
+    fn boolean(self) -> Result<bool, String> {
+        match self {
+            Value::Bool(b) => Ok(b),
+            _ => Err("expected true or false".to_owned()),
+        }
+    }
+}
+
+/// Splits a `key = value` line, ignoring a trailing comment.
+fn parse_line(line: &str) -> Result<(&str, Value), String> {
+    let equals = line.find('=').ok_or_else(|| format!("expected key = value, got {:?}", line))?;
+    let key = line[..equals].trim().trim_matches('"');
+    let rest = line[equals + 1..].trim_start();
+
+    let (value, rest) = if let Some(quoted) = rest.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((i, '"')) => break i + 1,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 't')) => value.push('\t'),
+                    _ => return Err(format!("invalid escape in {:?}", line)),
+                },
+                Some((_, c)) => value.push(c),
+                None => return Err(format!("unterminated string in {:?}", line)),
+            }
+        };
+        (Value::Str(value), &quoted[end..])
+    } else {
+        let end = rest.find('#').unwrap_or(rest.len());
+        let word = rest[..end].trim();
+        let value = match word {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::Int(word.replace('_', "").parse().map_err(|_| format!("invalid value {:?}", word))?),
+        };
+        (value, &rest[end..])
+    };
+
+    let rest = rest.trim();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected {:?} after value", rest));
+    }
+    Ok((key, value))
+}
+
+fn set_memory_field(memory: &mut MemoryChannel, key: &str, value: Value) -> Result<(), String> {
+    let small = |n: u64| if n <= u8::MAX as u64 { Ok(n as u8) } else { Err(format!("{} is too large", n)) };
+
+    match key {
+        "rx_frequency" => memory.rx_frequency = Frequency::from_hz(value.int()?).map_err(|e| e.to_string())?,
+        "tx_frequency" => memory.tx_frequency = Some(Frequency::from_hz(value.int()?).map_err(|e| e.to_string())?),
+        "mode" => memory.mode = value.string()?.parse().map_err(|e: RadioError| e.to_string())?,
+        "lockout" => memory.lockout = value.boolean()?,
+        "tone" => memory.tone = value.string()?.parse().map_err(|e: RadioError| e.to_string())?,
+        "tone_number" => memory.tone_number = small(value.int()?)?,
+        "ctcss_number" => memory.ctcss_number = small(value.int()?)?,
+        "fm_narrow" => memory.fm_narrow = value.boolean()?,
+        "name" => memory.name = value.string()?,
+        _ => return Err(format!("unknown memory field {:?}", key)),
+    }
+    Ok(())
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToneMode;
+
+    fn snapshot() -> RigSnapshot {
+        let mut snapshot = RigSnapshot::default();
+        snapshot.settings.insert("FA".to_owned(), "00014074000".to_owned());
+        snapshot.settings.insert("MD".to_owned(), "2".to_owned());
+        snapshot.settings.insert("XX".to_owned(), "a \"b\" # c".to_owned());
+        snapshot.menu.insert(0, "1".to_owned());
+        snapshot.menu.insert(LAST_MENU, "\\".to_owned());
+
+        let mut repeater = MemoryChannel::new(Frequency::from_hz(29_620_000).unwrap(), Mode::Fm);
+        repeater.tx_frequency = Some(Frequency::from_hz(29_520_000).unwrap());
+        repeater.tone = ToneMode::Tone;
+        repeater.tone_number = 8;
+        repeater.lockout = true;
+        repeater.fm_narrow = true;
+        repeater.name = "\"Q\\#= '".to_owned();
+        snapshot.memories.insert(3, repeater);
+        snapshot.memories.insert(109, MemoryChannel::new(Frequency::from_hz(7_074_000).unwrap(), Mode::Usb));
+        snapshot
+    }
+
+    fn error(toml: &str) -> String {
+        match RigSnapshot::from_toml(toml) {
+            Err(RadioError::InvalidParameter(message)) => message,
+            result => panic!("expected InvalidParameter, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let snapshot = snapshot();
+        assert_eq!(RigSnapshot::from_toml(&snapshot.to_toml()).unwrap(), snapshot);
+        assert_eq!(RigSnapshot::from_toml(&RigSnapshot::default().to_toml()).unwrap(), RigSnapshot::default());
+    }
+
+    #[test]
+    fn quotes_and_escapes_strings() {
+        let toml = snapshot().to_toml();
+        assert!(toml.contains("name = \"\\\"Q\\\\#= '\"\n"), "{}", toml);
+        assert!(toml.contains("XX = \"a \\\"b\\\" # c\"\n"), "{}", toml);
+        assert!(toml.contains(&format!("{:03} = \"\\\\\"\n", LAST_MENU)), "{}", toml);
+    }
+
+    #[test]
+    fn parses_documented_example() {
+        let snapshot = RigSnapshot::from_toml(concat!(
+            "[settings]\n",
+            "FA = \"00014074000\"  # VFO A frequency\n",
+            "MD = \"2\"  # Mode\n",
+            "\n",
+            "[menu]\n",
+            "005 = \"1\"\n",
+            "\n",
+            "[memories.003]\n",
+            "rx_frequency = 29620000\n",
+            "tx_frequency = 29520000\n",
+            "mode = \"FM\"\n",
+            "tone = \"Tone\"\n",
+            "tone_number = 8\n",
+            "name = \"RPT\"\n",
+        )).unwrap();
+
+        assert_eq!(snapshot.settings["FA"], "00014074000");
+        assert_eq!(snapshot.settings["MD"], "2");
+        assert_eq!(snapshot.menu[&5], "1");
+        let memory = &snapshot.memories[&3];
+        assert_eq!(memory.rx_frequency.hz(), 29_620_000);
+        assert_eq!(memory.tx_frequency.map(|f| f.hz()), Some(29_520_000));
+        assert_eq!(memory.mode, Mode::Fm);
+        assert_eq!(memory.tone, ToneMode::Tone);
+        assert_eq!(memory.tone_number, 8);
+        assert_eq!(memory.name, "RPT");
+        assert!(!memory.lockout);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        assert!(error("[settings]\nFA = \"000").contains("unterminated string"));
+        assert!(error("[settings]\nFA = \"\\q\"").contains("invalid escape"));
+        assert!(error("[settings]\nFA = \"1\" 2").contains("after value"));
+        assert!(error("[settings]\nFA = 1").contains("expected a string"));
+        assert!(error("[radio]").contains("unknown section"));
+        assert!(error("FA = \"1\"").contains("outside of a section"));
+        assert!(error("[menu]\n999 = \"1\"").contains("invalid menu number"));
+        assert!(error("[memories.110]").contains("invalid memory channel"));
+        assert!(error("[memories.001]\nmode = \"FM\"").contains("has no rx_frequency"));
+        assert!(error("[memories.001]\nrx_frequency = 7074000\nmode = \"FM\"\nname = \"TOO LONG!\"").contains("at most"));
+        assert!(error("[memories.001]\ncolour = \"red\"").contains("unknown memory field"));
+    }
+
+    #[test]
+    fn rejects_duplicate_sections() {
+        let memory = "rx_frequency = 7074000\nmode = \"USB\"\n";
+        let toml = format!("[memories.001]\n{}\n[memories.002]\n{}\n[memories.001]\nname = \"B\"\n", memory, memory);
+        assert_eq!(error(&toml), "line 9: duplicate section [memories.1]");
+        // However the channel is written
+        assert!(error(&format!("[memories.7]\n{}[memories.\"007\"]\n", memory)).contains("duplicate section [memories.7]"));
+        assert_eq!(error("[settings]\n[menu]\n[settings]\n"), "line 3: duplicate section [settings]");
+        assert_eq!(error("[menu]\n[ menu ]\n"), "line 2: duplicate section [menu]");
+    }
+}
@@ -0,0 +1,249 @@
+//! The transceiver status returned by the `IF` command.
+
+use crate::commands::{parse_field, parse_flag};
+use crate::transport::Transport;
+use crate::types::{Frequency, Mode, ToneMode, Vfo};
+use crate::{RadioError, RadioResult, TS480};
+
+use std::str::FromStr;
+
+/// Decoded `IF` answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransceiverStatus {
+    /// Frequency of the VFO or memory channel in use
+    pub frequency: Frequency,
+
+    /// RIT/XIT offset in Hz, -9999 - 9999
+    pub rit_xit_offset: i16,
+
+    pub rit: bool,
+    pub xit: bool,
+
+    /// Memory channel number, 0 - 109
+    pub memory_channel: u8,
+
+    pub transmitting: bool,
+    pub mode: Mode,
+
+    /// VFO or memory channel used for receiving
+    pub vfo: Vfo,
+
+    pub scanning: bool,
+    pub split: bool,
+    pub tone: ToneMode,
+
+    /// Tone or CTCSS frequency number, 0 - 41
+    pub tone_number: u8,
+}
+
+impl TransceiverStatus {
+    /// Parses the 35 parameter characters of an `IF` answer.
+    ///
+    /// | Characters | Field                         |
+    /// |------------|-------------------------------|
+    /// | 0 - 10     | Frequency in Hz               |
+    /// | 11 - 15    | Spaces                        |
+    /// | 16 - 20    | RIT/XIT offset, e.g. `+0150`  |
+    /// | 21         | RIT on/off                    |
+    /// | 22         | XIT on/off                    |
+    /// | 23 - 25    | Memory channel                |
+    /// | 26         | 0 = RX; 1 = TX                |
+    /// | 27         | Mode                          |
+    /// | 28         | VFO, as for `FR`              |
+    /// | 29         | Scan on/off                   |
+    /// | 30         | Split on/off                  |
+    /// | 31         | 0 = Off; 1 = Tone; 2 = CTCSS  |
+    /// | 32 - 33    | Tone frequency number         |
+    /// | 34         | Always 0                      |
+    pub fn from_cat(params: &str) -> RadioResult<Self> {
+        let unexpected = || RadioError::UnexpectedAnswer(params.to_owned());
+
+        if params.len() != 35 || !params.is_ascii() {
+            return Err(unexpected());
+        }
+
+        let offset = &params[16..21];
+        let rit_xit_offset = match offset.as_bytes()[0] {
+            b'+' | b'-' | b'0' if offset[1..].bytes().all(|b| b.is_ascii_digit()) => {
+                offset.parse().map_err(|_| unexpected())?
+            },
+            _ => return Err(unexpected()),
+        };
+
+        Ok(TransceiverStatus {
+            frequency: Frequency::from_cat(&params[0..11])?,
+            rit_xit_offset,
+            rit: parse_flag(params, 21)?,
+            xit: parse_flag(params, 22)?,
+            memory_channel: parse_field(params, 23..26)?,
+            transmitting: parse_flag(params, 26)?,
+            mode: Mode::from_cat(&params[27..28])?,
+            vfo: Vfo::from_cat(&params[28..29])?,
+            scanning: parse_flag(params, 29)?,
+            split: parse_flag(params, 30)?,
+            tone: ToneMode::from_cat(&params[31..32])?,
+            tone_number: parse_field(params, 32..34)?,
+        })
+    }
+
+    /// Returns the parameters of the `IF` answer describing this status.
+    pub fn to_cat(&self) -> String {
+        format!(
+            "{}     {:+05}{}{}{:03}{}{}{}{}{}{}{:02}0",
+            self.frequency.to_cat(),
+            self.rit_xit_offset,
+            self.rit as u8,
+            self.xit as u8,
+            self.memory_channel,
+            self.transmitting as u8,
+            self.mode.to_cat(),
+            self.vfo.to_cat(),
+            self.scanning as u8,
+            self.split as u8,
+            self.tone.to_cat(),
+            self.tone_number,
+        )
+    }
+}
+
+/// Parses a complete `IF` answer, e.g. as captured from
+/// the serial line: `IF00014074000     +000000000020000080;`
+impl FromStr for TransceiverStatus {
+    type Err = RadioError;
+
+    fn from_str(s: &str) -> RadioResult<Self> {
+        let s = s.trim();
+        if !s.starts_with("IF") || !s.ends_with(';') {
+            return Err(RadioError::UnexpectedAnswer(s.to_owned()));
+        }
+        TransceiverStatus::from_cat(&s[2..s.len() - 1])
+    }
+}
+
+impl<T: Transport> TS480<T> {
+    /// Reads and decodes the transceiver status.
+    pub fn status(&mut self) -> RadioResult<TransceiverStatus> {
+        let params = self.read_information()?;
+        TransceiverStatus::from_cat(&params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "IF00014074000     +000000000020000080;";
+
+    fn status(answer: &str) -> TransceiverStatus {
+        answer.parse().unwrap()
+    }
+
+    fn is_unexpected(answer: &str) -> bool {
+        matches!(answer.parse::<TransceiverStatus>(), Err(RadioError::UnexpectedAnswer(_)))
+    }
+
+    #[test]
+    fn parses_documented_example() {
+        assert_eq!(status(EXAMPLE), TransceiverStatus {
+            frequency: Frequency::from_hz(14_074_000).unwrap(),
+            rit_xit_offset: 0,
+            rit: false,
+            xit: false,
+            memory_channel: 0,
+            transmitting: false,
+            mode: Mode::Usb,
+            vfo: Vfo::A,
+            scanning: false,
+            split: false,
+            tone: ToneMode::Off,
+            tone_number: 8,
+        });
+    }
+
+    #[test]
+    fn round_trips() {
+        let example = status(EXAMPLE);
+        assert_eq!(format!("IF{};", example.to_cat()), EXAMPLE);
+
+        let variant = TransceiverStatus {
+            rit_xit_offset: -9999,
+            xit: true,
+            memory_channel: 109,
+            vfo: Vfo::Memory,
+            tone: ToneMode::Ctcss,
+            tone_number: 41,
+            ..example
+        };
+        assert_eq!(TransceiverStatus::from_cat(&variant.to_cat()).unwrap(), variant);
+    }
+
+    #[test]
+    fn parses_split() {
+        let split = status("IF00007074000     +000000000131010000;");
+        assert_eq!(split.mode, Mode::Cw);
+        assert_eq!(split.vfo, Vfo::B);
+        assert!(split.transmitting);
+        assert!(split.split);
+        assert!(!split.scanning);
+    }
+
+    #[test]
+    fn parses_rit_and_xit() {
+        let rit = status("IF00014074000     +015010000020000000;");
+        assert_eq!(rit.rit_xit_offset, 150);
+        assert!(rit.rit);
+        assert!(!rit.xit);
+
+        let xit = status("IF00014074000     -999901000020000000;");
+        assert_eq!(xit.rit_xit_offset, -9999);
+        assert!(!xit.rit);
+        assert!(xit.xit);
+
+        // The radio sends a zero offset without its sign
+        assert_eq!(status("IF00014074000     0000000000020000000;").rit_xit_offset, 0);
+    }
+
+    #[test]
+    fn parses_tones() {
+        let tone = status("IF00029620000     +000000000040001120;");
+        assert_eq!(tone.mode, Mode::Fm);
+        assert_eq!(tone.tone, ToneMode::Tone);
+        assert_eq!(tone.tone_number, 12);
+
+        let ctcss = status("IF00029620000     +000000000040002410;");
+        assert_eq!(ctcss.tone, ToneMode::Ctcss);
+        assert_eq!(ctcss.tone_number, 41);
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        assert!(is_unexpected("IF00014074000     +00000000002000008;"));
+        assert!(is_unexpected("IF00014074000     +0000000000200000800;"));
+        assert!(is_unexpected("IF;"));
+        assert!(TransceiverStatus::from_cat("").is_err());
+    }
+
+    #[test]
+    fn rejects_missing_prefix_or_terminator() {
+        assert!(is_unexpected("FA00014074000     +000000000020000080;"));
+        assert!(is_unexpected("IF00014074000     +000000000020000080"));
+    }
+
+    #[test]
+    fn rejects_malformed_fields() {
+        // Offset sign and digits
+        assert!(is_unexpected("IF00014074000     *000000000020000080;"));
+        assert!(is_unexpected("IF00014074000     +0a0000000020000080;"));
+        // RIT flag
+        assert!(is_unexpected("IF00014074000     +000020000020000080;"));
+        // Memory channel
+        assert!(is_unexpected("IF00014074000     +000000x00020000080;"));
+        // Non-ASCII of the right length in bytes
+        assert!(TransceiverStatus::from_cat("00014074000     +0000000000200000\u{e9}").is_err());
+        // Frequency, mode, VFO and tone mode
+        assert!("IF0001407400x     +000000000020000080;".parse::<TransceiverStatus>().is_err());
+        assert!("IF00014074000     +0000000000A0000080;".parse::<TransceiverStatus>().is_err());
+        assert!("IF00014074000     +000000000029000080;".parse::<TransceiverStatus>().is_err());
+        assert!("IF00014074000     +000000000020009080;".parse::<TransceiverStatus>().is_err());
+    }
+}
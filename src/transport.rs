@@ -0,0 +1,248 @@
+//! Connections the radio can be driven over.
+
+use serial::{SerialPort, SystemPort};
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+/// A byte stream connected to the radio.
+///
+/// The modem control lines are optional; transports
+/// without them ignore requests to change them.
+pub trait Transport: Read + Write {
+    /// Sets the level of the RTS line.
+    fn set_rts(&mut self, _level: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Sets the level of the DTR line.
+    fn set_dtr(&mut self, _level: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Discards bytes that have been received but not read yet,
+    /// such as an answer that arrived after its query timed out.
+    fn discard_input(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Opens a second handle to the same connection, which the
+    /// transmit watchdog uses to switch the radio to receive from
+    /// its own thread.
+    ///
+    /// Returns `None`, the default, if the connection cannot be
+    /// shared; the maximum transmit time is then only enforced
+    /// while the driver is in use.
+    fn try_clone_port(&self) -> io::Result<Option<Box<dyn Transport + Send>>> {
+        Ok(None)
+    }
+}
+
+impl Transport for SystemPort {
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        Ok(SerialPort::set_rts(self, level)?)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        Ok(SerialPort::set_dtr(self, level)?)
+    }
+
+    #[cfg(unix)]
+    fn discard_input(&mut self) -> io::Result<()> {
+        flush_input(self.as_raw_fd())
+    }
+
+    #[cfg(unix)]
+    fn try_clone_port(&self) -> io::Result<Option<Box<dyn Transport + Send>>> {
+        let fd = unsafe { libc::dup(self.as_raw_fd()) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(Box::new(ClonedPort(unsafe { File::from_raw_fd(fd) }))))
+    }
+}
+
+/// A duplicate of a serial port's file descriptor, which shares
+/// the port's settings.
+#[cfg(unix)]
+struct ClonedPort(File);
+
+#[cfg(unix)]
+impl Read for ClonedPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+#[cfg(unix)]
+impl Write for ClonedPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(unix)]
+impl Transport for ClonedPort {
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        set_modem_line(self.0.as_raw_fd(), libc::TIOCM_RTS, level)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        set_modem_line(self.0.as_raw_fd(), libc::TIOCM_DTR, level)
+    }
+}
+
+#[cfg(unix)]
+fn set_modem_line(fd: RawFd, line: libc::c_int, level: bool) -> io::Result<()> {
+    let request = if level { libc::TIOCMBIS } else { libc::TIOCMBIC };
+    if unsafe { libc::ioctl(fd, request, &line) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A network connection, e.g. to a serial port shared with ser2net.
+impl Transport for TcpStream {
+    fn discard_input(&mut self) -> io::Result<()> {
+        self.set_nonblocking(true)?;
+        let mut chunk = [0; 64];
+        let result = loop {
+            match self.read(&mut chunk) {
+                Ok(0) => break Ok(()),
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => break Err(e),
+            }
+        };
+        self.set_nonblocking(false)?;
+        result
+    }
+
+    fn try_clone_port(&self) -> io::Result<Option<Box<dyn Transport + Send>>> {
+        Ok(Some(Box::new(self.try_clone()?)))
+    }
+}
+
+/// An already-opened device such as a pseudo-terminal.
+impl Transport for File {
+    #[cfg(unix)]
+    fn discard_input(&mut self) -> io::Result<()> {
+        match flush_input(self.as_raw_fd()) {
+            // Not a terminal, so nothing is buffered
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOTTY) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn try_clone_port(&self) -> io::Result<Option<Box<dyn Transport + Send>>> {
+        Ok(Some(Box::new(self.try_clone()?)))
+    }
+}
+
+#[cfg(unix)]
+fn flush_input(fd: RawFd) -> io::Result<()> {
+    if unsafe { libc::tcflush(fd, libc::TCIFLUSH) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        (**self).set_rts(level)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        (**self).set_dtr(level)
+    }
+
+    fn discard_input(&mut self) -> io::Result<()> {
+        (**self).discard_input()
+    }
+
+    fn try_clone_port(&self) -> io::Result<Option<Box<dyn Transport + Send>>> {
+        (**self).try_clone_port()
+    }
+}
+
+/// An in-memory transport. Reads are served from data
+/// queued with `push_input` and everything written is kept
+/// until it is taken with `take_output`.
+///
+/// Reading when no input is queued reports end of file.
+#[derive(Debug, Default)]
+pub struct MemoryTransport {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    rts: bool,
+    dtr: bool,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues `data` to be read by the driver.
+    pub fn push_input(&mut self, data: &[u8]) {
+        self.input.extend(data);
+    }
+
+    /// Returns everything written so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Returns and clears everything written so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        ::std::mem::take(&mut self.output)
+    }
+
+    /// Returns the current level of the RTS line.
+    pub fn rts(&self) -> bool {
+        self.rts
+    }
+
+    /// Returns the current level of the DTR line.
+    pub fn dtr(&self) -> bool {
+        self.dtr
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn set_rts(&mut self, level: bool) -> io::Result<()> {
+        self.rts = level;
+        Ok(())
+    }
+
+    fn set_dtr(&mut self, level: bool) -> io::Result<()> {
+        self.dtr = level;
+        Ok(())
+    }
+}
@@ -0,0 +1,415 @@
+//! Value types for command parameters, with conversions
+//! to and from their CAT representation.
+
+use crate::{RadioError, RadioResult};
+
+use std::fmt;
+use std::str::FromStr;
+
+/// A frequency within the TS-480's tuning range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Frequency(u64);
+
+impl Frequency {
+    /// The lowest frequency the TS-480 can tune, in Hz
+    pub const MIN_HZ: u64 = 30_000;
+
+    /// The highest frequency the TS-480 can tune, in Hz
+    pub const MAX_HZ: u64 = 60_000_000;
+
+    /// Creates a frequency from a value in Hz, checking
+    /// that it is within the TS-480's tuning range.
+    pub fn from_hz(hz: u64) -> RadioResult<Self> {
+        if !(Self::MIN_HZ..=Self::MAX_HZ).contains(&hz) {
+            return Err(RadioError::InvalidParameter(
+                format!("frequency must be {} - {} Hz, got {}", Self::MIN_HZ, Self::MAX_HZ, hz)
+            ));
+        }
+        Ok(Frequency(hz))
+    }
+
+    /// Returns the frequency in Hz
+    pub fn hz(&self) -> u64 {
+        self.0
+    }
+
+    /// Returns the 11-digit CAT representation, e.g. `00014074000`
+    pub fn to_cat(&self) -> String {
+        format!("{:011}", self.0)
+    }
+
+    /// Parses the 11-digit CAT representation
+    pub fn from_cat(params: &str) -> RadioResult<Self> {
+        if params.len() != 11 || !params.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(RadioError::UnexpectedAnswer(params.to_owned()));
+        }
+        params.parse()
+            .map_err(|_| RadioError::UnexpectedAnswer(params.to_owned()))
+            .and_then(Frequency::from_hz)
+    }
+}
+
+impl fmt::Display for Frequency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:06} MHz", self.0 / 1_000_000, self.0 % 1_000_000)
+    }
+}
+
+/// Parses a frequency in Hz, optionally followed by a
+/// `Hz`, `kHz` or `MHz` unit, e.g. `14074000`, `7.1 MHz`
+/// or `3573kHz`.
+impl FromStr for Frequency {
+    type Err = RadioError;
+
+    fn from_str(s: &str) -> RadioResult<Self> {
+        let invalid = || RadioError::InvalidParameter(format!("invalid frequency {:?}", s));
+
+        let s = s.trim();
+        let lower = s.to_ascii_lowercase();
+        let (number, multiplier) = if lower.ends_with("mhz") {
+            (&s[..s.len() - 3], 1_000_000)
+        } else if lower.ends_with("khz") {
+            (&s[..s.len() - 3], 1_000)
+        } else if lower.ends_with("hz") {
+            (&s[..s.len() - 2], 1)
+        } else {
+            (s, 1)
+        };
+
+        let number = number.trim();
+        let (whole, fraction) = match number.find('.') {
+            Some(dot) => (&number[..dot], &number[dot + 1..]),
+            None => (number, ""),
+        };
+
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(invalid());
+        }
+        if !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        // Only as many decimal places as the unit allows,
+        // since the radio cannot tune below 1 Hz.
+        let places = match multiplier {
+            1_000_000 => 6,
+            1_000 => 3,
+            _ => 0,
+        };
+        if fraction.len() > places {
+            return Err(invalid());
+        }
+
+        let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| invalid())? };
+        let mut hz = whole.checked_mul(multiplier).ok_or_else(invalid)?;
+        if !fraction.is_empty() {
+            let scale = 10u64.pow((places - fraction.len()) as u32);
+            let fraction: u64 = fraction.parse().map_err(|_| invalid())?;
+            hz = hz.checked_add(fraction * scale).ok_or_else(invalid)?;
+        }
+
+        Frequency::from_hz(hz)
+    }
+}
+
+/// Operating mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Lsb,
+    Usb,
+    Cw,
+    Fm,
+    Am,
+    Fsk,
+    CwReverse,
+    FskReverse,
+}
+
+impl Mode {
+    /// Returns the CAT representation, e.g. `2` for USB
+    pub fn to_cat(&self) -> char {
+        match *self {
+            Mode::Lsb => '1',
+            Mode::Usb => '2',
+            Mode::Cw => '3',
+            Mode::Fm => '4',
+            Mode::Am => '5',
+            Mode::Fsk => '6',
+            Mode::CwReverse => '7',
+            Mode::FskReverse => '9',
+        }
+    }
+
+    /// Parses the CAT representation
+    pub fn from_cat(params: &str) -> RadioResult<Self> {
+        match params {
+            "1" => Ok(Mode::Lsb),
+            "2" => Ok(Mode::Usb),
+            "3" => Ok(Mode::Cw),
+            "4" => Ok(Mode::Fm),
+            "5" => Ok(Mode::Am),
+            "6" => Ok(Mode::Fsk),
+            "7" => Ok(Mode::CwReverse),
+            "9" => Ok(Mode::FskReverse),
+            _ => Err(RadioError::UnexpectedAnswer(params.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Mode::Lsb => "LSB",
+            Mode::Usb => "USB",
+            Mode::Cw => "CW",
+            Mode::Fm => "FM",
+            Mode::Am => "AM",
+            Mode::Fsk => "FSK",
+            Mode::CwReverse => "CW-R",
+            Mode::FskReverse => "FSK-R",
+        })
+    }
+}
+
+impl FromStr for Mode {
+    type Err = RadioError;
+
+    fn from_str(s: &str) -> RadioResult<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "LSB" => Ok(Mode::Lsb),
+            "USB" => Ok(Mode::Usb),
+            "CW" => Ok(Mode::Cw),
+            "FM" => Ok(Mode::Fm),
+            "AM" => Ok(Mode::Am),
+            "FSK" => Ok(Mode::Fsk),
+            "CW-R" | "CWR" => Ok(Mode::CwReverse),
+            "FSK-R" | "FSKR" => Ok(Mode::FskReverse),
+            _ => Err(RadioError::InvalidParameter(format!("invalid mode {:?}", s))),
+        }
+    }
+}
+
+/// VFO or memory channel selection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Vfo {
+    A,
+    B,
+    Memory,
+}
+
+impl Vfo {
+    /// Returns the CAT representation, e.g. `0` for VFO A
+    pub fn to_cat(&self) -> char {
+        match *self {
+            Vfo::A => '0',
+            Vfo::B => '1',
+            Vfo::Memory => '2',
+        }
+    }
+
+    /// Parses the CAT representation
+    pub fn from_cat(params: &str) -> RadioResult<Self> {
+        match params {
+            "0" => Ok(Vfo::A),
+            "1" => Ok(Vfo::B),
+            "2" => Ok(Vfo::Memory),
+            _ => Err(RadioError::UnexpectedAnswer(params.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Vfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Vfo::A => "VFO A",
+            Vfo::B => "VFO B",
+            Vfo::Memory => "Memory",
+        })
+    }
+}
+
+impl FromStr for Vfo {
+    type Err = RadioError;
+
+    fn from_str(s: &str) -> RadioResult<Self> {
+        match s.trim().to_ascii_uppercase().replace(' ', "").as_str() {
+            "A" | "VFOA" => Ok(Vfo::A),
+            "B" | "VFOB" => Ok(Vfo::B),
+            "M" | "MEM" | "MEMORY" => Ok(Vfo::Memory),
+            _ => Err(RadioError::InvalidParameter(format!("invalid VFO {:?}", s))),
+        }
+    }
+}
+
+/// Antenna connector
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Antenna {
+    Ant1,
+    Ant2,
+}
+
+impl Antenna {
+    /// Returns the CAT representation, e.g. `0` for ANT1
+    pub fn to_cat(&self) -> char {
+        match *self {
+            Antenna::Ant1 => '0',
+            Antenna::Ant2 => '1',
+        }
+    }
+
+    /// Parses the CAT representation
+    pub fn from_cat(params: &str) -> RadioResult<Self> {
+        match params {
+            "0" => Ok(Antenna::Ant1),
+            "1" => Ok(Antenna::Ant2),
+            _ => Err(RadioError::UnexpectedAnswer(params.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Antenna {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Antenna::Ant1 => "ANT1",
+            Antenna::Ant2 => "ANT2",
+        })
+    }
+}
+
+impl FromStr for Antenna {
+    type Err = RadioError;
+
+    fn from_str(s: &str) -> RadioResult<Self> {
+        match s.trim().to_ascii_uppercase().replace(' ', "").as_str() {
+            "1" | "ANT1" => Ok(Antenna::Ant1),
+            "2" | "ANT2" => Ok(Antenna::Ant2),
+            _ => Err(RadioError::InvalidParameter(format!("invalid antenna {:?}", s))),
+        }
+    }
+}
+
+/// Tone and CTCSS frequencies in tenths of Hz,
+/// indexed by frequency number as used by `TN`, `CN` and `MW`.
+pub const TONE_FREQUENCIES: [u16; 42] = [
+    670, 693, 719, 744, 770, 797, 825, 854, 885, 915,
+    948, 974, 1000, 1035, 1072, 1109, 1148, 1188, 1230, 1273,
+    1318, 1365, 1413, 1462, 1514, 1567, 1622, 1679, 1738, 1799,
+    1862, 1928, 2035, 2065, 2107, 2181, 2257, 2291, 2336, 2418,
+    2503, 2541,
+];
+
+/// Tone function used for FM repeater access and squelch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ToneMode {
+    Off,
+    Tone,
+    Ctcss,
+}
+
+impl ToneMode {
+    /// Returns the CAT representation, e.g. `1` for Tone
+    pub fn to_cat(&self) -> char {
+        match *self {
+            ToneMode::Off => '0',
+            ToneMode::Tone => '1',
+            ToneMode::Ctcss => '2',
+        }
+    }
+
+    /// Parses the CAT representation
+    pub fn from_cat(params: &str) -> RadioResult<Self> {
+        match params {
+            "0" => Ok(ToneMode::Off),
+            "1" => Ok(ToneMode::Tone),
+            "2" => Ok(ToneMode::Ctcss),
+            _ => Err(RadioError::UnexpectedAnswer(params.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for ToneMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ToneMode::Off => "Off",
+            ToneMode::Tone => "Tone",
+            ToneMode::Ctcss => "CTCSS",
+        })
+    }
+}
+
+impl FromStr for ToneMode {
+    type Err = RadioError;
+
+    fn from_str(s: &str) -> RadioResult<Self> {
+        match s.trim().to_ascii_uppercase().as_str() {
+            "" | "OFF" => Ok(ToneMode::Off),
+            "TONE" => Ok(ToneMode::Tone),
+            "CTCSS" | "TSQL" => Ok(ToneMode::Ctcss),
+            _ => Err(RadioError::InvalidParameter(format!("invalid tone mode {:?}", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> u64 {
+        s.parse::<Frequency>().unwrap_or_else(|e| panic!("{:?}: {}", s, e)).hz()
+    }
+
+    fn is_invalid(s: &str) -> bool {
+        match s.parse::<Frequency>() {
+            Err(RadioError::InvalidParameter(_)) => true,
+            result => panic!("expected InvalidParameter for {:?}, got {:?}", s, result),
+        }
+    }
+
+    #[test]
+    fn parses_units() {
+        assert_eq!(parse("14074000"), 14_074_000);
+        assert_eq!(parse("14074000 Hz"), 14_074_000);
+        assert_eq!(parse("3573kHz"), 3_573_000);
+        assert_eq!(parse("7.1 MHz"), 7_100_000);
+        assert_eq!(parse(" 14mhz "), 14_000_000);
+        assert_eq!(parse("50 KHZ"), 50_000);
+    }
+
+    #[test]
+    fn scales_decimal_places_to_unit() {
+        assert_eq!(parse("14.074MHz"), 14_074_000);
+        assert_eq!(parse("14.074123 MHz"), 14_074_123);
+        assert_eq!(parse("7.05MHz"), 7_050_000);
+        assert_eq!(parse(".5 MHz"), 500_000);
+        assert_eq!(parse("14. MHz"), 14_000_000);
+        assert_eq!(parse("3573.5 kHz"), 3_573_500);
+        assert_eq!(parse("3573.125kHz"), 3_573_125);
+
+        // Nothing finer than 1 Hz, rather than rounding
+        assert!(is_invalid("14.0741234 MHz"));
+        assert!(is_invalid("3573.1255 kHz"));
+        assert!(is_invalid("14074000.5"));
+        assert!(is_invalid("14074000.5 Hz"));
+    }
+
+    #[test]
+    fn checks_tuning_range() {
+        assert_eq!(parse("30 kHz"), Frequency::MIN_HZ);
+        assert_eq!(parse("60 MHz"), Frequency::MAX_HZ);
+        assert!(is_invalid("29999"));
+        assert!(is_invalid("29.999 kHz"));
+        assert!(is_invalid("60.000001 MHz"));
+        assert!(is_invalid("0"));
+        assert!(is_invalid("99999999999999999999 MHz"));
+        assert!(is_invalid("18446744073709551 MHz"));
+    }
+
+    #[test]
+    fn refuses_bad_input() {
+        for s in &["", " ", "MHz", ".", ". kHz", "-7 MHz", "+7 MHz", "7,1 MHz", "7.1.0 MHz", "7 GHz", "seven", "0x1000000", "1e7"] {
+            assert!(is_invalid(s));
+        }
+        let message = "7 m".parse::<Frequency>().unwrap_err().to_string();
+        assert!(message.contains("invalid frequency \"7 m\""), "{}", message);
+    }
+}
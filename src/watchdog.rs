@@ -0,0 +1,134 @@
+//! A thread that switches the radio back to receive when the
+//! maximum transmit time runs out, even if the program has
+//! stopped talking to the radio.
+
+use crate::ptt::LineUse;
+use crate::transport::Transport;
+
+use std::io::Write;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+/// Handle to a watchdog thread, which stops when it is dropped.
+pub(crate) struct Watchdog {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+struct State {
+    /// When to switch to receive; `None` while not transmitting
+    deadline: Option<Instant>,
+    rts: LineUse,
+    dtr: LineUse,
+    /// Set when the thread has switched the radio to receive
+    fired: bool,
+    stopped: bool,
+}
+
+/// Keeps the watchdog thread from writing to the radio.
+pub(crate) struct Hold<'a> {
+    state: MutexGuard<'a, State>,
+    changed: &'a Condvar,
+}
+
+impl Watchdog {
+    /// Starts a thread that unkeys the radio through `port`, a
+    /// second handle to the connection the driver uses.
+    pub(crate) fn spawn(port: Box<dyn Transport + Send>) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                deadline: None,
+                rts: LineUse::Unused,
+                dtr: LineUse::Unused,
+                fired: false,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let watchdog = shared.clone();
+        thread::spawn(move || run(port, &watchdog));
+
+        Watchdog { shared }
+    }
+
+    /// Holds the thread off until the returned guard is dropped, so
+    /// that its `RX;` cannot land in the middle of a command.
+    pub(crate) fn hold(&self) -> Hold<'_> {
+        Hold { state: self.shared.lock(), changed: &self.shared.changed }
+    }
+
+    /// Returns whether the thread has switched the radio to receive
+    /// without the driver having taken note of it yet.
+    pub(crate) fn fired(&self) -> bool {
+        self.shared.lock().fired
+    }
+
+    /// Returns whether the thread has switched the radio to
+    /// receive, and resets that.
+    pub(crate) fn take_fired(&self) -> bool {
+        ::std::mem::replace(&mut self.shared.lock().fired, false)
+    }
+}
+
+impl<'a> Hold<'a> {
+    /// Returns whether the thread has switched the radio to receive.
+    pub(crate) fn fired(&self) -> bool {
+        self.state.fired
+    }
+
+    /// Sets when to switch the radio to receive, releasing the
+    /// lines used for keying; `None` to not.
+    pub(crate) fn set_deadline(&mut self, deadline: Option<Instant>, rts: LineUse, dtr: LineUse) {
+        self.state.deadline = deadline;
+        self.state.rts = rts;
+        self.state.dtr = dtr;
+        self.changed.notify_one();
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.changed.notify_one();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn run(mut port: Box<dyn Transport + Send>, shared: &Shared) {
+    let mut state = shared.lock();
+
+    while !state.stopped {
+        state = match state.deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    // Nobody can be told of errors here, so the lines are
+                    // released whether or not `RX;` could be sent
+                    let _ = port.write_all(b"RX;");
+                    if let LineUse::Ptt | LineUse::CwKey = state.rts {
+                        let _ = port.set_rts(false);
+                    }
+                    if let LineUse::Ptt | LineUse::CwKey = state.dtr {
+                        let _ = port.set_dtr(false);
+                    }
+                    state.deadline = None;
+                    state.fired = true;
+                    continue;
+                }
+                shared.changed.wait_timeout(state, deadline - now).unwrap_or_else(|e| e.into_inner()).0
+            },
+            None => shared.changed.wait(state).unwrap_or_else(|e| e.into_inner()),
+        };
+    }
+}
@@ -0,0 +1,156 @@
+//! Runs `AsyncTS480` against `sim::Simulator` over
+//! `tokio::io::duplex`. Built with the `tokio` feature.
+
+#![cfg(feature = "tokio")]
+
+extern crate tokio;
+extern crate ts480;
+
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use ts480::asynchronous::AsyncTS480;
+use ts480::sim::Simulator;
+use ts480::{BandPrivileges, Frequency, Mode, PttSource, RadioError, RadioEvent, TS480, Vfo};
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Answers commands with the simulator, writing `noise`
+/// before each batch of answers.
+async fn serve(mut stream: DuplexStream, noise: &'static [u8]) {
+    let mut sim = Simulator::new();
+    let mut chunk = [0; 64];
+    loop {
+        let n = match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        sim.feed(&chunk[..n]);
+        let output = sim.take_output();
+        if !output.is_empty() {
+            let mut answer = noise.to_vec();
+            answer.extend_from_slice(&output);
+            if stream.write_all(&answer).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn connect(noise: &'static [u8]) -> AsyncTS480<DuplexStream> {
+    let (client, server) = duplex(1024);
+    tokio::spawn(serve(server, noise));
+    AsyncTS480::new(client)
+}
+
+fn mhz(mhz: f64) -> Frequency {
+    Frequency::from_hz((mhz * 1_000_000.0).round() as u64).unwrap()
+}
+
+#[tokio::test]
+async fn runs_commands() {
+    let mut radio = connect(b"");
+    assert_eq!(radio.command(TS480::read_frequency_a).await.unwrap(), mhz(14.074));
+
+    radio.command(|r| r.set_frequency_b(mhz(7.074))).await.unwrap();
+    radio.command(|r| r.set_mode(Mode::Cw)).await.unwrap();
+    assert_eq!(radio.command(TS480::read_frequency_b).await.unwrap(), mhz(7.074));
+
+    // Several answers for one command
+    let status = radio.command(TS480::status).await.unwrap();
+    assert_eq!(status.mode, Mode::Cw);
+    assert_eq!(status.vfo, Vfo::A);
+}
+
+#[tokio::test]
+async fn skips_line_noise() {
+    let mut radio = connect(b"\xff\x00;FA\x01\x02;\x7f  ");
+    assert_eq!(radio.command(TS480::read_frequency_a).await.unwrap(), mhz(14.074));
+    assert_eq!(radio.command(TS480::read_mode).await.unwrap(), Mode::Usb);
+    assert_eq!(radio.command(TS480::status).await.unwrap().frequency, mhz(14.074));
+}
+
+#[tokio::test]
+async fn returns_error_answers() {
+    let mut radio = connect(b"");
+    match radio.command(|r| r.query("ZZ;")).await {
+        Err(RadioError::SyntaxOrStatus) => {},
+        result => panic!("expected SyntaxOrStatus, got {:?}", result),
+    }
+    assert_eq!(radio.command(TS480::read_mode).await.unwrap(), Mode::Usb);
+}
+
+#[tokio::test]
+async fn reports_events() {
+    let (client, mut server) = duplex(1024);
+    let mut radio = AsyncTS480::new(client);
+    radio.command(|r| r.set_auto_information(2)).await.unwrap();
+
+    server.write_all(b"\x00MD3;FA00007074000;").await.unwrap();
+    assert_eq!(radio.next_event().await.unwrap(), RadioEvent::ModeChanged(Mode::Cw));
+    assert_eq!(radio.next_event().await.unwrap(), RadioEvent::FrequencyChanged {
+        vfo: Vfo::A,
+        frequency: mhz(7.074),
+    });
+}
+
+#[tokio::test]
+async fn times_out() {
+    let (client, _server) = duplex(1024);
+    let mut radio = AsyncTS480::new(client);
+    radio.set_timeout(Duration::from_millis(50));
+    match radio.command(TS480::read_mode).await {
+        Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut => {},
+        result => panic!("expected TimedOut, got {:?}", result),
+    }
+}
+
+#[tokio::test]
+async fn runs_each_command_once() {
+    let mut radio = connect(b"");
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = runs.clone();
+    let status = radio.command(move |r| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        r.status()
+    }).await.unwrap();
+    assert_eq!(status.frequency, mhz(14.074));
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn keeps_privileges_and_transmit_state() {
+    let mut radio = connect(b"");
+    radio.command(|r| {
+        r.set_band_privileges(Some(BandPrivileges::parse("allow 14.000MHz-14.150MHz CW\n")?));
+        Ok(())
+    }).await.unwrap();
+    match radio.command(|r| r.ptt_on(PttSource::Microphone)).await {
+        Err(RadioError::TransmitRefused(_)) => {},
+        result => panic!("expected TransmitRefused, got {:?}", result),
+    }
+
+    radio.command(|r| r.set_mode(Mode::Cw)).await.unwrap();
+    radio.command(|r| r.ptt_on(PttSource::Microphone)).await.unwrap();
+    assert!(radio.command(|r| Ok(r.is_transmitting())).await.unwrap());
+    radio.command(TS480::ptt_off).await.unwrap();
+    assert!(!radio.command(|r| Ok(r.is_transmitting())).await.unwrap());
+}
+
+#[tokio::test]
+async fn keeps_watchdog() {
+    let mut radio = connect(b"");
+    radio.command(|r| {
+        r.set_max_transmit_time(Some(Duration::from_millis(50)));
+        r.ptt_on(PttSource::Microphone)
+    }).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    match radio.command(|r| r.ptt_on(PttSource::Microphone)).await {
+        Err(RadioError::TransmitRefused(ref reason)) if reason.contains("maximum transmit time") => {},
+        result => panic!("expected TransmitRefused, got {:?}", result),
+    }
+    assert!(!radio.command(|r| Ok(r.is_transmitting())).await.unwrap());
+}
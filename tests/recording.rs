@@ -0,0 +1,117 @@
+//! Records sessions with the simulator and replays them.
+
+extern crate ts480;
+
+use ts480::recording::{Recorder, Replay, Session, Traffic};
+use ts480::sim::Simulator;
+use ts480::{LineUse, Mode, PttSource, RadioError, RadioResult, TS480Options, TS480, Transport};
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+use std::time::Duration;
+
+/// A radio that never answers, with reads timing out at once.
+struct Silent;
+
+impl Read for Silent {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::WouldBlock, "no answer"))
+    }
+}
+
+impl Write for Silent {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Silent {}
+
+fn options() -> TS480Options {
+    TS480Options::new().rts(LineUse::Ptt).timeout(Duration::from_millis(50))
+}
+
+/// Runs commands whose results are compared between the live
+/// session and its replay.
+fn exercise<T: Transport>(radio: &mut TS480<T>) -> Vec<String> {
+    let mut results = Vec::new();
+    results.push(format!("{:?}", radio.read_frequency_a()));
+    results.push(format!("{:?}", radio.set_mode(Mode::Cw)));
+    results.push(format!("{:?}", radio.status()));
+    results.push(format!("{:?}", radio.query("ZZ;")));
+    // Recorded with its non-printable byte escaped
+    results.push(format!("{:?}", radio.query("FA\u{1}\\;")));
+    results.push(format!("{:?}", radio.ptt_on(PttSource::Rts)));
+    results.push(format!("{:?}", radio.ptt_off()));
+    results
+}
+
+/// Records `run` to a temporary file named after `name`.
+fn record<T: Transport, F, R>(name: &str, transport: T, run: F) -> (R, Session)
+    where F: FnOnce(&mut TS480<Recorder<T>>) -> R
+{
+    let path = env::temp_dir().join(format!("ts480-{}-{}.log", process::id(), name));
+    let result = {
+        let recorder = Recorder::create(transport, &path).unwrap();
+        let mut radio = options().connect(recorder).unwrap();
+        run(&mut radio)
+    };
+    let session = Session::open(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    (result, session)
+}
+
+fn is_timeout<R>(result: &RadioResult<R>) -> bool {
+    matches!(result, Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::TimedOut)
+}
+
+#[test]
+fn replays_recorded_session() {
+    let (live, session) = record("session", Simulator::new(), exercise);
+    assert!(session.started.is_some());
+    assert!(session.entries.iter().any(|e| e.traffic == Traffic::Sent(b"FA\x01\\;".to_vec())));
+    assert!(session.entries.iter().any(|e| e.traffic == Traffic::Rts(true)));
+
+    let mut radio = options().connect(Replay::new(session)).unwrap();
+    assert_eq!(exercise(&mut radio), live);
+    // Only the lines released when the radio is dropped remain
+    assert_eq!(radio.transport().remaining().iter().collect::<Vec<_>>(), [&Traffic::Rts(false)]);
+}
+
+#[test]
+fn replay_refuses_other_commands() {
+    let (_, session) = record("other", Simulator::new(), |radio| radio.read_frequency_a().unwrap());
+
+    let mut radio = options().connect(Replay::new(session)).unwrap();
+    match radio.read_mode() {
+        Err(RadioError::Io(ref e)) if e.kind() == io::ErrorKind::InvalidData => {},
+        result => panic!("expected InvalidData, got {:?}", result),
+    }
+}
+
+#[test]
+fn replays_timeouts() {
+    let (live, session) = record("timeouts", Silent, |radio| {
+        let first = radio.read_mode();
+        let second = radio.read_frequency_a();
+        (first, second)
+    });
+    assert!(is_timeout(&live.0) && is_timeout(&live.1), "{:?}", live);
+    // One entry for each time the driver waited
+    let timeouts = session.entries.iter().filter(|e| e.traffic == Traffic::Timeout).count();
+    assert_eq!(timeouts, 2);
+
+    let mut radio = options().connect(Replay::new(session.clone())).unwrap();
+    assert!(is_timeout(&radio.read_mode()));
+    assert!(is_timeout(&radio.read_frequency_a()));
+    // Only the lines released when the radio is dropped remain
+    assert_eq!(radio.transport().remaining().iter().collect::<Vec<_>>(), [&Traffic::Rts(false)]);
+
+    assert!(session.transcript().contains("timed out"));
+}
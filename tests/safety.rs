@@ -0,0 +1,183 @@
+//! Checks the band privilege and watchdog interlocks against
+//! `sim::Simulator`.
+
+extern crate ts480;
+
+use ts480::sim::Simulator;
+use ts480::{BandPrivileges, Frequency, Mode, PttSource, RadioError, RadioResult, TS480Options, TS480, Vfo};
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const MAX_TRANSMIT_TIME: Duration = Duration::from_millis(100);
+
+fn mhz(mhz: f64) -> Frequency {
+    Frequency::from_hz((mhz * 1_000_000.0).round() as u64).unwrap()
+}
+
+/// 20 m with CW only at the bottom and a blocked frequency.
+fn radio() -> TS480<Simulator> {
+    let mut radio = TS480::with_transport(Simulator::new());
+    radio.set_band_privileges(Some(BandPrivileges::parse(
+        "allow 14.000MHz-14.150MHz CW\nallow 14.150MHz-14.350MHz\nblock 14.230MHz\n",
+    ).unwrap()));
+    radio
+}
+
+fn refusal<R: std::fmt::Debug>(result: RadioResult<R>) -> String {
+    match result {
+        Err(RadioError::TransmitRefused(reason)) => reason,
+        result => panic!("expected TransmitRefused, got {:?}", result),
+    }
+}
+
+/// Serves a simulator over a local TCP connection, as `ts480-sim`
+/// does, so that the driver can clone the connection.
+fn serve() -> (TcpStream, Arc<Mutex<Simulator>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let simulator = Arc::new(Mutex::new(Simulator::new()));
+
+    let radio = simulator.clone();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut chunk = [0; 64];
+        loop {
+            let n = match stream.read(&mut chunk) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            let output = {
+                let mut radio = radio.lock().unwrap();
+                radio.feed(&chunk[..n]);
+                radio.take_output()
+            };
+            if stream.write_all(&output).is_err() {
+                return;
+            }
+        }
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+    (stream, simulator)
+}
+
+#[test]
+fn refuses_tx_outside_segments() {
+    let mut radio = radio();
+    // 14.074 MHz is CW only
+    assert_eq!(refusal(radio.ptt_on(PttSource::Microphone)), "USB is not allowed on 14.074000 MHz");
+    assert!(!radio.transport().state().transmitting);
+    assert!(!radio.is_transmitting());
+
+    radio.set_frequency_a(mhz(7.074)).unwrap();
+    assert!(refusal(radio.transmit("TX1;")).contains("outside the allowed bands"));
+    radio.set_frequency_a(mhz(14.23)).unwrap();
+    assert!(refusal(radio.ptt_on(PttSource::Data)).contains("blocked"));
+    assert!(!radio.transport().state().transmitting);
+
+    radio.set_frequency_a(mhz(14.2)).unwrap();
+    radio.ptt_on(PttSource::Data).unwrap();
+    assert!(radio.transport().state().transmitting);
+}
+
+#[test]
+fn refuses_retune_while_transmitting() {
+    let mut radio = radio();
+    radio.set_frequency_a(mhz(14.2)).unwrap();
+    radio.ptt_on(PttSource::Microphone).unwrap();
+
+    assert!(refusal(radio.set_frequency_a(mhz(14.23))).contains("blocked"));
+    assert!(refusal(radio.set_frequency_a(mhz(14.1))).contains("USB is not allowed"));
+    assert!(refusal(radio.set_frequency_b(mhz(7.1))).contains("outside"));
+    assert!(refusal(radio.transmit("BU;")).contains("BU is not allowed while transmitting"));
+    assert_eq!(radio.transport().state().frequency_a, mhz(14.2));
+
+    radio.set_frequency_a(mhz(14.25)).unwrap();
+    radio.set_mode(Mode::Cw).unwrap();
+    assert_eq!(radio.transport().state().frequency_a, mhz(14.25));
+    assert!(radio.transport().state().transmitting);
+
+    // Once back on receive, anything goes
+    radio.ptt_off().unwrap();
+    radio.set_frequency_a(mhz(7.1)).unwrap();
+}
+
+#[test]
+fn refuses_retune_sent_with_tx() {
+    let mut radio = radio();
+    assert_eq!(refusal(radio.transmit("FA00014200000;TX0;")), "FA must be sent before TX, not with it");
+    assert!(!radio.transport().state().transmitting);
+    assert_eq!(radio.transport().state().frequency_a, mhz(14.074));
+}
+
+#[test]
+fn reads_tx_frequency_with_split_and_xit() {
+    let mut radio = radio();
+    radio.set_frequency_b(mhz(14.2)).unwrap();
+    assert_eq!(radio.read_tx_frequency().unwrap(), (mhz(14.074), Mode::Usb));
+
+    radio.transmit("FT1;").unwrap();
+    assert_eq!(radio.read_tx_vfo().unwrap(), Vfo::B);
+    assert_eq!(radio.read_tx_frequency().unwrap(), (mhz(14.2), Mode::Usb));
+    // Receiving on a CW-only frequency, transmitting on B
+    radio.ptt_on(PttSource::Microphone).unwrap();
+    radio.ptt_off().unwrap();
+
+    radio.transport_mut().state_mut().rit_offset = -500;
+    radio.transmit("XT1;").unwrap();
+    assert_eq!(radio.read_tx_frequency().unwrap(), (mhz(14.1995), Mode::Usb));
+
+    radio.transmit("FT0;").unwrap();
+    assert_eq!(radio.read_tx_frequency().unwrap(), (mhz(14.0735), Mode::Usb));
+}
+
+#[test]
+fn watchdog_thread_unkeys_radio() {
+    let (stream, simulator) = serve();
+    let mut radio = TS480Options::new().max_transmit_time(MAX_TRANSMIT_TIME).connect(stream).unwrap();
+
+    radio.ptt_on(PttSource::Microphone).unwrap();
+    // Answered once the simulator has acted on the commands before it
+    assert_eq!(radio.read_mode().unwrap(), Mode::Usb);
+    assert!(simulator.lock().unwrap().state().transmitting);
+
+    // Nothing is sent to the radio meanwhile
+    thread::sleep(MAX_TRANSMIT_TIME * 4);
+    assert!(!simulator.lock().unwrap().state().transmitting);
+    assert!(!radio.is_transmitting());
+
+    // Keying again is refused rather than unkeyed after the fact
+    assert!(refusal(radio.transmit("TX0;")).contains("maximum transmit time"));
+    assert_eq!(radio.read_mode().unwrap(), Mode::Usb);
+    assert!(!simulator.lock().unwrap().state().transmitting);
+
+    radio.ptt_off().unwrap();
+    radio.ptt_on(PttSource::Data).unwrap();
+    assert_eq!(radio.read_mode().unwrap(), Mode::Usb);
+    assert!(simulator.lock().unwrap().state().transmitting);
+    radio.ptt_off().unwrap();
+    assert_eq!(radio.read_mode().unwrap(), Mode::Usb);
+    assert!(!simulator.lock().unwrap().state().transmitting);
+}
+
+#[test]
+fn watchdog_is_checked_without_thread() {
+    // The simulator cannot be cloned, so the driver checks the
+    // maximum transmit time whenever it is used
+    let mut radio = TS480Options::new().max_transmit_time(MAX_TRANSMIT_TIME).connect(Simulator::new()).unwrap();
+    radio.ptt_on(PttSource::Microphone).unwrap();
+    thread::sleep(MAX_TRANSMIT_TIME * 2);
+    assert!(radio.transport().state().transmitting);
+
+    assert!(refusal(radio.ptt_on(PttSource::Microphone)).contains("maximum transmit time"));
+    assert!(!radio.transport().state().transmitting);
+    assert!(!radio.is_transmitting());
+
+    radio.transmit("RX;TX0;").unwrap();
+    assert!(radio.transport().state().transmitting);
+}
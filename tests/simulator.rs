@@ -0,0 +1,190 @@
+//! Runs the driver against `sim::Simulator`.
+
+extern crate ts480;
+
+use ts480::sim::Simulator;
+use ts480::{Frequency, MemoryChannel, MeterKind, Mode, PttSource, RadioError, RadioEvent, RigSnapshot, TS480, Vfo};
+
+fn radio() -> TS480<Simulator> {
+    TS480::with_transport(Simulator::new())
+}
+
+fn mhz(mhz: f64) -> Frequency {
+    Frequency::from_hz((mhz * 1_000_000.0).round() as u64).unwrap()
+}
+
+#[test]
+fn sets_and_reads_frequency_and_mode() {
+    let mut radio = radio();
+    radio.set_frequency_a(mhz(7.074)).unwrap();
+    radio.set_mode(Mode::Cw).unwrap();
+
+    assert_eq!(radio.read_frequency_a().unwrap(), mhz(7.074));
+    assert_eq!(radio.read_mode().unwrap(), Mode::Cw);
+    assert_eq!(radio.transport().state().frequency_a, mhz(7.074));
+
+    let status = radio.status().unwrap();
+    assert_eq!(status.frequency, mhz(7.074));
+    assert_eq!(status.mode, Mode::Cw);
+    assert_eq!(status.vfo, Vfo::A);
+    assert!(!status.transmitting);
+}
+
+#[test]
+fn keys_and_unkeys() {
+    let mut radio = radio();
+    radio.ptt_on(PttSource::Microphone).unwrap();
+    assert!(radio.is_transmitting());
+    assert!(radio.transport().state().transmitting);
+    assert!(radio.status().unwrap().transmitting);
+
+    radio.ptt_off().unwrap();
+    assert!(!radio.is_transmitting());
+    assert!(!radio.transport().state().transmitting);
+}
+
+#[test]
+fn writes_and_reads_memory() {
+    let mut radio = radio();
+    let mut memory = MemoryChannel::new(mhz(29.62), Mode::Fm);
+    memory.tx_frequency = Some(mhz(29.52));
+    memory.name = "REPEATER".to_owned();
+    radio.write_memory(12, &memory).unwrap();
+
+    assert_eq!(radio.read_memory(12).unwrap(), Some(memory));
+    assert_eq!(radio.read_memory(13).unwrap(), None);
+}
+
+#[test]
+fn unknown_command_is_a_syntax_error() {
+    let mut radio = radio();
+    match radio.query("ZZ;") {
+        Err(RadioError::SyntaxOrStatus) => {},
+        result => panic!("expected SyntaxOrStatus, got {:?}", result),
+    }
+    // The driver keeps working afterwards
+    assert_eq!(radio.read_mode().unwrap(), Mode::Usb);
+}
+
+#[test]
+fn garbled_command_is_a_communication_error() {
+    let mut radio = radio();
+    match radio.query("FA\u{1};") {
+        Err(RadioError::CommError) => {},
+        result => panic!("expected CommError, got {:?}", result),
+    }
+}
+
+#[test]
+fn retries_after_communication_error() {
+    let mut radio = radio();
+    radio.transport_mut().state_mut().garbled = 2;
+    assert_eq!(radio.read_frequency_a().unwrap(), mhz(14.074));
+
+    radio.set_retries(0);
+    radio.transport_mut().state_mut().garbled = 1;
+    match radio.read_frequency_a() {
+        Err(RadioError::CommError) => {},
+        result => panic!("expected CommError, got {:?}", result),
+    }
+}
+
+#[test]
+fn retries_while_busy() {
+    let mut radio = radio();
+    radio.transport_mut().state_mut().busy = 1;
+    assert_eq!(radio.read_mode().unwrap(), Mode::Usb);
+
+    radio.set_retries(0);
+    radio.transport_mut().state_mut().busy = 1;
+    match radio.read_mode() {
+        Err(RadioError::ProcIncomplete) => {},
+        result => panic!("expected ProcIncomplete, got {:?}", result),
+    }
+}
+
+#[test]
+fn reports_front_panel_changes() {
+    let mut radio = radio();
+    radio.set_auto_information(2).unwrap();
+    assert!(radio.transport_mut().front_panel("FA00007074000"));
+
+    match radio.next_event().unwrap() {
+        RadioEvent::FrequencyChanged { vfo, frequency } => {
+            assert_eq!(vfo, Vfo::A);
+            assert_eq!(frequency, mhz(7.074));
+        },
+        event => panic!("expected FrequencyChanged, got {:?}", event),
+    }
+}
+
+#[test]
+fn keeps_reports_received_while_reading() {
+    let mut radio = radio();
+    radio.set_auto_information(2).unwrap();
+    assert!(radio.transport_mut().front_panel("MD3"));
+
+    // The report arrives before the answer and is kept as an event
+    assert_eq!(radio.read_frequency_a().unwrap(), mhz(14.074));
+    let mut events = Vec::new();
+    while let Ok(event) = radio.next_event() {
+        events.push(event);
+    }
+    assert!(events.contains(&RadioEvent::ModeChanged(Mode::Cw)), "{:?}", events);
+}
+
+#[test]
+fn no_reports_without_auto_information() {
+    let mut radio = radio();
+    assert!(radio.transport_mut().front_panel("FA00007074000"));
+    assert!(radio.next_event().is_err());
+}
+
+#[test]
+fn restores_snapshot() {
+    let mut radio = radio();
+    let mut memory = MemoryChannel::new(mhz(29.62), Mode::Fm);
+    memory.name = "RPT \"1\"".to_owned();
+    radio.write_memory(3, &memory).unwrap();
+    let snapshot = radio.snapshot().unwrap();
+    let snapshot = RigSnapshot::from_toml(&snapshot.to_toml()).unwrap();
+
+    radio.set_frequency_a(mhz(7.074)).unwrap();
+    radio.set_mode(Mode::Cw).unwrap();
+    radio.set_extended_menu(5, "0").unwrap();
+    radio.write_memory(3, &MemoryChannel::new(mhz(28.5), Mode::Usb)).unwrap();
+    assert!(!radio.snapshot().unwrap().diff(&snapshot).is_empty());
+
+    assert_eq!(radio.restore(&snapshot).unwrap(), Vec::new());
+    assert_eq!(radio.read_frequency_a().unwrap(), mhz(14.074));
+    assert_eq!(radio.read_memory(3).unwrap(), Some(memory));
+    assert!(radio.snapshot().unwrap().diff(&snapshot).is_empty());
+}
+
+#[test]
+fn reads_meters() {
+    let mut radio = radio();
+    radio.transport_mut().state_mut().smeter = 20;
+    radio.transport_mut().state_mut().meters = [3, 10, 17];
+
+    let reading = radio.read_smeter().unwrap();
+    assert_eq!(reading.raw(), 20);
+    assert_eq!(reading.to_string(), "S9+20dB");
+
+    assert_eq!(radio.read_meter(MeterKind::Swr).unwrap(), 3);
+    assert_eq!(radio.read_meter(MeterKind::Alc).unwrap(), 17);
+    assert_eq!(radio.transport().state().selected_meter, 3);
+    radio.set_meter(MeterKind::Comp).unwrap();
+    assert_eq!(radio.transport().state().selected_meter, 2);
+
+    // A meter number the radio does not have is an error, not a panic
+    for &meter in &[0, 4] {
+        radio.transport_mut().state_mut().selected_meter = meter;
+        match radio.query("RM;") {
+            Err(RadioError::SyntaxOrStatus) => {},
+            result => panic!("expected SyntaxOrStatus, got {:?}", result),
+        }
+    }
+    assert!(radio.query("RM4;").is_err());
+    assert_eq!(radio.transport().state().selected_meter, 4);
+}